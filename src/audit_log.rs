@@ -0,0 +1,113 @@
+use crate::block::BlockHeader;
+use crate::blockchain::Blockchain;
+use crate::merkle_tree::{MerkleTree, MerkleProof};
+use crate::ShaHash;
+
+/// Consistency proofs of an AuditLog are checked just like those of any other Blockchain.
+pub use crate::blockchain::verify_consistency;
+
+/// A tamper-evident log built on top of a Blockchain, similar to Certificate Transparency logs.
+///
+/// Every entry appended to the log is confirmed with a Receipt that can later be used to prove
+/// that the entry is part of the log. Consistency proofs show that a bigger version of the log
+/// is an append-only extension of a smaller one, i.e. that no history was rewritten.
+///
+/// The size of the log is measured in Blocks, one Block being appended per append() or
+/// append_all() call.
+#[derive(Debug)]
+pub struct AuditLog<T : AsRef<[u8]> + Clone> {
+    /// The Blockchain storing all the entries of this log.
+    chain: Blockchain<T>
+}
+
+/// The confirmation that an entry was appended to an AuditLog.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Receipt {
+    /// The height of the Block the entry was stored in.
+    pub height: usize,
+    /// The position of the entry within its Block.
+    pub leaf_index: usize,
+    /// The proof that the entry is part of the Merkle Tree of its Block.
+    pub proof: MerkleProof
+}
+
+impl<T : AsRef<[u8]> + Clone> AuditLog<T> {
+
+    /// Creates a new, empty `AuditLog`.
+    pub fn new() -> AuditLog<T> {
+        AuditLog {
+            chain: Blockchain::new()
+        }
+    }
+
+    /// Returns the Blockchain storing all the entries of this log.
+    pub fn get_chain(&self) -> &Blockchain<T> {
+        &self.chain
+    }
+
+    /// Returns the size of this log, i.e. the number of Blocks in it.
+    pub fn size(&self) -> usize {
+        self.chain.length()
+    }
+
+    /// Appends a single entry to this log (in a Block of its own) and returns its Receipt.
+    pub fn append(&mut self, entry : T) -> Receipt {
+        self.append_all(&[entry]).remove(0)
+    }
+
+    /// Appends all of the given entries to this log in a single Block and returns their
+    /// Receipts, in the same order as the entries. An empty slice appends an empty Block.
+    pub fn append_all(&mut self, entries : &[T]) -> Vec<Receipt> {
+        let height = self.chain.length();
+        let block = self.chain.try_append_data(MerkleTree::new(entries))
+            .expect("the Blockchain of an AuditLog has neither limits nor another TreeVersion");
+        (0..entries.len())
+            .map(|leaf_index| Receipt {
+                height,
+                leaf_index,
+                proof: block.get_merkle_tree().generate_proof_for_index(leaf_index)
+                    .expect("the entry was just added to the Merkle Tree")
+            })
+            .collect()
+    }
+
+    /// Checks whether the given Receipt proves that the given entry is part of this log, at the
+    /// position within its Block that the Receipt claims.
+    pub fn verify_receipt(&self, receipt : &Receipt, entry : &T) -> bool {
+        let block = match self.chain.get_block(receipt.height) {
+            Some(block) => block,
+            None => return false
+        };
+        let expected_path = block.get_merkle_tree().generate_proof_for_index(receipt.leaf_index)
+            .map(|proof| proof.sides_from_root());
+        expected_path == Some(receipt.proof.sides_from_root())
+            && receipt.proof.verify(block.header().merkle_root(), entry)
+    }
+
+    /// Returns the hash of the last Block of this log at the time it had the given size
+    /// (the initial hash for size 0), or None when this log is not that big (yet).
+    ///
+    /// Publishing this "head" is enough for others to later check that the log was only
+    /// appended to, see verify_consistency().
+    pub fn head(&self, size : usize) -> Option<ShaHash> {
+        match size {
+            0 => Some(self.chain.get_params().initial_hash),
+            _ => self.chain.get_block(size - 1).map(|block| block.calculate_hash())
+        }
+    }
+
+    /// Generates a proof that this log at `new_size` is an append-only extension of this log
+    /// at `old_size`. The proof consists of the headers of all the Blocks in between.
+    ///
+    /// Returns None when `old_size` is bigger than `new_size` or when this log is not
+    /// `new_size` big (yet).
+    pub fn consistency_proof(&self, old_size : usize, new_size : usize) -> Option<Vec<BlockHeader>> {
+        self.chain.consistency_proof(self.head(old_size)?, self.head(new_size)?)
+    }
+}
+
+impl<T : AsRef<[u8]> + Clone> Default for AuditLog<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,55 @@
+use sha2::Sha256;
+use sha2::Digest;
+use crate::ShaHash;
+
+// Randomness derived from a Blockchain (see Blockchain::randomness_at()) and a commit-reveal
+// scheme for combining it with secrets of the participants.
+//
+// Bias: whoever "mines" the Blocks of the window can try out nonces and Merkle Trees and throw
+// away Blocks whose randomness they don't like - at the cost of mining another Block, which is
+// cheap with the low difficulty of this crate (see ZEROS). Block hashes alone are therefore only
+// good for low-stakes decisions. Combining them with secrets that were committed to before the
+// Blocks were mined (see commit() and combine()) removes the miners' influence, but now the last
+// participant to reveal their secret can decide to not reveal it after seeing all the others -
+// so not revealing has to be punished by the application.
+
+/// The number of Blocks whose hashes make up the randomness at a height, see
+/// Blockchain::randomness_at().
+pub const RANDOMNESS_WINDOW : usize = 16;
+
+/// Prefixed to everything hashed by this module, so that its hashes can't be confused with
+/// hashes of Blocks or Merkle Trees.
+const DOMAIN : &[u8] = b"rust-blockchain beacon";
+
+/// Derives randomness from the given Block hashes (oldest first) and the given salt.
+pub(crate) fn randomness_from<'a, I : IntoIterator<Item = &'a ShaHash>>(block_hashes: I, salt: &[u8]) -> ShaHash {
+    let mut hasher = Sha256::new().chain(DOMAIN).chain(b"randomness");
+    for hash in block_hashes {
+        hasher.update(hash);
+    }
+    hasher.chain(salt).finalize().into()
+}
+
+/// Returns the commitment to the given secret, to be published before the randomness it will be
+/// combined with is known. The secret should contain at least 32 random bytes, so it can't be
+/// guessed from its commitment.
+pub fn commit(secret: &[u8]) -> ShaHash {
+    Sha256::new().chain(DOMAIN).chain(b"commitment").chain(secret).finalize().into()
+}
+
+/// Checks whether the given (revealed) secret matches the given commitment.
+pub fn verify_reveal(commitment: &ShaHash, secret: &[u8]) -> bool {
+    commit(secret) == *commitment
+}
+
+/// Combines the given randomness (e.g. from Blockchain::randomness_at()) with the given revealed
+/// secrets. The order of the secrets matters, so all participants have to agree on it (e.g. the
+/// order of their commitments).
+pub fn combine(randomness: &ShaHash, secrets: &[&[u8]]) -> ShaHash {
+    let mut hasher = Sha256::new().chain(DOMAIN).chain(b"combined").chain(randomness);
+    for secret in secrets {
+        // Hashing each secret first keeps the boundaries between them unambiguous
+        hasher.update(Sha256::digest(secret));
+    }
+    hasher.finalize().into()
+}
@@ -1,6 +1,9 @@
 use sha2::Sha256;
 use sha2::Digest;
-use crate::merkle_tree::MerkleTree;
+use crate::merkle_tree::{MerkleTree, MerkleProof};
+use crate::pow::{PowFunction, Sha256Pow};
+use crate::chain_params::{ChainParams, TreeVersion};
+use crate::{ShaHash, Nonce};
 
 /// The number of zeros the hash of a Block has to start with in order for it to be accepted.
 ///
@@ -9,12 +12,13 @@ use crate::merkle_tree::MerkleTree;
 ///
 /// As we use SHA-256 as the hashing algorithm, this number can be anywhere between
 /// 0 (no effort at all) and 255 (essentially impossible) -> therefore stored as a u8
-const ZEROS : u8 = 5;
+pub(crate) const ZEROS : u8 = 5;
 
 
 /// This is the hash that's stored as the 'previous hash' (prev_hash) for
-/// the very first / initial Block of a Blockchain
-static INITIAL_HASH : SHAHash = [0u8; 32];
+/// the very first / initial Block of a Blockchain with the default ChainParams
+/// (see ChainParams::initial_hash).
+pub static INITIAL_HASH : ShaHash = ShaHash::ZERO;
 
 /// A single Block in the Blockchain.
 /// May store all the data of this Block or just parts of it, but at least the root hash of the
@@ -22,12 +26,25 @@ static INITIAL_HASH : SHAHash = [0u8; 32];
 #[derive(Clone, Debug)]
 pub struct Block<T : AsRef<[u8]> + Clone> {
     /// The hash of the Block that came before this Block.
-    pub(crate) prev_hash: SHAHash,
+    pub(crate) prev_hash: ShaHash,
     /// Random data such that the overall hash of this Block starts with ZEROS 0's.
-    nonce: Nonce,
+    pub(crate) nonce: Nonce,
     // The actual data of a Block (or just parts of it, but the root hash at minimum)
     // is stored in a Merkle Tree.
-    merkle_tree: MerkleTree<T>
+    pub(crate) merkle_tree: MerkleTree<T>
+}
+
+/// The size of a BlockHeader in bytes: the previous hash, the nonce and the Merkle root hash.
+pub const HEADER_SIZE : usize = 32 + std::mem::size_of::<Nonce>() + 32;
+
+/// The header of a Block, i.e. everything that goes into the hash of a Block but none of its
+/// data: the previous hash, the nonce and the root hash of the Merkle Tree.
+///
+/// The header is stored in its serialized form so that it can itself be used as the data of a
+/// Merkle Tree, e.g. for relaying the headers of another Blockchain (see the bridge module).
+#[derive(Clone, Debug, PartialEq)]
+pub struct BlockHeader {
+    bytes: [u8; HEADER_SIZE]
 }
 
 impl<T : AsRef<[u8]> + Clone> Block<T> {
@@ -38,7 +55,7 @@ impl<T : AsRef<[u8]> + Clone> Block<T> {
     ///
     /// The Nonce of the new Block has yet to be calculated by calling calculate_nonce()
     /// afterwards ("mining") !!!
-    pub fn new(previous_hash : SHAHash, data : MerkleTree<T>) -> Block<T> {
+    pub fn new(previous_hash : ShaHash, data : MerkleTree<T>) -> Block<T> {
         Block {
             prev_hash : previous_hash,
             nonce : 0, // has yet to be calculated!
@@ -51,68 +68,120 @@ impl<T : AsRef<[u8]> + Clone> Block<T> {
     /// The higher the ZEROS constant, the more difficult/time-intensive this operation becomes.
     ///
     /// This is essentially the "mining" process.
+    ///
+    /// The search for the nonce is deterministic: it always starts at the Block's current nonce
+    /// (0 for a new Block), so mining the same data after the same Block always results in the
+    /// very same Block, on every run and on every platform.
     pub fn calculate_nonce(&mut self) -> Nonce {
+        self.calculate_nonce_with(&Sha256Pow)
+    }
+
+    /// Same as calculate_nonce(), but for a Blockchain using the given proof of work function
+    /// (see ChainParams).
+    pub fn calculate_nonce_with(&mut self, pow : &dyn PowFunction) -> Nonce {
         // Increment this Block's nonce until it's correct, i.e. this Block's hash starts with ZERO 0's:
-        while !self.verify_nonce() {
-            self.nonce += 1;
+        while !self.verify_nonce_with(pow) {
+            self.nonce = self.nonce.wrapping_add(1);
         }
         self.nonce
     }
 
+    /// Same as calculate_nonce(), but starts the search at a nonce derived from the given seed
+    /// and this Block's prev_hash instead of 0.
+    ///
+    /// Mining with the same seed is just as reproducible as calculate_nonce(), but different
+    /// seeds lead to different Blocks, e.g. for simulating several independent miners in tests.
+    pub fn calculate_nonce_seeded(&mut self, seed : u64) -> Nonce {
+        self.seed_nonce(seed);
+        self.calculate_nonce()
+    }
+
+    /// Sets the nonce of this Block to the start of the search of calculate_nonce_seeded().
+    pub(crate) fn seed_nonce(&mut self, seed : u64) {
+        let start : ShaHash = Sha256::new()
+            .chain(seed.to_be_bytes())
+            .chain(self.prev_hash)
+            .finalize()
+            .into();
+        let mut start_nonce = [0u8; std::mem::size_of::<Nonce>()];
+        start_nonce.copy_from_slice(&start.as_bytes()[..std::mem::size_of::<Nonce>()]);
+        self.nonce = Nonce::from_be_bytes(start_nonce);
+    }
+
+    /// Returns the Merkle Tree storing the data of this Block (or just parts of it).
+    pub fn get_merkle_tree(&self) -> &MerkleTree<T> {
+        &self.merkle_tree
+    }
+
+    /// Generates a proof that the given element is part of this Block, see
+    /// MerkleTree::generate_proof(). Only works for Blocks of a Blockchain using TreeVersion::V1,
+    /// see generate_proof_with().
+    pub fn generate_proof(&self, element : &T) -> Option<MerkleProof> {
+        self.generate_proof_with(element, TreeVersion::V1)
+    }
+
+    /// Same as generate_proof(), but for a Block of a Blockchain using the given TreeVersion (see
+    /// ChainParams). A light client holding nothing but the header of this Block can check the
+    /// proof against BlockHeader::merkle_root() using TreeVersion::verify_proof() with the same
+    /// version ("Simplified Payment Verification").
+    pub fn generate_proof_with(&self, element : &T, version : TreeVersion) -> Option<MerkleProof> {
+        version.generate_proof(&self.merkle_tree, element)
+    }
+
+    /// Returns the header of this Block.
+    pub fn header(&self) -> BlockHeader {
+        BlockHeader::new(self.prev_hash, self.nonce, self.merkle_tree.get_root_hash())
+    }
+
     /// Returns the hash of this Block.
     /// When calculate_nonce() has been called on this Block beforehand,
     /// the hash will start with ZEROS 0's.
-    pub fn calculate_hash(&self) -> SHAHash {
+    pub fn calculate_hash(&self) -> ShaHash {
         Sha256::new()
             .chain(self.prev_hash)
             .chain(self.nonce.to_be_bytes())
             .chain(self.merkle_tree.get_root_hash()) // Very important to just use the root hash!
             .finalize()
+            .into()
     }
 
     /// Checks whether the nonce of this Block was chosen correctly, i.e. whether the hash of
     /// this block starts with ZEROS 0's.
     pub fn verify_nonce(&self) -> bool {
-        let hash = self.calculate_hash();
-
-        // Expected
-        const NO_OF_NULL_BYTES : usize = (ZEROS / 8) as usize;
-        const NO_OF_0_BITS: usize = (ZEROS % 8) as usize;
-        assert!(NO_OF_0_BITS <= 8);
-
-        // Check if there are NO_OF_NULL_BYTES bytes with the value 0.
-        if hash.iter().take(NO_OF_NULL_BYTES).any(|&byte| byte != 0) {
-            return false;
-        }
-
-        if NO_OF_0_BITS == 0 {
-            // Only whole bytes should be 0, but no single bits in the last mixed byte.
-            return true;
-        }
+        self.verify_nonce_with(&Sha256Pow)
+    }
 
-        // Go through the mixed byte after the zero bytes and check that the first NO_OF_0_BITS are zero
-        let last_mixed_byte = hash[NO_OF_NULL_BYTES];
-        let mut pattern = 0b1000_0000u8;
-        for _ in 0..NO_OF_0_BITS {
-            if last_mixed_byte & pattern != 0 {
-                // Found a non-zero bit
-                return false;
-            }
-            pattern >>= 1;
-        }
-        true
+    /// Same as verify_nonce(), but for a Blockchain using the given proof of work function
+    /// (see ChainParams), i.e. checks whether the proof of work hash of this Block starts with
+    /// ZEROS 0's.
+    pub fn verify_nonce_with(&self, pow : &dyn PowFunction) -> bool {
+        self.header().verify_nonce_with(pow)
     }
 
-    /// Checks whether the Merkle Tree of this Block is valid.
+    /// Checks whether the Merkle Tree of this Block is valid, for a Blockchain using
+    /// TreeVersion::V1 (see verify_merkle_tree_with()).
     pub fn verify_merkle_tree(&self) -> bool {
-        self.merkle_tree.verify()
+        self.verify_merkle_tree_with(TreeVersion::V1)
+    }
+
+    /// Same as verify_merkle_tree(), but for a Blockchain using the given TreeVersion (see
+    /// ChainParams).
+    pub fn verify_merkle_tree_with(&self, version : TreeVersion) -> bool {
+        version.verify(&self.merkle_tree)
     }
 
     /// Checks whether this Block is valid (as seen on its own not in its context as part of
-    /// a Blockchain).
+    /// a Blockchain) with the default ChainParams.
     /// Combined check of both verify_nonce() and verify_merkle_tree().
     pub fn verify(&self) -> bool {
-        self.verify_nonce() && self.verify_merkle_tree()
+        self.verify_with(&ChainParams::default())
+    }
+
+    /// Same as verify(), but for a Blockchain with the given ChainParams, i.e. using its proof of
+    /// work function and TreeVersion (the BlockLimits depend on the Blockchain and aren't
+    /// checked).
+    pub fn verify_with(&self, params : &ChainParams) -> bool {
+        self.verify_nonce_with(params.pow) && self.verify_merkle_tree_with(params.tree_version)
     }
 
     /// Removes the storage of all the data in this Block to clean up space/memory.
@@ -126,12 +195,107 @@ impl<T : AsRef<[u8]> + Clone> Block<T> {
     /// Returns true if the data was restored successfully, i.e. the MerkleTree was correct and
     /// its root hash was equal to the root hash stored in this Block's Header.
     /// Returns false if no data was restored, i.e. the MerkleTree given was somehow invalid.
+    ///
+    /// Only works for Blocks of a Blockchain using TreeVersion::V1, see restore_merkle_tree_with().
     pub fn restore_merkle_tree(&mut self, mtree : MerkleTree<T>) -> bool {
-        if mtree.verify() && mtree.get_root_hash() == self.merkle_tree.get_root_hash() {
+        self.restore_merkle_tree_with(mtree, TreeVersion::V1)
+    }
+
+    /// Same as restore_merkle_tree(), but for a Block of a Blockchain using the given
+    /// TreeVersion (see ChainParams).
+    pub fn restore_merkle_tree_with(&mut self, mtree : MerkleTree<T>, version : TreeVersion) -> bool {
+        if version.verify_against(&mtree, &self.merkle_tree.get_root_hash()) {
             self.merkle_tree = mtree;
             true
         } else {
             false
         }
     }
-}
\ No newline at end of file
+}
+
+impl BlockHeader {
+
+    /// Creates a new BlockHeader from the parts that make up the hash of a Block.
+    pub fn new(prev_hash : ShaHash, nonce : Nonce, merkle_root : ShaHash) -> BlockHeader {
+        let mut bytes = [0u8; HEADER_SIZE];
+        bytes[..32].copy_from_slice(prev_hash.as_bytes());
+        bytes[32..HEADER_SIZE - 32].copy_from_slice(&nonce.to_be_bytes());
+        bytes[HEADER_SIZE - 32..].copy_from_slice(merkle_root.as_bytes());
+        BlockHeader { bytes }
+    }
+
+    /// Returns the hash of the Block that came before the Block of this header.
+    pub fn prev_hash(&self) -> ShaHash {
+        let mut hash = [0u8; 32];
+        hash.copy_from_slice(&self.bytes[..32]);
+        hash.into()
+    }
+
+    /// Returns the nonce of the Block of this header.
+    pub fn nonce(&self) -> Nonce {
+        let mut nonce = [0u8; std::mem::size_of::<Nonce>()];
+        nonce.copy_from_slice(&self.bytes[32..HEADER_SIZE - 32]);
+        Nonce::from_be_bytes(nonce)
+    }
+
+    /// Returns the root hash of the Merkle Tree of the Block of this header.
+    pub fn merkle_root(&self) -> ShaHash {
+        let mut hash = [0u8; 32];
+        hash.copy_from_slice(&self.bytes[HEADER_SIZE - 32..]);
+        hash.into()
+    }
+
+    /// Returns the hash of the Block of this header.
+    /// This is always equal to calculate_hash() of the Block itself.
+    pub fn calculate_hash(&self) -> ShaHash {
+        Sha256::digest(&self.bytes).into()
+    }
+
+    /// Checks whether the nonce of the Block of this header was chosen correctly,
+    /// see Block::verify_nonce().
+    pub fn verify_nonce(&self) -> bool {
+        self.verify_nonce_with(&Sha256Pow)
+    }
+
+    /// Same as verify_nonce(), but for a Blockchain using the given proof of work function
+    /// (see Block::verify_nonce_with()).
+    pub fn verify_nonce_with(&self, pow : &dyn PowFunction) -> bool {
+        has_required_zeros(&pow.hash(&self.bytes))
+    }
+}
+
+impl AsRef<[u8]> for BlockHeader {
+    fn as_ref(&self) -> &[u8] {
+        &self.bytes
+    }
+}
+
+/// Checks whether the given hash starts with ZEROS 0's, i.e. whether it's the hash of a
+/// correctly "mined" Block.
+pub(crate) fn has_required_zeros(hash: &ShaHash) -> bool {
+    // Expected
+    const NO_OF_NULL_BYTES : usize = (ZEROS / 8) as usize;
+    const NO_OF_0_BITS: usize = (ZEROS % 8) as usize;
+
+    // Check if there are NO_OF_NULL_BYTES bytes with the value 0.
+    if hash.as_bytes().iter().take(NO_OF_NULL_BYTES).any(|&byte| byte != 0) {
+        return false;
+    }
+
+    if NO_OF_0_BITS == 0 {
+        // Only whole bytes should be 0, but no single bits in the last mixed byte.
+        return true;
+    }
+
+    // Go through the mixed byte after the zero bytes and check that the first NO_OF_0_BITS are zero
+    let last_mixed_byte = hash.as_bytes()[NO_OF_NULL_BYTES];
+    let mut pattern = 0b1000_0000u8;
+    for _ in 0..NO_OF_0_BITS {
+        if last_mixed_byte & pattern != 0 {
+            // Found a non-zero bit
+            return false;
+        }
+        pattern >>= 1;
+    }
+    true
+}
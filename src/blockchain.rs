@@ -1,27 +1,288 @@
-use crate::block::Block;
+use crate::block::{Block, BlockHeader, ZEROS};
+use crate::bridge::verify_header_chain_with;
+use crate::chain_params::ChainParams;
+use crate::overlay::Overlay;
+use crate::observer::Observer;
+use crate::governance::{Governance, ParamUpdate};
+use crate::beacon::{randomness_from, RANDOMNESS_WINDOW};
 use std::sync::Mutex;
-use crate::merkle_tree::MerkleTree;
+use std::ops::Range;
+use std::collections::{HashMap, HashSet};
+use std::collections::hash_map::Entry;
+use std::fmt;
+use crate::merkle_tree::{MerkleTree, MerkleProof, LeafProvider};
+use crate::ShaHash;
 
 /// A Blockchain chaining Blocks, each of the Blocks storing multiple values of type T.
 #[derive(Debug)]
 pub struct Blockchain<T : AsRef<[u8]> + Clone> {
     /// The list of all blocks stored in this blockchain.
     blocks : Vec<Block<T>>,
+    /// The parameters of this Blockchain, see ChainParams.
+    params : ChainParams,
     /// Only 1 thread shall be able to append a Block to a Blockchain at a given time
     /// (to undefined undefined behaviour). Note that this mutex only locks the appending and
     /// obviously not the time-intensive mining process that happens beforehand!
     /// (see difference between append_block() and append_data())
-    append_mutex : Mutex<()>
+    append_mutex : Mutex<()>,
+    /// Everything that was reclaimed by prune() over the lifetime of this Blockchain.
+    reclaimed : Reclaimed,
+    /// The sequence numbers of all leaves, if enabled (see enable_sequence_numbers()).
+    sequence_numbers : Option<SequenceNumbers>,
+    /// The hashes of all the Blocks of this Blockchain that were fully validated already and don't
+    /// have to be validated again by verify(). The Blocks of a Blockchain can only be changed by
+    /// prune(), which removes the pruned Blocks from this set.
+    validated : Mutex<HashSet<ShaHash>>,
+    /// The heights of all the Blocks of this Blockchain by their hashes, which prune() doesn't
+    /// change (see height_of()).
+    heights : HashMap<ShaHash, usize>,
+    /// Gets notified about what happens to this Blockchain, see set_observer().
+    observer : Option<Box<dyn Observer>>,
+    /// The hashes of the leaves and subtrees that prune() must not forget, see pin().
+    pinned : HashSet<ShaHash>,
+    /// Decides which governance leaves may change the ChainParams, see set_governance().
+    governance : Option<Box<dyn Governance<T>>>,
+    /// The authorized updates of the ChainParams, each with the height it takes effect at and its
+    /// sequence number, sorted by both. They are recorded when their Blocks are appended, so
+    /// pruning doesn't lose them.
+    param_updates : Vec<(usize, u64, ParamUpdate)>,
+    /// Sends the new TipInfo to all receivers whenever a Block is appended, see watch_tip().
+    #[cfg(feature = "watch")]
+    tip_sender : tokio::sync::watch::Sender<TipInfo>
+}
+
+/// The sequence numbers of the leaves of a Blockchain, see Blockchain::sequence_of().
+#[derive(Debug, Default)]
+struct SequenceNumbers {
+    /// The sequence numbers of all leaves by their hashes.
+    by_leaf_hash : HashMap<ShaHash, u64>,
+    /// The sequence number the next confirmed leaf gets.
+    next : u64
+}
+
+impl SequenceNumbers {
+
+    /// Assigns sequence numbers to all leaves of the given Merkle Tree that don't have one yet.
+    fn assign<T : AsRef<[u8]> + Clone>(&mut self, mtree : &MerkleTree<T>) {
+        for leaf_hash in mtree.get_leaf_hashes() {
+            if let Entry::Vacant(entry) = self.by_leaf_hash.entry(leaf_hash) {
+                entry.insert(self.next);
+                self.next += 1;
+            }
+        }
+    }
+}
+
+/// The reason why a Block of a Blockchain is invalid, see Blockchain::first_invalid().
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BlockchainError {
+    /// The prev_hash of the Block is not the hash of the Block that comes directly before it
+    /// (or the initial hash for the very first Block).
+    InvalidPrevHash,
+    /// The proof of work hash of the Block does not start with ZEROS 0's.
+    InvalidNonce,
+    /// Some of the hashes in the Merkle Tree of the Block are incorrect.
+    InvalidMerkleTree,
+    /// The Merkle Tree of the Block exceeds the BlockLimits of the ChainParams.
+    LimitExceeded,
+    /// The Merkle Tree of the Block lacks the data of some of its leaves, which a Blockchain with
+    /// a Governance needs to find all governance leaves (see Blockchain::set_governance()).
+    MissingData
+}
+
+impl fmt::Display for BlockchainError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BlockchainError::InvalidPrevHash => write!(f, "prev_hash does not match the previous Block"),
+            BlockchainError::InvalidNonce => write!(f, "invalid nonce"),
+            BlockchainError::InvalidMerkleTree => write!(f, "invalid Merkle Tree"),
+            BlockchainError::LimitExceeded => write!(f, "Block exceeds the limits"),
+            BlockchainError::MissingData => write!(f, "Block lacks the data of some of its leaves")
+        }
+    }
+}
+
+impl std::error::Error for BlockchainError {}
+
+/// Statistics about a Blockchain, see Blockchain::stats().
+#[derive(Clone, Debug, PartialEq)]
+pub struct ChainStats {
+    /// The total number of Blocks in the Blockchain.
+    pub length : usize,
+    /// Everything that was reclaimed by pruning the Blockchain so far.
+    pub reclaimed : Reclaimed
+}
+
+/// The tip of a Blockchain, see Blockchain::tip().
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TipInfo {
+    /// The number of Blocks in the Blockchain.
+    pub length : usize,
+    /// The hash of the last Block, or the initial hash of the ChainParams when there is none.
+    pub hash : ShaHash
+}
+
+/// What has to be fetched (e.g. from other nodes or a backup) to restore requested data, see
+/// Blockchain::plan_restore().
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RestorePlan {
+    /// The forgotten leaves and subtrees to fetch, each as the height of its Block together with
+    /// its hash, sorted by height (and otherwise in the order of the requests).
+    pub fetch : Vec<(usize, ShaHash)>,
+    /// The requested hashes whose data is stored completely already.
+    pub available : Vec<ShaHash>,
+    /// The requested hashes that were not found in any Block. They may be part of a forgotten
+    /// subtree, which has to be restored first (its leaf hashes are not known until then).
+    pub unknown : Vec<ShaHash>
+}
+
+/// What a single call of Blockchain::scrub() found and repaired.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ScrubReport {
+    /// The number of Blocks that were checked.
+    pub checked : usize,
+    /// The leaves whose data was corrupted and restored from the archive, each as the height of
+    /// its Block together with its hash.
+    pub repaired : Vec<(usize, ShaHash)>,
+    /// The leaves whose data was corrupted but not found in the archive, so it was forgotten.
+    pub forgotten : Vec<(usize, ShaHash)>,
+    /// The Blocks that are still invalid (e.g. because their headers were corrupted), together
+    /// with the reason why.
+    pub invalid : Vec<(usize, BlockchainError)>
+}
+
+/// The amount of data that was removed from a Blockchain by pruning it.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Reclaimed {
+    /// The number of leaves whose data was forgotten.
+    pub leaves : usize,
+    /// The size of the forgotten data in bytes.
+    pub bytes : usize
+}
+
+/// What was reclaimed by a single call of Blockchain::prune().
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PruneReport {
+    /// What was reclaimed in each of the pruned Blocks, together with the Block's height.
+    pub per_block : Vec<(usize, Reclaimed)>,
+    /// What was reclaimed in all of the pruned Blocks together.
+    pub total : Reclaimed
 }
 
 impl<T : AsRef<[u8]> + Clone> Blockchain<T> {
 
-    /// Creates a new `Blockchain`.
+    /// Creates a new `Blockchain` with the default ChainParams.
     pub fn new() -> Blockchain<T> {
+        Self::with_params(ChainParams::default())
+    }
+
+    /// Creates a new `Blockchain` with the given ChainParams.
+    pub fn with_params(params : ChainParams) -> Blockchain<T> {
         Blockchain {
             blocks: Vec::new(),
-            append_mutex : Mutex::new(())
+            params,
+            append_mutex : Mutex::new(()),
+            reclaimed : Reclaimed::default(),
+            sequence_numbers : None,
+            validated : Mutex::new(HashSet::new()),
+            heights : HashMap::new(),
+            observer : None,
+            pinned : HashSet::new(),
+            governance : None,
+            param_updates : Vec::new(),
+            #[cfg(feature = "watch")]
+            tip_sender : tokio::sync::watch::channel(TipInfo { length: 0, hash: params.initial_hash }).0
+        }
+    }
+
+    /// Returns the parameters of this Blockchain.
+    pub fn get_params(&self) -> &ChainParams {
+        &self.params
+    }
+
+    /// Sets the Observer getting notified about what happens to this Blockchain from now on,
+    /// replacing the previous one.
+    pub fn set_observer(&mut self, observer : Box<dyn Observer>) {
+        self.observer = Some(observer);
+    }
+
+    /// Sets the Governance deciding which governance leaves may update the ChainParams of this
+    /// Blockchain (see params_at()). The Blocks that are already part of this Blockchain are
+    /// searched for governance leaves right away, so this should be called before pruning them.
+    ///
+    /// Every participant has to see every governance leaf, so the Blocks appended to this
+    /// Blockchain must contain the data of all of their leaves (they can be pruned afterwards).
+    /// Blocks lacking some of it are rejected with BlockchainError::MissingData.
+    pub fn set_governance(&mut self, governance : Box<dyn Governance<T>>) {
+        self.governance = Some(governance);
+        self.param_updates.clear();
+        for height in 0..self.blocks.len() {
+            let updates = self.authorized_updates(&self.blocks[height], height);
+            schedule(&mut self.param_updates, None, updates);
         }
+        // The limits may have changed, so every Block has to be validated again
+        self.validated.get_mut().unwrap().clear();
+    }
+
+    /// Returns the ChainParams the Block at the given height is validated with: the ones of this
+    /// Blockchain with all governance updates that took effect up to that height applied in the
+    /// order they were confirmed (see set_governance()).
+    pub fn params_at(&self, height : usize) -> ChainParams {
+        let mut params = self.params;
+        for (_, _, update) in self.param_updates.iter().take_while(|(activation, _, _)| *activation <= height) {
+            update.apply(&mut params);
+        }
+        params
+    }
+
+    /// Returns the authorized updates of the given Block at the given height (of this Blockchain
+    /// or of an Overlay on top of it), each with the height it takes effect at and its sequence
+    /// number (see set_governance()). Whether their sequence numbers are new is up to schedule().
+    pub(crate) fn authorized_updates(&self, block : &Block<T>, height : usize) -> Vec<(usize, u64, ParamUpdate)> {
+        let governance = match &self.governance {
+            Some(governance) => governance,
+            None => return Vec::new()
+        };
+        let activation = height + 1 + governance.activation_delay();
+        block.merkle_tree.get_currently_stored_data().iter()
+            .filter_map(|leaf| governance.authorized_update(leaf))
+            .map(|(sequence, update)| (activation, sequence, update))
+            .collect()
+    }
+
+    /// Returns the sequence number of the last governance update confirmed by this Blockchain, see
+    /// ParamUpdate::to_leaf().
+    pub(crate) fn last_update_sequence(&self) -> Option<u64> {
+        self.param_updates.last().map(|(_, sequence, _)| *sequence)
+    }
+
+    /// Checks whether the given Block contains the data of all of its leaves in case this
+    /// Blockchain has a Governance (see set_governance()), so no governance leaf can be hidden.
+    pub(crate) fn check_complete(&self, block : &Block<T>) -> Result<(), BlockchainError> {
+        match self.governance.is_some() && block.merkle_tree.forgotten_leaf_count() > 0 {
+            true => Err(BlockchainError::MissingData),
+            false => Ok(())
+        }
+    }
+
+    /// Pins the leaf or subtree with the given hash, so that prune() never forgets its data
+    /// (whatever is currently stored of it), e.g. for data an application still needs.
+    /// Returns false when it was pinned already.
+    ///
+    /// The hash does not have to be part of this Blockchain (yet).
+    pub fn pin(&mut self, hash : ShaHash) -> bool {
+        self.pinned.insert(hash)
+    }
+
+    /// Unpins the leaf or subtree with the given hash again, see pin().
+    /// Returns false when it was not pinned.
+    pub fn unpin(&mut self, hash : ShaHash) -> bool {
+        self.pinned.remove(&hash)
+    }
+
+    /// Returns whether the leaf or subtree with the given hash is pinned, see pin().
+    pub fn is_pinned(&self, hash : &ShaHash) -> bool {
+        self.pinned.contains(hash)
     }
 
     /// Returns the total number of Blocks in this Blockchain.
@@ -29,13 +290,233 @@ impl<T : AsRef<[u8]> + Clone> Blockchain<T> {
         self.blocks.len()
     }
 
+    /// Returns the difficulty of the Block at the given height, i.e. the number of zero bits its
+    /// proof of work hash has to start with, or None when this Blockchain is not that long (yet).
+    ///
+    /// The difficulty is currently the same for all Blocks (see ZEROS), but explorers should use
+    /// this instead of relying on that.
+    pub fn difficulty_at(&self, height : usize) -> Option<u8> {
+        self.blocks.get(height).map(|_| ZEROS)
+    }
+
+    /// Estimates how much memory this Blockchain takes up in bytes: its Blocks (see
+    /// MerkleTree::approximate_size_bytes()) and the entries of its indexes (the heights, the
+    /// validated and pinned hashes, the ChainParams updates and the sequence numbers). The
+    /// overhead of the collections themselves (e.g. unused capacity) is not included.
+    pub fn approximate_size_bytes(&self) -> usize {
+        self.approximate_size_bytes_with(|data| data.as_ref().len())
+    }
+
+    /// Same as approximate_size_bytes(), but uses the given function to determine how much memory
+    /// a stored element takes up, see MerkleTree::approximate_size_bytes_with().
+    pub fn approximate_size_bytes_with<F : Fn(&T) -> usize>(&self, data_size : F) -> usize {
+        // The root of each Merkle Tree is part of its Block already
+        let blocks : usize = self.blocks.iter()
+            .map(|block| std::mem::size_of::<Block<T>>() - std::mem::size_of::<MerkleTree<T>>()
+                + block.merkle_tree.approximate_size_bytes_with(&data_size))
+            .sum();
+        let validated = self.validated.lock().unwrap().len() * std::mem::size_of::<ShaHash>();
+        let heights = self.heights.len() * std::mem::size_of::<(ShaHash, usize)>();
+        let pinned = self.pinned.len() * std::mem::size_of::<ShaHash>();
+        let param_updates = self.param_updates.len() * std::mem::size_of::<(usize, u64, ParamUpdate)>();
+        let sequence_numbers = self.sequence_numbers.as_ref()
+            .map_or(0, |numbers| numbers.by_leaf_hash.len() * std::mem::size_of::<(ShaHash, u64)>());
+        std::mem::size_of::<Blockchain<T>>() + blocks + validated + heights + pinned + param_updates + sequence_numbers
+    }
+
+    /// Returns randomness derived from the hashes of the RANDOMNESS_WINDOW Blocks up to (and
+    /// including) the given height, and from the given salt (e.g. the purpose of the randomness,
+    /// so that different applications get different values from the same Blocks).
+    /// Returns None when this Blockchain is not that long (yet).
+    ///
+    /// Everyone gets the same randomness for the same height and salt, but it is not unbiased:
+    /// see the beacon module for the limitations and for combining it with the secrets of
+    /// participants.
+    pub fn randomness_at(&self, height : usize, salt : &[u8]) -> Option<ShaHash> {
+        if height >= self.blocks.len() {
+            return None;
+        }
+        let first = (height + 1).saturating_sub(RANDOMNESS_WINDOW);
+        let hashes : Vec<ShaHash> = self.blocks[first..=height].iter().map(Block::calculate_hash).collect();
+        Some(randomness_from(&hashes, salt))
+    }
+
+    /// Returns the Block at the given height (the very first Block having height 0)
+    /// or None when this Blockchain is not that long (yet).
+    pub fn get_block(&self, height : usize) -> Option<&Block<T>> {
+        self.blocks.get(height)
+    }
+
+    /// Returns an Overlay on top of this Blockchain, to which Blocks can be appended and
+    /// validated without changing this Blockchain.
+    pub fn overlay(&self) -> Overlay<'_, T> {
+        Overlay::new(self)
+    }
+
+    /// Returns statistics about this Blockchain.
+    pub fn stats(&self) -> ChainStats {
+        ChainStats {
+            length: self.length(),
+            reclaimed: self.reclaimed
+        }
+    }
+
+    /// Returns the current tip of this Blockchain, i.e. its length and the hash of its last Block.
+    pub fn tip(&self) -> TipInfo {
+        TipInfo {
+            length: self.length(),
+            hash: self.hash_of_last_block()
+        }
+    }
+
+    /// Returns a receiver that is updated with the new tip whenever a Block is appended to this
+    /// Blockchain, so async tasks (e.g. a miner that has to start over on a new tip) can await
+    /// it using changed() instead of observing everything (see set_observer()).
+    #[cfg(feature = "watch")]
+    pub fn watch_tip(&self) -> tokio::sync::watch::Receiver<TipInfo> {
+        self.tip_sender.subscribe()
+    }
+
     /// Returns the hash of the last/latest block in this Blockchain
-    /// or the INITIAL_HASH when this Blockchain is still empty.
-    pub fn hash_of_last_block(&self) -> SHAHash {
+    /// or the initial hash of its ChainParams when this Blockchain is still empty.
+    pub fn hash_of_last_block(&self) -> ShaHash {
         match self.blocks.last() {
             Some(last_block) => last_block.calculate_hash(),
-            None => INITIAL_HASH,
+            None => self.params.initial_hash,
+        }
+    }
+
+    /// Returns the number of Blocks up to and including the Block with the given hash
+    /// (0 for the initial hash), or None when there is no Block with that hash in this Blockchain.
+    fn length_up_to(&self, hash : ShaHash) -> Option<usize> {
+        if hash == self.params.initial_hash {
+            return Some(0);
+        }
+        self.height_of(hash).map(|height| height + 1)
+    }
+
+    /// Returns the height of the Block with the given hash, or None when there is no Block with
+    /// that hash in this Blockchain. Only that Block is hashed, not the whole Blockchain.
+    fn height_of(&self, hash : ShaHash) -> Option<usize> {
+        let height = *self.heights.get(&hash)?;
+        Some(height).filter(|height| self.blocks[*height].calculate_hash() == hash)
+    }
+
+    /// Generates a proof that this Blockchain up to the Block with the hash `new_tip` is an
+    /// append-only extension of this Blockchain up to the Block with the hash `old_tip`, i.e. that
+    /// none of the Blocks up to `old_tip` were changed. Use the initial hash as `old_tip` to prove
+    /// the entire Blockchain up to `new_tip`.
+    ///
+    /// The proof consists of the headers of all the Blocks after `old_tip` up to and including
+    /// `new_tip`. Auditors can check it using verify_consistency() while only ever remembering
+    /// the hash of the last Block they have seen.
+    ///
+    /// Returns None when one of the Blocks is not part of this Blockchain or when `new_tip`
+    /// comes before `old_tip`.
+    pub fn consistency_proof(&self, old_tip : ShaHash, new_tip : ShaHash) -> Option<Vec<BlockHeader>> {
+        let old_length = self.length_up_to(old_tip)?;
+        let new_length = self.length_up_to(new_tip)?;
+        if old_length > new_length {
+            return None;
         }
+        Some(self.blocks[old_length..new_length].iter()
+            .map(|block| block.header())
+            .collect())
+    }
+
+    /// Returns a page of the currently stored data of the Block with the given hash: at most
+    /// `limit` elements, skipping the first `offset` ones, each together with a proof that it is
+    /// part of that Block (see TreeVersion::verify_proof() against the Block's Merkle root).
+    /// Equal elements each get the proof of their own leaf.
+    ///
+    /// This allows handing out the data of large Blocks incrementally instead of all at once.
+    /// Forgotten elements are not counted, so the pages only stay stable as long as no data of
+    /// the Block is forgotten or restored in between.
+    ///
+    /// Returns None when there is no Block with that hash in this Blockchain.
+    pub fn get_block_data(&self, hash : ShaHash, offset : usize, limit : usize) -> Option<Vec<(T, MerkleProof)>> {
+        let height = self.height_of(hash)?;
+        Some(self.blocks[height].merkle_tree.get_stored_data_page(offset, limit))
+    }
+
+    /// Plans restoring the data of the leaves (or subtrees) with the given hashes: for each of
+    /// them, lists the forgotten parts that have to be fetched - at most one per forgotten leaf
+    /// or subtree, as fetching a bigger subtree would fetch more than requested. Fetched data can
+    /// be put back using restore() and restore_subtree().
+    ///
+    /// The expected sizes of the forgotten parts are unknown, as forgetting data keeps nothing
+    /// but its hash.
+    pub fn plan_restore(&self, requests : &[ShaHash]) -> RestorePlan {
+        let mut plan = RestorePlan::default();
+        for request in requests {
+            let found : Vec<(usize, &MerkleTree<T>)> = self.blocks.iter().enumerate()
+                .filter_map(|(height, block)| Some((height, block.merkle_tree.get_subtree(request)?)))
+                .collect();
+            if found.is_empty() {
+                plan.unknown.push(*request);
+                continue;
+            }
+            let mut complete = true;
+            for (height, subtree) in found {
+                for (hash, _) in subtree.get_leaves().into_iter().filter(|(_, data)| data.is_none()) {
+                    complete = false;
+                    if !plan.fetch.contains(&(height, hash)) {
+                        plan.fetch.push((height, hash));
+                    }
+                }
+            }
+            if complete {
+                plan.available.push(*request);
+            }
+        }
+        plan.fetch.sort_by_key(|(height, _)| *height);
+        plan
+    }
+
+    /// Restores the data of a forgotten leaf of the Block at the given height, hashing it like the
+    /// TreeVersion of this Blockchain does (see plan_restore()).
+    ///
+    /// Returns false when the Block has no leaf with the hash of the given data.
+    pub fn restore(&mut self, height : usize, data : T) -> bool {
+        let tree_version = self.params.tree_version;
+        match self.blocks.get_mut(height) {
+            Some(block) => tree_version.restore_element(&mut block.merkle_tree, data),
+            None => false
+        }
+    }
+
+    /// Restores a forgotten subtree of the Block at the given height (see plan_restore()), see
+    /// MerkleTree::restore_subtree().
+    ///
+    /// Returns false when the given subtree is not valid for the TreeVersion of this Blockchain or
+    /// was not found in the Block.
+    pub fn restore_subtree(&mut self, height : usize, subtree : MerkleTree<T>) -> bool {
+        if !self.params.tree_version.verify(&subtree) {
+            return false;
+        }
+        match self.blocks.get_mut(height) {
+            Some(block) => block.merkle_tree.restore_subtree(subtree),
+            None => false
+        }
+    }
+
+    /// Exports all the leaves of this Blockchain as CSV, one row per leaf, e.g. for analysing
+    /// the Blockchain with a spreadsheet or a data frame library. The columns are:
+    /// `height,block_hash,leaf_index,leaf_hash,size`
+    ///
+    /// Hashes are hex-encoded and the size is the size of the data of the leaf in bytes, left empty
+    /// when the data is not stored anymore. Subtrees that were forgotten entirely show up as a
+    /// single leaf (see MerkleTree::get_leaf_hashes()).
+    pub fn export_csv(&self) -> String {
+        let mut csv = String::from("height,block_hash,leaf_index,leaf_hash,size\n");
+        for (height, block) in self.blocks.iter().enumerate() {
+            let block_hash = block.calculate_hash();
+            for (leaf_index, (leaf_hash, data)) in block.merkle_tree.get_leaves().into_iter().enumerate() {
+                let size = data.map(|data| data.as_ref().len().to_string()).unwrap_or_default();
+                csv.push_str(&format!("{},{},{},{},{}\n", height, block_hash, leaf_index, leaf_hash, size));
+            }
+        }
+        csv
     }
 
     /// Verify the correctness of this Blockchain:
@@ -45,26 +526,41 @@ impl<T : AsRef<[u8]> + Clone> Blockchain<T> {
     /// Or to put it differently:
     /// - Checks whether all the 'Previous Hashes' of the Blocks actually are the hash of the
     ///   blocks that comes directly before it.
-    /// - Calls .verify() on each of the blocks in this Blockchain (this includes checking all
-    ///   of the Merkle Trees for validity!)
+    /// - Calls .verify_with() with the ChainParams of this Blockchain on each of its blocks (this
+    ///   includes checking all of the Merkle Trees for validity!)
+    ///
+    /// Blocks that were validated before (when appending them or by an earlier call) are not
+    /// validated again unless they were pruned in the meantime, only their prev_hash is checked.
     pub fn verify(&self) -> bool {
-        let mut previous_hash = INITIAL_HASH;
-        for block in &self.blocks {
-            let valid_link_to_prev_block = block.prev_hash == previous_hash;
-            let valid_block = block.verify();
-            if !valid_link_to_prev_block || !valid_block {
-                // Inconsistency found!
-                return false;
+        self.first_invalid().is_none()
+    }
+
+    /// Same as verify(), but instead of just returning false, returns the height of the first
+    /// invalid Block together with the reason why it is invalid.
+    /// Returns None when this Blockchain is valid.
+    pub fn first_invalid(&self) -> Option<(usize, BlockchainError)> {
+        let mut validated = self.validated.lock().unwrap();
+        let mut previous_hash = self.params.initial_hash;
+        for (height, block) in self.blocks.iter().enumerate() {
+            // Inconsistency found?
+            if block.prev_hash != previous_hash {
+                return Some((height, BlockchainError::InvalidPrevHash));
+            }
+            let block_hash = block.calculate_hash();
+            if !validated.contains(&block_hash) {
+                if let Err(error) = check_block(&self.params_at(height), block) {
+                    return Some((height, error));
+                }
+                validated.insert(block_hash);
             }
-            // TODO: I don't think it's necessary to recalculate the hashes
-            // previous_hash = block.calculate_hash(); // update the previous_hash
+            previous_hash = block_hash; // update the previous_hash
         }
         // No inconsistencies found in the Blockchain!
-        true
+        None
     }
 
-    /// Checks whether the given Block has a correct nonce and prev_hash.
-    /// If so, appends the given Block to this Blockchain and returns true.
+    /// Checks whether the given Block has a correct nonce and prev_hash (and stays within the
+    /// BlockLimits of the ChainParams). If so, appends the given Block to this Blockchain and returns true.
     /// Returns false when the given Block was incorrect and was not appended.
     ///
     /// This function is primarily used for appending Blocks that others publicly announced to
@@ -72,20 +568,82 @@ impl<T : AsRef<[u8]> + Clone> Blockchain<T> {
     /// In order to append your own data, you have to find out the nonce using trial-and-error
     /// first - the append_data() function does that for you.
     pub fn append_block(&mut self, block : Block<T>) -> bool {
+        self.append_checked(block, false).is_ok()
+    }
+
+    /// Same as append_block(), but returns why the given Block was not appended. When
+    /// `prevalidated` is true, the checks of prevalidate() are skipped, as they were done already.
+    pub(crate) fn append_checked(&mut self, block : Block<T>, prevalidated : bool) -> Result<(), BlockchainError> {
         let _guard = self.append_mutex.lock().unwrap(); // <synchronize>
-        let valid_block = block.verify();
+        let height = self.blocks.len();
+        let params = self.params_at(height);
+        let valid_block = match prevalidated {
+            true => check_limits(&params, &block),
+            false => check_block(&params, &block)
+        }.and_then(|_| self.check_complete(&block));
         let valid_link_to_prev_block = block.prev_hash == self.hash_of_last_block();
-        if valid_block && valid_link_to_prev_block {
+        if valid_block.is_ok() && valid_link_to_prev_block {
+            if let Some(sequence_numbers) = &mut self.sequence_numbers {
+                sequence_numbers.assign(&block.merkle_tree);
+            }
+            let block_hash = block.calculate_hash();
+            self.validated.get_mut().unwrap().insert(block_hash);
+            self.heights.entry(block_hash).or_insert(height);
+            let updates = self.authorized_updates(&block, height);
+            schedule(&mut self.param_updates, None, updates);
             self.blocks.push(block);
-            true
+            if let Some(observer) = &self.observer {
+                observer.on_block_validated(self.blocks.len() - 1, block_hash);
+            }
+            #[cfg(feature = "watch")]
+            self.tip_sender.send_replace(TipInfo { length: self.blocks.len(), hash: block_hash });
+            Ok(())
         } else {
             // Invalid blockchain
             // TODO: Instead of a return value maybe just panic, if the blockchain is invalid?
-            false
+            valid_block.and(Err(BlockchainError::InvalidPrevHash))
         }
         // </synchronize> The lock is released automatically here because the MutexGuard goes out of scope!
     }
 
+    /// Starts assigning sequence numbers to all leaves of this Blockchain, see sequence_of().
+    /// The leaves of the Blocks that are already part of this Blockchain are numbered right away.
+    pub fn enable_sequence_numbers(&mut self) {
+        if self.sequence_numbers.is_some() {
+            return;
+        }
+        let mut sequence_numbers = SequenceNumbers::default();
+        for block in &self.blocks {
+            sequence_numbers.assign(&block.merkle_tree);
+        }
+        self.sequence_numbers = Some(sequence_numbers);
+    }
+
+    /// Rebuilds everything this Blockchain derives from its Blocks: the set of already validated
+    /// Blocks is cleared and every Block is validated again from scratch, and the sequence numbers
+    /// (if enabled) are assigned anew. Forgotten leaves keep their hashes, so pruning does not
+    /// change the sequence numbers.
+    ///
+    /// Returns the same as first_invalid(), i.e. None when this Blockchain is valid.
+    pub fn reindex(&mut self) -> Option<(usize, BlockchainError)> {
+        self.validated.get_mut().unwrap().clear();
+        if self.sequence_numbers.take().is_some() {
+            self.enable_sequence_numbers();
+        }
+        self.first_invalid()
+    }
+
+    /// Returns the sequence number of the leaf with the given hash, or None when there is no
+    /// such leaf or when sequence numbers are not enabled (see enable_sequence_numbers()).
+    ///
+    /// Sequence numbers are assigned to the leaves in the order they are confirmed, i.e. block by
+    /// block and from left to right within a Block, starting at 0. This gives a total order over
+    /// all the data in this Blockchain that does not change when data is forgotten later on.
+    /// When the same leaf is confirmed more than once, it keeps its first sequence number.
+    pub fn sequence_of(&self, leaf_hash : &ShaHash) -> Option<u64> {
+        self.sequence_numbers.as_ref()?.by_leaf_hash.get(leaf_hash).copied()
+    }
+
     /// Takes the data given as a MerkleTree and "mines" a new Block for it, then appends it to
     /// this Blockchain. When the "mining" (nonce calculation) finished but another Block was
     /// appended in the meantime (most likely via an append_block() call), the whole process has
@@ -94,9 +652,12 @@ impl<T : AsRef<[u8]> + Clone> Blockchain<T> {
     ///
     /// This function also returns a copy of the "mined" Block so you can announce it to the network!!
     /// (The communication with others on the network is NOT part of this library!!)
+    ///
+    /// The mined Block is only appended when it is valid, e.g. when its Merkle Tree stays within
+    /// the BlockLimits, see try_append_data() for finding out whether it was.
     pub fn append_data(&mut self, mtree : MerkleTree<T>) -> Block<T> {
         let mut new_block = Block::new(self.hash_of_last_block(), mtree);
-        new_block.calculate_nonce();
+        self.mine(&mut new_block, None);
         self.append_block(new_block.clone());
         new_block
 
@@ -107,4 +668,243 @@ impl<T : AsRef<[u8]> + Clone> Blockchain<T> {
         //       b) somebody else publicly announced a new block which you/another thread of you
         //          simply added using a quick append_block() call
     }
-}
\ No newline at end of file
+
+    /// Same as append_data(), but "mines" the new Block using Block::calculate_nonce_seeded(),
+    /// so that the resulting Blockchain is reproducible for the given seed.
+    pub fn append_data_seeded(&mut self, mtree : MerkleTree<T>, seed : u64) -> Block<T> {
+        let mut new_block = Block::new(self.hash_of_last_block(), mtree);
+        self.mine(&mut new_block, Some(seed));
+        self.append_block(new_block.clone());
+        new_block
+    }
+
+    /// Same as append_data(), but returns why no Block was appended instead. The Merkle Tree is
+    /// checked before mining, so a Merkle Tree exceeding the BlockLimits (see params_at()) or of
+    /// another TreeVersion than the one of this Blockchain (create it using
+    /// `tree_version.new_tree()` of its ChainParams) is rejected right away.
+    pub fn try_append_data(&mut self, mtree : MerkleTree<T>) -> Result<Block<T>, BlockchainError> {
+        self.mine_and_append(Block::new(self.hash_of_last_block(), mtree), None)
+    }
+
+    /// Same as append_data_seeded(), but returns why no Block was appended, see try_append_data().
+    pub fn try_append_data_seeded(&mut self, mtree : MerkleTree<T>, seed : u64) -> Result<Block<T>, BlockchainError> {
+        self.mine_and_append(Block::new(self.hash_of_last_block(), mtree), Some(seed))
+    }
+
+    /// Checks the Merkle Tree of the given new Block, mines it and appends it, see
+    /// try_append_data().
+    fn mine_and_append(&mut self, mut new_block : Block<T>, seed : Option<u64>) -> Result<Block<T>, BlockchainError> {
+        check_unmined(&self.params_at(self.blocks.len()), &new_block)?;
+        self.check_complete(&new_block)?;
+        self.mine(&mut new_block, seed);
+        self.append_checked(new_block.clone(), true)?;
+        Ok(new_block)
+    }
+
+    /// Calculates the nonce of the given new Block, starting at the given seed (see
+    /// Block::calculate_nonce_seeded()).
+    fn mine(&self, new_block : &mut Block<T>, seed : Option<u64>) {
+        self.notify_mining_started(new_block.prev_hash);
+        if let Some(seed) = seed {
+            new_block.seed_nonce(seed);
+        }
+        new_block.calculate_nonce_with(self.params.pow);
+    }
+
+    /// Prunes the Blocks with the given heights by calling `forget` on each of their Merkle Trees
+    /// (e.g. with MerkleTree::forget_all_leaves() or MerkleTree::shrink_to_minimum()) and reports
+    /// how much data was reclaimed by that.
+    ///
+    /// Heights beyond the end of this Blockchain are ignored. Pinned leaves and subtrees (see
+    /// pin()) are restored right after calling `forget`, so they are never reclaimed.
+    pub fn prune<F>(&mut self, heights : Range<usize>, mut forget : F) -> PruneReport
+        where F : FnMut(&mut MerkleTree<T>) {
+        let mut report = PruneReport::default();
+        let end = heights.end.min(self.blocks.len());
+        for height in heights.start..end {
+            // Forgetting may also change the data in invalid ways, so validate the Block again
+            self.validated.get_mut().unwrap().remove(&self.blocks[height].calculate_hash());
+            let merkle_tree = &mut self.blocks[height].merkle_tree;
+            let before = merkle_tree.stats();
+            let pinned = pinned_skeleton(merkle_tree, &self.pinned);
+            forget(merkle_tree);
+            if let Some(pinned) = pinned {
+                merkle_tree.restore_subtree(pinned);
+            }
+            let after = merkle_tree.stats();
+            // Forgetting may also restore data (e.g. insert_subtree()) which is not reclaimed
+            let reclaimed = Reclaimed {
+                leaves: before.stored_leaf_count.saturating_sub(after.stored_leaf_count),
+                bytes: before.stored_bytes.saturating_sub(after.stored_bytes)
+            };
+            report.per_block.push((height, reclaimed));
+            report.total.leaves += reclaimed.leaves;
+            report.total.bytes += reclaimed.bytes;
+        }
+        self.reclaimed.leaves += report.total.leaves;
+        self.reclaimed.bytes += report.total.bytes;
+        if let Some(observer) = &self.observer {
+            observer.on_prune(&report);
+        }
+        report
+    }
+
+    /// Validates the Blocks with the given heights again from scratch (even if they were validated
+    /// before), to detect data that was corrupted in storage. The data of leaves that doesn't match
+    /// their hashes is forgotten and restored from the given archive (e.g. a backup or data fetched
+    /// from other nodes), or stays forgotten when the archive doesn't have it - either way, the
+    /// Block is valid again. Everything else that is wrong is reported as invalid.
+    ///
+    /// Heights beyond the end of this Blockchain are ignored. Scrubbing a few Blocks at a time
+    /// (e.g. periodically from a background thread) spreads the work of scrubbing everything.
+    pub fn scrub<P : LeafProvider<T>>(&mut self, heights : Range<usize>, archive : &P) -> ScrubReport {
+        let mut report = ScrubReport::default();
+        let end = heights.end.min(self.blocks.len());
+        for height in heights.start..end {
+            let tree_version = self.params.tree_version;
+            let block = &mut self.blocks[height];
+            self.validated.get_mut().unwrap().remove(&block.calculate_hash());
+            let corrupted = tree_version.forget_corrupted(&mut block.merkle_tree);
+            if !corrupted.is_empty() {
+                tree_version.restore_all(&mut block.merkle_tree, archive);
+                let stored : HashSet<ShaHash> = block.merkle_tree.get_leaves().into_iter()
+                    .filter(|(_, data)| data.is_some())
+                    .map(|(hash, _)| hash)
+                    .collect();
+                for leaf_hash in corrupted {
+                    if stored.contains(&leaf_hash) {
+                        report.repaired.push((height, leaf_hash));
+                    } else {
+                        report.forgotten.push((height, leaf_hash));
+                    }
+                }
+            }
+            let block = &self.blocks[height];
+            let prev_hash = match height {
+                0 => self.params.initial_hash,
+                _ => self.blocks[height - 1].calculate_hash()
+            };
+            let result = if block.prev_hash != prev_hash {
+                Err(BlockchainError::InvalidPrevHash)
+            } else {
+                check_block(&self.params_at(height), block)
+            };
+            match result {
+                Ok(()) => { self.validated.get_mut().unwrap().insert(block.calculate_hash()); },
+                Err(error) => report.invalid.push((height, error))
+            }
+            report.checked += 1;
+        }
+        if let Some(observer) = &self.observer {
+            observer.on_scrub(&report);
+        }
+        report
+    }
+
+    /// Notifies the Observer (if any) that mining a Block after the given one started.
+    fn notify_mining_started(&self, prev_hash : ShaHash) {
+        if let Some(observer) = &self.observer {
+            observer.on_mining_started(prev_hash);
+        }
+    }
+}
+
+impl<T : AsRef<[u8]> + Clone> Default for Blockchain<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Checks everything about the given Block but its prev_hash against the given ChainParams (the
+/// ones of its height, see Blockchain::params_at()): whether it stays within the BlockLimits, has
+/// a correct nonce and a valid Merkle Tree (in this order, the cheapest checks first).
+pub(crate) fn check_block<T : AsRef<[u8]> + Clone>(params : &ChainParams, block : &Block<T>) -> Result<(), BlockchainError> {
+    check_limits(params, block)?;
+    prevalidate(params, block)
+}
+
+/// Checks whether the given Block stays within the BlockLimits of the given ChainParams, which
+/// (unlike the rest of check_block()) depend on the Blocks before it, see Blockchain::params_at().
+pub(crate) fn check_limits<T : AsRef<[u8]> + Clone>(params : &ChainParams, block : &Block<T>) -> Result<(), BlockchainError> {
+    match params.limits.allow(&block.merkle_tree) {
+        true => Ok(()),
+        false => Err(BlockchainError::LimitExceeded)
+    }
+}
+
+/// Checks everything check_block() does but the nonce, i.e. whether the given Block is worth
+/// mining.
+pub(crate) fn check_unmined<T : AsRef<[u8]> + Clone>(params : &ChainParams, block : &Block<T>) -> Result<(), BlockchainError> {
+    check_limits(params, block)?;
+    match block.verify_merkle_tree_with(params.tree_version) {
+        true => Ok(()),
+        false => Err(BlockchainError::InvalidMerkleTree)
+    }
+}
+
+/// Checks everything about the given Block that doesn't depend on the Blocks before it: whether
+/// it has a correct nonce and a valid Merkle Tree (the expensive part of validating a Block).
+/// This can be done in parallel for many Blocks, see Blockchain::bulk_import().
+pub(crate) fn prevalidate<T : AsRef<[u8]> + Clone>(params : &ChainParams, block : &Block<T>) -> Result<(), BlockchainError> {
+    if !block.verify_nonce_with(params.pow) {
+        Err(BlockchainError::InvalidNonce)
+    } else if !block.verify_merkle_tree_with(params.tree_version) {
+        Err(BlockchainError::InvalidMerkleTree)
+    } else {
+        Ok(())
+    }
+}
+
+/// Adds the given updates of the ChainParams to the given sorted ones, see
+/// Blockchain::set_governance(). An update is ignored unless its sequence number is greater than
+/// the one of every update before it (including the one confirmed before the given ones with the
+/// sequence number `last_sequence`, if any), so replayed governance leaves have no effect.
+pub(crate) fn schedule(param_updates : &mut Vec<(usize, u64, ParamUpdate)>, last_sequence : Option<u64>, updates : Vec<(usize, u64, ParamUpdate)>) {
+    // Updates are confirmed in the order of their activation heights, so the last one is the newest
+    let mut last_sequence = param_updates.last().map(|(_, sequence, _)| *sequence).or(last_sequence);
+    for (activation, sequence, update) in updates {
+        if last_sequence.is_none_or(|last| sequence > last) {
+            param_updates.push((activation, sequence, update));
+            last_sequence = Some(sequence);
+        }
+    }
+}
+
+/// Returns a copy of the given Merkle Tree that only stores the pinned subtrees (and the nodes on
+/// the way to them), or None when nothing in it is pinned, see Blockchain::prune().
+fn pinned_skeleton<T : AsRef<[u8]> + Clone>(mtree : &MerkleTree<T>, pinned : &HashSet<ShaHash>) -> Option<MerkleTree<T>> {
+    if pinned.contains(&mtree.get_root_hash()) {
+        return Some(mtree.clone());
+    }
+    match mtree {
+        MerkleTree::Leaf{..} => None,
+        MerkleTree::Node{hash, left, right} => {
+            let forgotten = |subtree : &MerkleTree<T>| MerkleTree::Leaf { hash: subtree.get_root_hash(), data: None };
+            match (pinned_skeleton(left, pinned), pinned_skeleton(right, pinned)) {
+                (None, None) => None,
+                (new_left, new_right) => Some(MerkleTree::Node {
+                    hash: *hash,
+                    left: Box::new(new_left.unwrap_or_else(|| forgotten(left))),
+                    right: Box::new(new_right.unwrap_or_else(|| forgotten(right)))
+                })
+            }
+        }
+    }
+}
+
+/// Checks whether the given consistency proof (see Blockchain::consistency_proof()) shows that
+/// the Blockchain up to the Block with the hash `new_tip` is an append-only extension of the
+/// Blockchain up to the Block with the hash `old_tip`.
+pub fn verify_consistency(old_tip : ShaHash, new_tip : ShaHash, proof : &[BlockHeader]) -> bool {
+    verify_consistency_with(&ChainParams::default(), old_tip, new_tip, proof)
+}
+
+/// Same as verify_consistency(), but for a Blockchain with the given ChainParams.
+pub fn verify_consistency_with(params : &ChainParams, old_tip : ShaHash, new_tip : ShaHash,
+                               proof : &[BlockHeader]) -> bool {
+    let proven_tip = match proof.last() {
+        Some(header) => header.calculate_hash(),
+        None => old_tip
+    };
+    proven_tip == new_tip && verify_header_chain_with(params, old_tip, proof)
+}
@@ -0,0 +1,93 @@
+use sha2::Sha256;
+use crate::merkle_tree::{MerkleTree, MerkleProof, TreeHasher};
+use crate::ShaHash;
+
+/// A Bloom filter over the hashes of leaves, e.g. for a light client that is only interested in
+/// some of the data of a Blockchain (like the transactions of its own addresses).
+///
+/// The light client inserts the hashes of all the leaves it is interested in and hands the
+/// filter to a full node. The full node then only sends the leaves matching the filter together
+/// with their Merkle proofs (see matching_leaves()). As with any Bloom filter, leaves that were
+/// never inserted may match as well (false positives), which also hides from the full node
+/// which leaves the light client is actually interested in.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BloomFilter {
+    /// The bits of this filter.
+    bits: Vec<u8>,
+    /// The number of bits set for each inserted hash.
+    hash_count: u32
+}
+
+impl BloomFilter {
+
+    /// Creates a new, empty BloomFilter with the given size (in bytes) setting `hash_count` bits
+    /// for every inserted hash.
+    ///
+    /// Important: this function panics when called with a size of 0!
+    pub fn new(size_in_bytes: usize, hash_count: u32) -> BloomFilter {
+        assert!(size_in_bytes > 0, "Cannot create a BloomFilter with a size of 0!");
+        BloomFilter {
+            bits: vec![0u8; size_in_bytes],
+            hash_count
+        }
+    }
+
+    /// Inserts the given leaf hash into this filter.
+    pub fn insert_hash(&mut self, hash: &ShaHash) {
+        for index in self.bit_indices(hash) {
+            self.bits[index / 8] |= 1 << (index % 8);
+        }
+    }
+
+    /// Inserts the hash of the given element into this filter, i.e. the hash of the leaf storing
+    /// it in a Merkle Tree created using MerkleTree::new() (see insert_with()).
+    pub fn insert<T : AsRef<[u8]>>(&mut self, element: &T) {
+        self.insert_with::<Sha256, T>(element);
+    }
+
+    /// Same as insert(), but for Merkle Trees created using new_with() with the hash function D
+    /// (e.g. DomainSeparated<Sha256> for a Blockchain using TreeVersion::V2).
+    pub fn insert_with<D : TreeHasher, T : AsRef<[u8]>>(&mut self, element: &T) {
+        self.insert_hash(&D::hash_leaf(element.as_ref()));
+    }
+
+    /// Checks whether the given leaf hash matches this filter, i.e. whether it was (probably)
+    /// inserted before.
+    pub fn contains_hash(&self, hash: &ShaHash) -> bool {
+        self.bit_indices(hash).all(|index| self.bits[index / 8] & (1 << (index % 8)) != 0)
+    }
+
+    /// Returns all the leaves currently stored in the given Merkle Tree that match this filter,
+    /// each together with the proof that it's part of the Merkle Tree.
+    pub fn matching_leaves<T : AsRef<[u8]> + Clone>(&self, mtree: &MerkleTree<T>) -> Vec<(T, MerkleProof)> {
+        self.matching_leaves_with::<Sha256, T>(mtree)
+    }
+
+    /// Same as matching_leaves(), but for a Merkle Tree created using new_with() with the hash
+    /// function D.
+    pub fn matching_leaves_with<D : TreeHasher, T : AsRef<[u8]> + Clone>(&self, mtree: &MerkleTree<T>) -> Vec<(T, MerkleProof)> {
+        mtree.get_currently_stored_data().into_iter()
+            .filter(|element| self.contains_hash(&D::hash_leaf(element.as_ref())))
+            .filter_map(|element| {
+                let proof = mtree.generate_proof_with::<D>(&element)?;
+                Some((element, proof))
+            })
+            .collect()
+    }
+
+    /// Returns the indices of the bits of the given hash.
+    ///
+    /// As the hash already is a SHA-256 hash, the indices are derived from two parts of it
+    /// instead of hashing it another `hash_count` times ("double hashing").
+    fn bit_indices(&self, hash: &ShaHash) -> impl Iterator<Item = usize> {
+        let mut first = [0u8; 8];
+        let mut second = [0u8; 8];
+        first.copy_from_slice(&hash.as_bytes()[..8]);
+        second.copy_from_slice(&hash.as_bytes()[8..16]);
+        let first = u64::from_be_bytes(first);
+        let second = u64::from_be_bytes(second);
+        let bit_count = (self.bits.len() * 8) as u64;
+        (0..self.hash_count as u64)
+            .map(move |i| (first.wrapping_add(i.wrapping_mul(second)) % bit_count) as usize)
+    }
+}
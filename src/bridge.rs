@@ -0,0 +1,54 @@
+use crate::block::{Block, BlockHeader};
+use crate::chain_params::ChainParams;
+use crate::merkle_tree::MerkleTree;
+use crate::ShaHash;
+
+// Header relay: A Blockchain can follow another ("foreign") Blockchain by storing the headers
+// of the foreign Blocks as its own data. Anyone holding this Blockchain can then check that the
+// foreign Blockchain was continued correctly without holding any of the foreign data.
+
+/// Collects the headers of the given Blocks of a foreign Blockchain into a MerkleTree, so that
+/// they can be appended to this Blockchain like any other data (e.g. using append_data()).
+///
+/// An empty slice results in the empty Merkle Tree (see MerkleTree::empty()).
+pub fn relay_headers<U : AsRef<[u8]> + Clone>(foreign_blocks: &[Block<U>]) -> MerkleTree<BlockHeader> {
+    let headers: Vec<BlockHeader> = foreign_blocks.iter()
+        .map(|block| block.header())
+        .collect();
+    MerkleTree::new(&headers)
+}
+
+/// Checks whether the given headers form a valid and continuous part of a foreign Blockchain
+/// that continues right after the Block with the hash `prev_hash`:
+/// - every header has a correct nonce (see BlockHeader::verify_nonce())
+/// - every header's prev_hash is the hash of the header that comes directly before it
+///   (or `prev_hash` for the first header)
+///
+/// Use the initial hash of the foreign Blockchain (see ChainParams::initial_hash) as `prev_hash`
+/// when the headers start with its very first Block.
+pub fn verify_header_chain(prev_hash: ShaHash, headers: &[BlockHeader]) -> bool {
+    verify_header_chain_with(&ChainParams::default(), prev_hash, headers)
+}
+
+/// Same as verify_header_chain(), but for a foreign Blockchain with the given ChainParams.
+pub fn verify_header_chain_with(params: &ChainParams, prev_hash: ShaHash, headers: &[BlockHeader]) -> bool {
+    let mut previous_hash = prev_hash;
+    for header in headers {
+        if header.prev_hash() != previous_hash || !header.verify_nonce_with(params.pow) {
+            // Discontinuity or invalid foreign Block found!
+            return false;
+        }
+        previous_hash = header.calculate_hash();
+    }
+    true
+}
+
+/// Checks whether the given MerkleTree of relayed headers (see relay_headers()) is valid and
+/// whether the headers continue the foreign Blockchain right after the Block with the hash
+/// `prev_hash` (see verify_header_chain()).
+///
+/// Only the headers currently stored in the MerkleTree are checked. When headers were
+/// forgotten, false is returned, unless only the last ones are missing.
+pub fn verify_relayed_headers(prev_hash: ShaHash, mtree: &MerkleTree<BlockHeader>) -> bool {
+    mtree.verify() && verify_header_chain(prev_hash, &mtree.get_currently_stored_data())
+}
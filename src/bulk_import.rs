@@ -0,0 +1,140 @@
+use crate::block::Block;
+use crate::blockchain::{Blockchain, BlockchainError, prevalidate};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::{Arc, Condvar, Mutex};
+use std::sync::mpsc::sync_channel;
+use std::thread;
+
+/// How Blockchain::bulk_import() runs.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ImportOptions {
+    /// The number of worker threads decoding and pre-validating Blocks.
+    pub workers : usize,
+    /// The number of items (and of decoded Blocks) that may be waiting in each queue, which is
+    /// also how far reading the input may get ahead of appending (e.g. while a worker is slow
+    /// on an item, the Blocks of the items after it have to wait).
+    pub queue_size : usize
+}
+
+impl Default for ImportOptions {
+    /// One worker per available CPU core and queues of 64 items.
+    fn default() -> Self {
+        ImportOptions {
+            workers: thread::available_parallelism().map_or(1, |cores| cores.get()),
+            queue_size: 64
+        }
+    }
+}
+
+/// The reason why an item could not be imported by Blockchain::bulk_import().
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ImportError {
+    /// The item could not be decoded into a Block.
+    Undecodable,
+    /// The Block is invalid (or doesn't come right after the Block before it).
+    Invalid(BlockchainError)
+}
+
+impl fmt::Display for ImportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ImportError::Undecodable => write!(f, "item could not be decoded"),
+            ImportError::Invalid(error) => write!(f, "invalid Block: {}", error)
+        }
+    }
+}
+
+impl std::error::Error for ImportError {}
+
+/// What Blockchain::bulk_import() did.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ImportReport {
+    /// The number of Blocks appended.
+    pub appended : usize,
+    /// The index of the item the import stopped at together with the reason why, or None when
+    /// all items were imported.
+    pub failed : Option<(usize, ImportError)>
+}
+
+impl<T : AsRef<[u8]> + Clone + Send + Sync> Blockchain<T> {
+
+    /// Imports many Blocks at once, e.g. for the initial sync or from a snapshot: the given items
+    /// (e.g. serialized Blocks read from a file) are decoded using `decode` and pre-validated
+    /// (their nonces and Merkle Trees checked, which is the expensive part) by worker threads,
+    /// while the calling thread appends the Blocks in the order of the items. The queues in
+    /// between are bounded and reading never gets more than queue_size items ahead of appending
+    /// (see ImportOptions), so the items are read only as fast as they are imported.
+    ///
+    /// Stops at the first item that can't be decoded or appended, keeping the Blocks appended
+    /// before it. Apart from being faster, this is the same as calling append_block() for each
+    /// decoded Block.
+    pub fn bulk_import<I, J, F>(&mut self, items : J, decode : F, options : &ImportOptions) -> ImportReport
+        where I : Send, J : IntoIterator<Item = I>, J::IntoIter : Send, F : Fn(I) -> Option<Block<T>> + Sync {
+        let params = *self.get_params();
+        let (item_sender, item_receiver) = sync_channel::<(usize, I)>(options.queue_size);
+        let (block_sender, block_receiver) = sync_channel::<(usize, Result<Block<T>, ImportError>)>(options.queue_size);
+        // The workers take turns receiving the next item. Only they hold the receiver, so that it
+        // is dropped (and reading the items stops) once all of them stopped
+        let item_receiver = Arc::new(Mutex::new(item_receiver));
+        // The number of Blocks appended so far (usize::MAX once the import stopped), so that the
+        // Blocks waiting for the ones before them can't pile up
+        let progress = (Mutex::new(0usize), Condvar::new());
+        let window = options.queue_size.max(1);
+        let mut report = ImportReport::default();
+
+        thread::scope(|scope| {
+            let items = items.into_iter();
+            let progress = &progress;
+            scope.spawn(move || {
+                for (index, item) in items.enumerate() {
+                    let (appended, advanced) = progress;
+                    drop(advanced.wait_while(appended.lock().unwrap(), |appended| index >= appended.saturating_add(window)).unwrap());
+                    // Sending fails once the import stopped
+                    if item_sender.send((index, item)).is_err() {
+                        break;
+                    }
+                }
+            });
+            for _ in 0..options.workers.max(1) {
+                let (item_receiver, block_sender, decode) = (Arc::clone(&item_receiver), block_sender.clone(), &decode);
+                scope.spawn(move || loop {
+                    let next = item_receiver.lock().unwrap().recv();
+                    let (index, item) = match next {
+                        Ok(next) => next,
+                        Err(_) => break
+                    };
+                    let block = decode(item).ok_or(ImportError::Undecodable)
+                        .and_then(|block| prevalidate(&params, &block).map(|()| block).map_err(ImportError::Invalid));
+                    if block_sender.send((index, block)).is_err() {
+                        break;
+                    }
+                });
+            }
+            drop(item_receiver);
+            drop(block_sender);
+            // Returning drops the receiver, which makes the workers stop
+            let block_receiver = block_receiver;
+
+            // The Blocks arrive in any order, so the ones that come too early have to wait
+            let mut waiting = BTreeMap::new();
+            let (appended, advanced) = progress;
+            'import: for (index, block) in block_receiver.iter() {
+                waiting.insert(index, block);
+                while let Some(block) = waiting.remove(&report.appended) {
+                    let result = block.and_then(|block| self.append_checked(block, true).map_err(ImportError::Invalid));
+                    if let Err(error) = result {
+                        report.failed = Some((report.appended, error));
+                        break 'import;
+                    }
+                    report.appended += 1;
+                    *appended.lock().unwrap() = report.appended;
+                    advanced.notify_all();
+                }
+            }
+            *appended.lock().unwrap() = usize::MAX;
+            advanced.notify_all();
+        });
+        report
+    }
+}
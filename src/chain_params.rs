@@ -0,0 +1,215 @@
+use sha2::Sha256;
+use sha2::Digest;
+use crate::block::INITIAL_HASH;
+use crate::pow::{PowFunction, Sha256Pow};
+use crate::merkle_tree::{MerkleTree, MerkleProof, DomainSeparated, LeafProvider};
+use crate::ShaHash;
+
+/// The parameters of a Blockchain, i.e. everything that all participants of a network have to
+/// agree on for their copies of the Blockchain to be compatible.
+#[derive(Clone, Copy, Debug)]
+pub struct ChainParams {
+    /// The hash function used for the proof of work, see PowFunction.
+    pub pow: &'static dyn PowFunction,
+    /// The hash that's stored as the 'previous hash' (prev_hash) for the very first Block.
+    ///
+    /// Blockchains with different initial hashes can never accept each other's Blocks, so every
+    /// network should have an initial hash of its own (see for_genesis_document()).
+    pub initial_hash: ShaHash,
+    /// How much work validating a single Block may take at most, see BlockLimits.
+    pub limits: BlockLimits,
+    /// How the Merkle Trees of the Blocks are hashed, see TreeVersion.
+    pub tree_version: TreeVersion
+}
+
+impl ChainParams {
+
+    /// Creates the default ChainParams, but with the hash of the given document as the initial
+    /// hash. The document could be anything describing the network, e.g. its name, its purpose
+    /// and the date it was started on.
+    pub fn for_genesis_document(document: &[u8]) -> ChainParams {
+        ChainParams {
+            initial_hash: Sha256::digest(document).into(),
+            ..ChainParams::default()
+        }
+    }
+
+    /// The parameters for local testing (a "regression test" network): no BlockLimits, so tests
+    /// can append Blocks of any size, and an initial hash of its own, so that its Blocks can never
+    /// end up on a real network.
+    ///
+    /// The difficulty is the same on all networks (see ZEROS), which is low enough for Blocks to
+    /// be mined instantly.
+    pub fn regtest() -> ChainParams {
+        ChainParams {
+            tree_version: TreeVersion::V2,
+            ..ChainParams::for_genesis_document(b"rust-blockchain regtest")
+        }
+    }
+
+    /// The parameters of the public test network: the same settings as production() recommends,
+    /// with a well-known genesis document.
+    pub fn testnet() -> ChainParams {
+        ChainParams::production(b"rust-blockchain testnet")
+    }
+
+    /// The recommended parameters for a production network started with the given genesis
+    /// document (see for_genesis_document()): TreeVersion::V2 and BlockLimits::RECOMMENDED.
+    ///
+    /// This is a template: once a network is started, its ChainParams can never change, so it
+    /// should store them as they are rather than calling this function, whose defaults might
+    /// change in future versions.
+    pub fn production(genesis_document: &[u8]) -> ChainParams {
+        ChainParams {
+            limits: BlockLimits::RECOMMENDED,
+            tree_version: TreeVersion::V2,
+            ..ChainParams::for_genesis_document(genesis_document)
+        }
+    }
+}
+
+impl Default for ChainParams {
+    /// The parameters used by Blockchain::new(): SHA-256 as the proof of work function,
+    /// INITIAL_HASH as the initial hash, no BlockLimits and TreeVersion::V1.
+    fn default() -> Self {
+        ChainParams {
+            pow: &Sha256Pow,
+            initial_hash: INITIAL_HASH,
+            limits: BlockLimits::UNLIMITED,
+            tree_version: TreeVersion::V1
+        }
+    }
+}
+
+/// How the leaves and nodes of the Merkle Trees of a Blockchain are hashed (see TreeHasher).
+///
+/// Blocks are validated using the TreeVersion of the ChainParams, so the Merkle Trees appended
+/// to a Blockchain have to be created using its new_tree().
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TreeVersion {
+    /// SHA-256 without domain separation, i.e. MerkleTree::new(). The default, as it is what all
+    /// Blockchains created before TreeVersion existed use.
+    #[default]
+    V1,
+    /// SHA-256 with different prefixes for leaves and nodes, i.e. DomainSeparated<Sha256>.
+    /// Recommended for new Blockchains.
+    V2
+}
+
+impl TreeVersion {
+
+    /// Creates a Merkle Tree of this version, see MerkleTree::new().
+    ///
+    /// An empty Vec results in the empty Merkle Tree (see MerkleTree::empty()).
+    pub fn new_tree<T : AsRef<[u8]> + Clone>(self, data: &[T]) -> MerkleTree<T> {
+        match self {
+            TreeVersion::V1 => MerkleTree::new_with::<Sha256>(data),
+            TreeVersion::V2 => MerkleTree::new_with::<DomainSeparated<Sha256>>(data)
+        }
+    }
+
+    /// Checks whether all the hashes of the given Merkle Tree of this version are correct, see
+    /// MerkleTree::verify().
+    pub fn verify<T : AsRef<[u8]> + Clone>(self, mtree: &MerkleTree<T>) -> bool {
+        match self {
+            TreeVersion::V1 => mtree.verify_with::<Sha256>(),
+            TreeVersion::V2 => mtree.verify_with::<DomainSeparated<Sha256>>()
+        }
+    }
+
+    /// Checks whether the given Merkle Tree of this version is valid and has the given root hash,
+    /// see MerkleTree::verify_against().
+    pub fn verify_against<T : AsRef<[u8]> + Clone>(self, mtree: &MerkleTree<T>, expected_root: &ShaHash) -> bool {
+        match self {
+            TreeVersion::V1 => mtree.verify_against_with::<Sha256>(expected_root),
+            TreeVersion::V2 => mtree.verify_against_with::<DomainSeparated<Sha256>>(expected_root)
+        }
+    }
+
+    /// Generates a proof that the given element is part of the given Merkle Tree of this version,
+    /// see MerkleTree::generate_proof().
+    pub fn generate_proof<T : AsRef<[u8]> + Clone>(self, mtree: &MerkleTree<T>, element: &T) -> Option<MerkleProof> {
+        match self {
+            TreeVersion::V1 => mtree.generate_proof_with::<Sha256>(element),
+            TreeVersion::V2 => mtree.generate_proof_with::<DomainSeparated<Sha256>>(element)
+        }
+    }
+
+    /// Forgets the data of the leaves of the given Merkle Tree of this version that doesn't match
+    /// their hashes, see MerkleTree::forget_corrupted().
+    pub fn forget_corrupted<T : AsRef<[u8]> + Clone>(self, mtree: &mut MerkleTree<T>) -> Vec<ShaHash> {
+        match self {
+            TreeVersion::V1 => mtree.forget_corrupted_with::<Sha256>(),
+            TreeVersion::V2 => mtree.forget_corrupted_with::<DomainSeparated<Sha256>>()
+        }
+    }
+
+    /// Restores the data of a forgotten leaf of the given Merkle Tree of this version, see
+    /// MerkleTree::restore_element().
+    pub fn restore_element<T : AsRef<[u8]> + Clone>(self, mtree: &mut MerkleTree<T>, element: T) -> bool {
+        match self {
+            TreeVersion::V1 => mtree.restore_element_with::<Sha256>(element),
+            TreeVersion::V2 => mtree.restore_element_with::<DomainSeparated<Sha256>>(element)
+        }
+    }
+
+    /// Restores the forgotten leaves of the given Merkle Tree of this version from the given
+    /// LeafProvider, see MerkleTree::restore_all().
+    pub fn restore_all<T : AsRef<[u8]> + Clone, P : LeafProvider<T>>(self, mtree: &mut MerkleTree<T>, provider: &P) -> usize {
+        match self {
+            TreeVersion::V1 => mtree.restore_all_with::<Sha256, P>(provider),
+            TreeVersion::V2 => mtree.restore_all_with::<DomainSeparated<Sha256>, P>(provider)
+        }
+    }
+
+    /// Checks whether the given proof shows that the given element is part of the Merkle Tree of
+    /// this version with the given root hash, see MerkleProof::verify().
+    pub fn verify_proof<T : AsRef<[u8]>>(self, proof: &MerkleProof, root_hash: ShaHash, element: &T) -> bool {
+        match self {
+            TreeVersion::V1 => proof.verify_with::<Sha256, T>(root_hash, element),
+            TreeVersion::V2 => proof.verify_with::<DomainSeparated<Sha256>, T>(root_hash, element)
+        }
+    }
+}
+
+/// Limits on the Merkle Tree of a single Block, so that nobody can craft a Block that makes
+/// everyone validating it do an unbounded amount of work (validating a Merkle Tree means hashing
+/// all of its leaves and nodes). Blocks exceeding them are rejected.
+///
+/// Forgetting data only ever decreases both numbers, so pruning never makes a Block exceed them.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BlockLimits {
+    /// The maximum number of leaves, no matter whether their data is stored or not
+    /// (a forgotten subtree counting as a single leaf).
+    pub max_leaves: usize,
+    /// The maximum total size of the stored data of the leaves in bytes.
+    pub max_bytes: usize
+}
+
+impl BlockLimits {
+    /// No limits at all, the default.
+    pub const UNLIMITED : BlockLimits = BlockLimits {
+        max_leaves: usize::MAX,
+        max_bytes: usize::MAX
+    };
+
+    /// The limits used by ChainParams::production() and ChainParams::testnet():
+    /// 65536 leaves and 16 MiB of data.
+    pub const RECOMMENDED : BlockLimits = BlockLimits {
+        max_leaves: 1 << 16,
+        max_bytes: 16 << 20
+    };
+
+    /// Checks whether the given Merkle Tree stays within these limits.
+    pub fn allow<T : AsRef<[u8]> + Clone>(&self, mtree: &MerkleTree<T>) -> bool {
+        let stats = mtree.stats();
+        stats.stored_leaf_count + stats.forgotten_leaf_count <= self.max_leaves
+            && stats.stored_bytes <= self.max_bytes
+    }
+}
+
+impl Default for BlockLimits {
+    fn default() -> Self {
+        BlockLimits::UNLIMITED
+    }
+}
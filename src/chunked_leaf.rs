@@ -0,0 +1,113 @@
+use sha2::Sha256;
+use sha2::Digest;
+use crate::merkle_tree::{MerkleTree, MerkleProof};
+use crate::ShaHash;
+
+/// A big piece of data that is split into chunks of a fixed size before being stored in a
+/// Merkle Tree.
+///
+/// The chunks are stored in a Merkle Tree of their own, and only the root hash of that tree
+/// ("chunk root") makes up the bytes of the leaf, i.e. the leaf hash of a ChunkedLeaf is the
+/// hash of its chunk root. Data that fits into a single chunk therefore ends up with a tree of a
+/// single chunk.
+///
+/// This allows retrieving and proving single chunks of large documents stored on a Blockchain:
+/// the MerkleProof of the ChunkedLeaf proves the chunk root, and chunk_proof() proves a chunk
+/// against the chunk root. Chunks can be forgotten and restored just like the leaves of any
+/// other Merkle Tree.
+#[derive(Clone, Debug)]
+pub struct ChunkedLeaf {
+    /// The root hash of `chunks`, i.e. the bytes of this leaf.
+    chunk_root: ShaHash,
+    /// The (possibly partially forgotten) chunks of the data.
+    chunks: MerkleTree<Vec<u8>>
+}
+
+impl ChunkedLeaf {
+
+    /// Splits the given data into chunks of `chunk_size` bytes (the last one possibly being
+    /// smaller).
+    ///
+    /// Important: this function panics when `chunk_size` is 0!
+    pub fn new(data: &[u8], chunk_size: usize) -> ChunkedLeaf {
+        assert!(chunk_size > 0, "The chunk size of a ChunkedLeaf must not be 0!");
+        let mut chunks : Vec<Vec<u8>> = data.chunks(chunk_size).map(<[u8]>::to_vec).collect();
+        if chunks.is_empty() {
+            chunks.push(Vec::new());
+        }
+        let chunks = MerkleTree::new(&chunks);
+        ChunkedLeaf {
+            chunk_root: chunks.get_root_hash(),
+            chunks
+        }
+    }
+
+    /// Creates a ChunkedLeaf of which nothing but the chunk root is known, e.g. for restoring a
+    /// forgotten leaf without retrieving all of its chunks. Chunks can be added afterwards using
+    /// restore_chunk().
+    pub fn from_chunk_root(chunk_root: ShaHash) -> ChunkedLeaf {
+        ChunkedLeaf {
+            chunk_root,
+            chunks: MerkleTree::Leaf { hash: chunk_root, data: None }
+        }
+    }
+
+    /// Returns the root hash of the Merkle Tree of the chunks.
+    pub fn chunk_root(&self) -> ShaHash {
+        self.chunk_root
+    }
+
+    /// Returns the Merkle Tree of the chunks.
+    pub fn get_chunks(&self) -> &MerkleTree<Vec<u8>> {
+        &self.chunks
+    }
+
+    /// Returns the data of this leaf, or None when some of its chunks are currently forgotten.
+    pub fn get_data(&self) -> Option<Vec<u8>> {
+        let leaf_count = self.chunks.get_leaf_hashes().len();
+        let chunks = self.chunks.get_currently_stored_data();
+        if chunks.len() == leaf_count {
+            Some(chunks.concat())
+        } else {
+            None
+        }
+    }
+
+    /// Generates a proof that the given chunk is part of this leaf, to be verified against
+    /// chunk_root(). Returns None when the chunk was not found.
+    pub fn chunk_proof(&self, chunk: &[u8]) -> Option<MerkleProof> {
+        self.chunks.generate_proof(&chunk.to_vec())
+    }
+
+    /// Forgets the given chunk, see MerkleTree::forget_subtree().
+    /// Returns false when the chunk was not found.
+    pub fn forget_chunk(&mut self, chunk: &[u8]) -> bool {
+        self.chunks.forget_subtree(Sha256::digest(chunk).into())
+    }
+
+    /// Tries to restore the given chunk, see MerkleTree::restore_element().
+    /// Returns false when the chunk was not found.
+    ///
+    /// For a ChunkedLeaf created using from_chunk_root(), the structure of the chunk tree has to be
+    /// restored first using restore_chunk_tree().
+    pub fn restore_chunk(&mut self, chunk: &[u8]) -> bool {
+        self.chunks.restore_element(chunk.to_vec())
+    }
+
+    /// Tries to insert the given (partial) Merkle Tree of chunks, see MerkleTree::insert_subtree().
+    /// Returns false when the given tree is invalid or does not belong to this leaf.
+    pub fn restore_chunk_tree(&mut self, chunks: MerkleTree<Vec<u8>>) -> bool {
+        chunks.verify() && self.chunks.insert_subtree(chunks)
+    }
+
+    /// Checks whether all the hashes of the chunk tree are correct.
+    pub fn verify(&self) -> bool {
+        self.chunks.verify_against(&self.chunk_root)
+    }
+}
+
+impl AsRef<[u8]> for ChunkedLeaf {
+    fn as_ref(&self) -> &[u8] {
+        self.chunk_root.as_ref()
+    }
+}
@@ -0,0 +1,275 @@
+use sha2::Sha256;
+use sha2::Digest;
+use std::convert::{TryFrom, TryInto};
+use crate::merkle_tree::{MerkleTree, MerkleProof, LeafCodec};
+use crate::ShaHash;
+#[cfg(feature = "serde")]
+use serde::{Serialize, Deserialize};
+
+// Data availability sampling: the data of a Merkle Tree is erasure-coded (Reed-Solomon over
+// GF(2^8)) into 2k shares, any k of which are enough to reconstruct it, and the shares are
+// committed to by the root hash of a Merkle Tree over them. To withhold the data, whoever stores
+// it has to withhold more than half of the shares, so a light client that downloads a few random
+// shares (see sample_indices()) and checks them against the commitment notices that with high
+// probability: with s samples, the chance of not noticing is below 2^-s.
+//
+// Please note that nothing checks that the shares are a correct encoding - a malicious encoder
+// could commit to shares that don't reconstruct the data of the Block at all. Check the
+// reconstructed leaves against the Merkle root of the Block (e.g. using
+// MerkleTree::verify_against()) before relying on them.
+
+/// The maximum number of data shares, as the 2k shares need distinct points in GF(2^8).
+pub const MAX_DATA_SHARES : usize = 128;
+
+/// One share of erasure-coded data: its index followed by its bytes.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(try_from = "RawShare"))]
+pub struct Share {
+    bytes: Vec<u8>
+}
+
+impl Share {
+
+    /// Parses the bytes of a share, e.g. received from others.
+    /// Returns None when they are empty, i.e. lack the index.
+    pub fn from_bytes(bytes: Vec<u8>) -> Option<Share> {
+        (!bytes.is_empty()).then_some(Share { bytes })
+    }
+
+    /// Returns the index of this share, the first k ones being the data shares.
+    pub fn index(&self) -> usize {
+        self.bytes[0] as usize
+    }
+
+    /// Returns the (coded) bytes of this share.
+    pub fn data(&self) -> &[u8] {
+        &self.bytes[1..]
+    }
+}
+
+impl AsRef<[u8]> for Share {
+    fn as_ref(&self) -> &[u8] {
+        &self.bytes
+    }
+}
+
+/// A Share as it is deserialized, before checking that it has an index (see Share::from_bytes()).
+#[cfg(feature = "serde")]
+#[derive(Deserialize)]
+struct RawShare {
+    bytes: Vec<u8>
+}
+
+#[cfg(feature = "serde")]
+impl TryFrom<RawShare> for Share {
+    type Error = &'static str;
+
+    fn try_from(raw: RawShare) -> Result<Share, Self::Error> {
+        Share::from_bytes(raw.bytes).ok_or("a share must start with its index")
+    }
+}
+
+/// Data erasure-coded into 2k shares together with the Merkle Tree committing to them.
+#[derive(Clone, Debug)]
+pub struct ErasureCoded {
+    shares: Vec<Share>,
+    tree: MerkleTree<Share>
+}
+
+impl ErasureCoded {
+
+    /// Erasure-codes the given data into the given number of data shares plus as many parity
+    /// shares. Returns None when the number of data shares is 0 or bigger than MAX_DATA_SHARES, or
+    /// when the data is 4 GiB or bigger.
+    pub fn encode(data: &[u8], data_shares: usize) -> Option<ErasureCoded> {
+        if data_shares == 0 || data_shares > MAX_DATA_SHARES {
+            return None;
+        }
+        // The length of the data comes first, so the padding can be removed again
+        let length = u32::try_from(data.len()).ok()?;
+        let mut payload = length.to_be_bytes().to_vec();
+        payload.extend_from_slice(data);
+        let share_size = payload.len().div_ceil(data_shares);
+        payload.resize(share_size * data_shares, 0);
+
+        let points : Vec<(u8, &[u8])> = payload.chunks(share_size).enumerate()
+            .map(|(index, chunk)| (index as u8, chunk))
+            .collect();
+        let shares : Vec<Share> = (0..2 * data_shares)
+            .map(|index| Share { bytes: interpolate(&points, index as u8, share_size) })
+            .collect();
+        let tree = MerkleTree::new(&shares);
+        Some(ErasureCoded { shares, tree })
+    }
+
+    /// Erasure-codes the data of all leaves of the given Merkle Tree (encoded using LeafCodec,
+    /// see encode()), to be reconstructed using reconstruct_leaves().
+    /// Returns None when some of its leaves are forgotten (or for an invalid number of shares or
+    /// too much data, including leaves of 4 GiB or more).
+    pub fn encode_leaves<T : AsRef<[u8]> + Clone + LeafCodec>(mtree: &MerkleTree<T>, data_shares: usize) -> Option<ErasureCoded> {
+        if mtree.forgotten_leaf_count() > 0 {
+            return None;
+        }
+        let mut data = Vec::new();
+        for leaf in mtree.get_currently_stored_data() {
+            let leaf = leaf.encode();
+            let length = u32::try_from(leaf.len()).ok()?;
+            data.extend_from_slice(&length.to_be_bytes());
+            data.extend_from_slice(&leaf);
+        }
+        ErasureCoded::encode(&data, data_shares)
+    }
+
+    /// Returns the commitment to the shares, i.e. the root hash of the Merkle Tree over them.
+    pub fn commitment(&self) -> ShaHash {
+        self.tree.get_root_hash()
+    }
+
+    /// Returns the number of shares (data and parity shares).
+    pub fn share_count(&self) -> usize {
+        self.shares.len()
+    }
+
+    /// Returns all shares, the data shares first.
+    pub fn shares(&self) -> &[Share] {
+        &self.shares
+    }
+
+    /// Returns the share with the given index together with a proof that it is part of the
+    /// commitment, to be checked using verify_sample(). Returns None for an index out of range.
+    pub fn sample(&self, index: usize) -> Option<(Share, MerkleProof)> {
+        let share = self.shares.get(index)?.clone();
+        let proof = self.tree.generate_proof(&share)?;
+        Some((share, proof))
+    }
+}
+
+/// Checks whether the given share is the one with the given index of the data with the given
+/// commitment, see ErasureCoded::sample().
+pub fn verify_sample(commitment: ShaHash, index: usize, share: &Share, proof: &MerkleProof) -> bool {
+    !share.bytes.is_empty() && share.index() == index && proof.verify(commitment, share)
+}
+
+/// Derives the indices of the shares to sample from the given seed, e.g. the hash of the Block
+/// combined with a secret of the light client, so that whoever answers the requests can't choose
+/// them. Returns `count` distinct indices below `share_count` (or all of them when `count` is
+/// bigger).
+pub fn sample_indices(seed: &ShaHash, count: usize, share_count: usize) -> Vec<usize> {
+    let mut indices = Vec::new();
+    let mut counter = 0u64;
+    while indices.len() < count.min(share_count) {
+        let hash = Sha256::new().chain(seed).chain(counter.to_be_bytes()).finalize();
+        let index = (u64::from_be_bytes(hash[..8].try_into().unwrap()) % share_count as u64) as usize;
+        if !indices.contains(&index) {
+            indices.push(index);
+        }
+        counter += 1;
+    }
+    indices
+}
+
+/// Reconstructs the data encoded using ErasureCoded::encode() with the given number of data
+/// shares from any of its shares with distinct indices, at least that many.
+/// Returns None when there are too few of them or they don't fit together.
+///
+/// The shares should have been checked using verify_sample() first.
+pub fn reconstruct(shares: &[Share], data_shares: usize) -> Option<Vec<u8>> {
+    if data_shares == 0 || data_shares > MAX_DATA_SHARES {
+        return None;
+    }
+    let mut points : Vec<(u8, &[u8])> = Vec::new();
+    for share in shares {
+        let index = *share.bytes.first()?;
+        if (index as usize) < 2 * data_shares && points.iter().all(|(other, _)| *other != index) {
+            points.push((index, share.data()));
+        }
+    }
+    points.truncate(data_shares);
+    let share_size = points.first()?.1.len();
+    if points.len() < data_shares || points.iter().any(|(_, data)| data.len() != share_size) {
+        return None;
+    }
+    let mut payload = Vec::with_capacity(share_size * data_shares);
+    for index in 0..data_shares {
+        payload.extend_from_slice(&interpolate(&points, index as u8, share_size)[1..]);
+    }
+    let length = u32::from_be_bytes(payload.get(..4)?.try_into().unwrap()) as usize;
+    payload.get(4..4 + length).map(<[u8]>::to_vec)
+}
+
+/// Reconstructs the leaves encoded using ErasureCoded::encode_leaves(), see reconstruct().
+/// Returns None when the data can't be reconstructed or decoded (see LeafCodec).
+pub fn reconstruct_leaves<T : LeafCodec>(shares: &[Share], data_shares: usize) -> Option<Vec<T>> {
+    let data = reconstruct(shares, data_shares)?;
+    let mut rest = &data[..];
+    let mut leaves = Vec::new();
+    while !rest.is_empty() {
+        let length = u32::from_be_bytes(rest.get(..4)?.try_into().unwrap()) as usize;
+        let leaf = rest.get(4..4 + length)?;
+        leaves.push(T::decode(leaf)?);
+        rest = &rest[4 + length..];
+    }
+    Some(leaves)
+}
+
+/// Evaluates the polynomials through the given points (index and bytes, one polynomial per byte
+/// position) at the given index, using Lagrange interpolation. Returns the bytes of the share
+/// with that index (including the index itself).
+fn interpolate(points: &[(u8, &[u8])], index: u8, share_size: usize) -> Vec<u8> {
+    let mut bytes = vec![0u8; share_size + 1];
+    bytes[0] = index;
+    for (j, (x_j, y_j)) in points.iter().enumerate() {
+        // The Lagrange basis polynomial of point j at the index (subtraction is XOR in GF(2^8))
+        let coefficient = points.iter().enumerate()
+            .filter(|(m, _)| *m != j)
+            .fold(1, |product, (_, (x_m, _))| gf_mul(product, gf_div(index ^ x_m, x_j ^ x_m)));
+        for (byte, y) in bytes[1..].iter_mut().zip(y_j.iter()) {
+            *byte ^= gf_mul(coefficient, *y);
+        }
+    }
+    bytes
+}
+
+/// The powers of the generator 2 of GF(2^8) (twice, so products don't need a modulo) and their
+/// logarithms, using the polynomial x^8 + x^4 + x^3 + x^2 + 1.
+const GF_TABLES : ([u8; 512], [u8; 256]) = gf_tables();
+
+const fn gf_tables() -> ([u8; 512], [u8; 256]) {
+    let mut exp = [0u8; 512];
+    let mut log = [0u8; 256];
+    let mut x : u16 = 1;
+    let mut i = 0;
+    while i < 255 {
+        exp[i] = x as u8;
+        log[x as usize] = i as u8;
+        x <<= 1;
+        if x & 0x100 != 0 {
+            x ^= 0x11d;
+        }
+        i += 1;
+    }
+    while i < 512 {
+        exp[i] = exp[i - 255];
+        i += 1;
+    }
+    (exp, log)
+}
+
+fn gf_mul(a: u8, b: u8) -> u8 {
+    let (exp, log) = &GF_TABLES;
+    if a == 0 || b == 0 {
+        0
+    } else {
+        exp[log[a as usize] as usize + log[b as usize] as usize]
+    }
+}
+
+/// Divides a by b, which must not be 0.
+fn gf_div(a: u8, b: u8) -> u8 {
+    let (exp, log) = &GF_TABLES;
+    if a == 0 {
+        0
+    } else {
+        exp[log[a as usize] as usize + 255 - log[b as usize] as usize]
+    }
+}
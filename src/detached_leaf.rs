@@ -0,0 +1,77 @@
+use sha2::Sha256;
+use sha2::Digest;
+use std::collections::HashMap;
+use crate::ShaHash;
+
+/// A content-addressed store holding the data of DetachedLeafs, i.e. data is stored and looked up
+/// by its SHA-256 hash (its content identifier or "CID").
+///
+/// Implement this trait for the storage of your choice (e.g. a directory, a database or IPFS).
+/// The store does not have to be trusted: DetachedLeaf::fetch() checks everything it returns.
+pub trait ContentStore {
+    /// Stores the given content under the given CID (the SHA-256 hash of the content).
+    fn put(&mut self, cid: ShaHash, content: Vec<u8>);
+
+    /// Returns the content stored under the given CID, or None when it is not available.
+    fn get(&self, cid: &ShaHash) -> Option<Vec<u8>>;
+}
+
+/// A ContentStore keeping everything in memory.
+#[derive(Clone, Debug, Default)]
+pub struct MemoryStore {
+    contents: HashMap<ShaHash, Vec<u8>>
+}
+
+impl ContentStore for MemoryStore {
+    fn put(&mut self, cid: ShaHash, content: Vec<u8>) {
+        self.contents.insert(cid, content);
+    }
+
+    fn get(&self, cid: &ShaHash) -> Option<Vec<u8>> {
+        self.contents.get(cid).cloned()
+    }
+}
+
+/// Data that lives in a ContentStore, of which only the CID is stored in a Merkle Tree.
+///
+/// The CID makes up the bytes of the leaf, so Blocks stay small no matter how big the data is,
+/// while the data remains verifiable: fetch() only returns data matching the CID.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DetachedLeaf {
+    /// The SHA-256 hash of the data.
+    cid: ShaHash
+}
+
+impl DetachedLeaf {
+
+    /// Puts the given content into the given ContentStore and returns the leaf referencing it.
+    pub fn store<S : ContentStore>(content: &[u8], store: &mut S) -> DetachedLeaf {
+        let leaf = DetachedLeaf { cid: Sha256::digest(content).into() };
+        store.put(leaf.cid, content.to_vec());
+        leaf
+    }
+
+    /// References content that was stored elsewhere (or by someone else) under the given CID.
+    pub fn from_cid(cid: ShaHash) -> DetachedLeaf {
+        DetachedLeaf { cid }
+    }
+
+    /// Returns the CID of the referenced content, i.e. its SHA-256 hash.
+    pub fn cid(&self) -> ShaHash {
+        self.cid
+    }
+
+    /// Fetches the referenced content from the given ContentStore.
+    /// Returns None when the store does not have it or returned something that does not match
+    /// the CID.
+    pub fn fetch<S : ContentStore>(&self, store: &S) -> Option<Vec<u8>> {
+        store.get(&self.cid)
+            .filter(|content| ShaHash::from(Sha256::digest(content)) == self.cid)
+    }
+}
+
+impl AsRef<[u8]> for DetachedLeaf {
+    fn as_ref(&self) -> &[u8] {
+        self.cid.as_ref()
+    }
+}
@@ -0,0 +1,72 @@
+use sha2::Sha256;
+use std::marker::PhantomData;
+use crate::merkle_tree::TreeHasher;
+use crate::ShaHash;
+
+/// An encryption scheme for the data stored in a Blockchain (see EncryptedLeaf).
+///
+/// This library does not come with any encryption itself, implement this trait using the
+/// encryption library of your choice.
+pub trait Cipher {
+    /// Encrypts the given plaintext.
+    fn encrypt(&self, plaintext: &[u8]) -> Vec<u8>;
+
+    /// Decrypts the given ciphertext.
+    /// Returns None when the ciphertext could not be decrypted (e.g. wrong key).
+    fn decrypt(&self, ciphertext: &[u8]) -> Option<Vec<u8>>;
+}
+
+/// A value of type T that is encrypted before being stored in a Merkle Tree.
+///
+/// Only the ciphertext is ever stored and hashed, i.e. a MerkleTree<EncryptedLeaf<T>> and all
+/// its proofs can be verified by anyone, but only those holding the key can read the data.
+/// Forgotten leaves are restored using the ciphertext as well.
+#[derive(Clone, Debug, PartialEq)]
+pub struct EncryptedLeaf<T> {
+    /// The encrypted bytes of the value.
+    ciphertext: Vec<u8>,
+    /// The type of the value before encryption.
+    plaintext_type: PhantomData<T>
+}
+
+impl<T : AsRef<[u8]>> EncryptedLeaf<T> {
+
+    /// Encrypts the given value using the given Cipher.
+    pub fn encrypt<C : Cipher>(value: &T, cipher: &C) -> EncryptedLeaf<T> {
+        EncryptedLeaf::from_ciphertext(cipher.encrypt(value.as_ref()))
+    }
+}
+
+impl<T> EncryptedLeaf<T> {
+
+    /// Wraps an already encrypted value, e.g. one received from someone else.
+    pub fn from_ciphertext(ciphertext: Vec<u8>) -> EncryptedLeaf<T> {
+        EncryptedLeaf {
+            ciphertext,
+            plaintext_type: PhantomData
+        }
+    }
+
+    /// Decrypts this value using the given Cipher.
+    /// Returns None when the Cipher could not decrypt it.
+    pub fn decrypt<C : Cipher>(&self, cipher: &C) -> Option<T> where T : From<Vec<u8>> {
+        cipher.decrypt(&self.ciphertext).map(T::from)
+    }
+
+    /// Returns the hash of the ciphertext, i.e. the hash of the leaf storing this value in a
+    /// MerkleTree created using MerkleTree::new() (see hash_with()).
+    pub fn hash(&self) -> ShaHash {
+        self.hash_with::<Sha256>()
+    }
+
+    /// Same as hash(), but for a MerkleTree created using new_with() with the hash function D.
+    pub fn hash_with<D : TreeHasher>(&self) -> ShaHash {
+        D::hash_leaf(&self.ciphertext)
+    }
+}
+
+impl<T> AsRef<[u8]> for EncryptedLeaf<T> {
+    fn as_ref(&self) -> &[u8] {
+        &self.ciphertext
+    }
+}
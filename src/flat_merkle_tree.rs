@@ -0,0 +1,130 @@
+use sha2::Sha256;
+use crate::merkle_tree::{MerkleTree, TreeHasher, hash_leaf, hash_node};
+use crate::ShaHash;
+
+/// A Merkle Tree stored as a flat list of nodes, each node referring to its children by their
+/// indices instead of Box pointers.
+///
+/// The nodes are stored in post-order (the children of a node come right before it, the root
+/// comes last), which keeps them close together in memory and allows processing them with a
+/// simple loop instead of recursion - so verify() can't overflow the stack no matter how deep
+/// the tree is. Convert from and to the MerkleTree enum using From.
+#[derive(Clone, Debug)]
+pub struct FlatMerkleTree<T : AsRef<[u8]> + Clone> {
+    /// All nodes and leaves in post-order, i.e. children always come before their parent.
+    nodes: Vec<FlatNode<T>>
+}
+
+/// A node or leaf of a FlatMerkleTree.
+#[derive(Clone, Debug)]
+enum FlatNode<T> {
+    Node {
+        hash: ShaHash,
+        /// The index of the left child (smaller than the index of this node).
+        left: usize,
+        /// The index of the right child (smaller than the index of this node).
+        right: usize
+    },
+    Leaf {
+        hash: ShaHash,
+        data: Option<T>
+    }
+}
+
+impl<T> FlatNode<T> {
+    fn hash(&self) -> ShaHash {
+        match self {
+            FlatNode::Node{hash, ..} | FlatNode::Leaf{hash, ..} => *hash
+        }
+    }
+}
+
+impl<T : AsRef<[u8]> + Clone> FlatMerkleTree<T> {
+
+    /// Returns the root hash of this Merkle Tree.
+    pub fn get_root_hash(&self) -> ShaHash {
+        self.nodes.last().expect("A FlatMerkleTree is never empty").hash()
+    }
+
+    /// Returns the number of nodes of this Merkle Tree, including the leaves.
+    pub fn node_count(&self) -> usize {
+        self.nodes.len()
+    }
+
+    /// Returns all the data that is currently stored in this Merkle Tree, from left to right,
+    /// see MerkleTree::get_currently_stored_data().
+    pub fn get_currently_stored_data(&self) -> Vec<T> {
+        self.nodes.iter()
+            .filter_map(|node| match node {
+                FlatNode::Leaf{data: Some(data), ..} => Some(data.clone()),
+                _ => None
+            })
+            .collect()
+    }
+
+    /// Checks whether all the hashes of this Merkle Tree are correct, see MerkleTree::verify().
+    pub fn verify(&self) -> bool {
+        self.verify_with::<Sha256>()
+    }
+
+    /// Same as verify(), but for a Merkle Tree using D (see MerkleTree::new_with()).
+    pub fn verify_with<D : TreeHasher>(&self) -> bool {
+        // Every node is checked against the hashes of its children, which are checked themselves
+        self.nodes.iter().all(|node| match node {
+            FlatNode::Node{hash, left, right} =>
+                hash_node::<D>(self.nodes[*left].hash(), self.nodes[*right].hash()) == *hash,
+            FlatNode::Leaf{hash, data: Some(data)} => hash_leaf::<D>(data.as_ref()) == *hash,
+            FlatNode::Leaf{data: None, ..} => true
+        })
+    }
+}
+
+impl<T : AsRef<[u8]> + Clone> From<&MerkleTree<T>> for FlatMerkleTree<T> {
+    fn from(mtree: &MerkleTree<T>) -> FlatMerkleTree<T> {
+        let mut nodes = Vec::new();
+        // The indices of the subtrees that were completely added to `nodes` but whose parent was not
+        let mut finished = Vec::new();
+        // The subtrees still to be added, together with whether their children were added already
+        let mut pending = vec![(mtree, false)];
+        while let Some((subtree, children_added)) = pending.pop() {
+            match subtree {
+                MerkleTree::Leaf{hash, data} => {
+                    nodes.push(FlatNode::Leaf { hash: *hash, data: data.clone() });
+                },
+                MerkleTree::Node{left, right, ..} if !children_added => {
+                    pending.push((subtree, true));
+                    pending.push((right, false));
+                    pending.push((left, false));
+                    continue;
+                },
+                MerkleTree::Node{hash, ..} => {
+                    let right = finished.pop().unwrap();
+                    let left = finished.pop().unwrap();
+                    nodes.push(FlatNode::Node { hash: *hash, left, right });
+                }
+            }
+            finished.push(nodes.len() - 1);
+        }
+        FlatMerkleTree { nodes }
+    }
+}
+
+impl<T : AsRef<[u8]> + Clone> From<FlatMerkleTree<T>> for MerkleTree<T> {
+    fn from(flat: FlatMerkleTree<T>) -> MerkleTree<T> {
+        // As the children come right before their parent, the last two finished subtrees are
+        // always the children of the next node
+        let mut finished : Vec<MerkleTree<T>> = Vec::new();
+        for node in flat.nodes {
+            let subtree = match node {
+                FlatNode::Leaf{hash, data} => MerkleTree::Leaf { hash, data },
+                FlatNode::Node{hash, ..} => {
+                    let right = finished.pop().unwrap();
+                    let left = finished.pop().unwrap();
+                    MerkleTree::Node { hash, left: Box::new(left), right: Box::new(right) }
+                }
+            };
+            finished.push(subtree);
+        }
+        finished.pop().expect("A FlatMerkleTree is never empty")
+    }
+}
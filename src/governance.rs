@@ -0,0 +1,96 @@
+use crate::chain_params::{ChainParams, BlockLimits};
+use std::convert::TryInto;
+use std::fmt::Debug;
+
+/// The prefix of the data of every governance leaf, see ParamUpdate::to_leaf().
+pub const GOVERNANCE_PREFIX : &[u8] = b"rust-blockchain governance\0";
+
+/// The activation delay of a Governance that doesn't choose one of its own, in Blocks.
+pub const DEFAULT_ACTIVATION_DELAY : usize = 10;
+
+/// A change of the ChainParams of a Blockchain, proposed by a governance leaf.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ParamUpdate {
+    /// Replaces the BlockLimits, e.g. to allow bigger Blocks.
+    Limits(BlockLimits)
+}
+
+impl ParamUpdate {
+
+    /// Applies this update to the given ChainParams.
+    pub fn apply(&self, params : &mut ChainParams) {
+        match self {
+            ParamUpdate::Limits(limits) => params.limits = *limits
+        }
+    }
+
+    /// Returns the data of a governance leaf proposing this update: GOVERNANCE_PREFIX, the given
+    /// sequence number (u64 big endian), the update and then the given authorization (e.g. a
+    /// signature of the authority over the rest), which is checked by Governance::is_authorized().
+    ///
+    /// - Limits: `0 <max_leaves (u64 big endian)> <max_bytes (u64 big endian)>`
+    ///
+    /// The sequence number has to be greater than the one of every update confirmed before,
+    /// otherwise the update is ignored. This prevents replaying an old governance leaf to revert
+    /// later updates.
+    pub fn to_leaf(&self, sequence : u64, authorization : &[u8]) -> Vec<u8> {
+        let mut bytes = GOVERNANCE_PREFIX.to_vec();
+        bytes.extend_from_slice(&sequence.to_be_bytes());
+        match self {
+            ParamUpdate::Limits(limits) => {
+                bytes.push(0);
+                bytes.extend_from_slice(&(limits.max_leaves as u64).to_be_bytes());
+                bytes.extend_from_slice(&(limits.max_bytes as u64).to_be_bytes());
+            }
+        }
+        bytes.extend_from_slice(authorization);
+        bytes
+    }
+
+    /// Parses the data of a governance leaf written by to_leaf(), returning its sequence number
+    /// and update together with the authorization. Returns None when the data is not a
+    /// governance leaf.
+    pub fn from_leaf(bytes : &[u8]) -> Option<(u64, ParamUpdate, &[u8])> {
+        let read = |bytes : &[u8]| u64::from_be_bytes(bytes.try_into().unwrap());
+        let rest = bytes.strip_prefix(GOVERNANCE_PREFIX)?;
+        let sequence = read(rest.get(..8)?);
+        let (tag, rest) = rest[8..].split_first()?;
+        match tag {
+            0 => {
+                let limits = BlockLimits {
+                    // Limits beyond what this machine can address are unlimited for it anyway
+                    max_leaves: read(rest.get(..8)?).try_into().unwrap_or(usize::MAX),
+                    max_bytes: read(rest.get(8..16)?).try_into().unwrap_or(usize::MAX)
+                };
+                Some((sequence, ParamUpdate::Limits(limits), &rest[16..]))
+            },
+            _ => None
+        }
+    }
+}
+
+/// The rule deciding which governance leaves of a Blockchain may change its ChainParams, see
+/// Blockchain::set_governance(). Just like the ChainParams, all participants of a network have to
+/// use the same rule.
+///
+/// Once the Block containing an authorized governance leaf is appended, its update takes effect
+/// activation_delay() Blocks later, giving everyone time to prepare for it (e.g. to update their
+/// software when the limits change).
+pub trait Governance<T : AsRef<[u8]>> : Debug + Send + Sync {
+    /// Checks whether the given update with the given sequence number was authorized by the
+    /// authority of the network, e.g. whether the authorization is a valid signature of one of its
+    /// keys over both of them.
+    fn is_authorized(&self, sequence : u64, update : &ParamUpdate, authorization : &[u8]) -> bool;
+
+    /// The number of Blocks after the Block containing an update until it takes effect.
+    fn activation_delay(&self) -> usize {
+        DEFAULT_ACTIVATION_DELAY
+    }
+
+    /// Returns the sequence number and the update the given leaf proposes, or None when it is not
+    /// an authorized governance leaf. Parses the leaf using ParamUpdate::from_leaf() by default.
+    fn authorized_update(&self, leaf : &T) -> Option<(u64, ParamUpdate)> {
+        let (sequence, update, authorization) = ParamUpdate::from_leaf(leaf.as_ref())?;
+        self.is_authorized(sequence, &update, authorization).then_some((sequence, update))
+    }
+}
@@ -0,0 +1,128 @@
+use sha2::Sha256;
+use sha2::digest::Output;
+use std::array::TryFromSliceError;
+use std::cmp::Ordering;
+use std::convert::{TryFrom, TryInto};
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::str::FromStr;
+#[cfg(feature = "serde")]
+use serde::{Serialize, Deserialize};
+
+/// A SHA-256 hash (or any other 32 byte hash, see TreeHasher and PowFunction).
+///
+/// It is formatted as hex (using Display or LowerHex) and parsed from hex (using FromStr).
+/// Comparing two hashes for equality takes the same time no matter where they differ, so that
+/// checking a secret hash (e.g. of a password) doesn't leak how much of it was guessed right.
+#[derive(Clone, Copy, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(transparent))]
+pub struct ShaHash([u8; 32]);
+
+impl ShaHash {
+    /// The hash consisting of 32 zero bytes.
+    pub const ZERO : ShaHash = ShaHash([0; 32]);
+
+    /// Creates a hash from its bytes.
+    pub const fn new(bytes : [u8; 32]) -> ShaHash {
+        ShaHash(bytes)
+    }
+
+    /// Returns the bytes of this hash.
+    pub fn as_bytes(&self) -> &[u8; 32] {
+        &self.0
+    }
+
+    /// Returns the bytes of this hash.
+    pub fn to_bytes(self) -> [u8; 32] {
+        self.0
+    }
+}
+
+impl From<[u8; 32]> for ShaHash {
+    fn from(bytes : [u8; 32]) -> Self {
+        ShaHash(bytes)
+    }
+}
+
+impl From<ShaHash> for [u8; 32] {
+    fn from(hash : ShaHash) -> Self {
+        hash.0
+    }
+}
+
+impl TryFrom<&[u8]> for ShaHash {
+    type Error = TryFromSliceError;
+
+    fn try_from(bytes : &[u8]) -> Result<Self, Self::Error> {
+        Ok(ShaHash(bytes.try_into()?))
+    }
+}
+
+impl From<Output<Sha256>> for ShaHash {
+    /// Converts the output of a hash function, e.g. `Sha256::digest(data).into()`.
+    fn from(output : Output<Sha256>) -> Self {
+        ShaHash(output.into())
+    }
+}
+
+impl AsRef<[u8]> for ShaHash {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl PartialEq for ShaHash {
+    fn eq(&self, other : &Self) -> bool {
+        // Look at all bytes instead of stopping at the first difference
+        self.0.iter().zip(other.0.iter()).fold(0u8, |difference, (a, b)| difference | (a ^ b)) == 0
+    }
+}
+
+impl Eq for ShaHash {}
+
+impl Hash for ShaHash {
+    fn hash<H : Hasher>(&self, state : &mut H) {
+        self.0.hash(state);
+    }
+}
+
+impl PartialOrd for ShaHash {
+    fn partial_cmp(&self, other : &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for ShaHash {
+    fn cmp(&self, other : &Self) -> Ordering {
+        self.0.cmp(&other.0)
+    }
+}
+
+impl fmt::LowerHex for ShaHash {
+    fn fmt(&self, f : &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&hex::encode(self.0))
+    }
+}
+
+impl fmt::Display for ShaHash {
+    fn fmt(&self, f : &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::LowerHex::fmt(self, f)
+    }
+}
+
+impl fmt::Debug for ShaHash {
+    fn fmt(&self, f : &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "ShaHash({})", self)
+    }
+}
+
+impl FromStr for ShaHash {
+    type Err = hex::FromHexError;
+
+    /// Parses a hash from 64 hex digits, as written by Display.
+    fn from_str(text : &str) -> Result<Self, Self::Err> {
+        let mut bytes = [0u8; 32];
+        hex::decode_to_slice(text, &mut bytes)?;
+        Ok(ShaHash(bytes))
+    }
+}
@@ -0,0 +1,87 @@
+use crate::block::BlockHeader;
+use crate::{ShaHash, Nonce};
+
+// Compressed batches of BlockHeaders, e.g. for syncing a light client that only keeps the
+// headers of a Blockchain. A BlockHeader on its own takes HEADER_SIZE bytes, but in a batch of
+// consecutive headers most of that is redundant:
+// - the prev_hash of every header but the first is the hash of the header before it, so it is
+//   only stored once for the whole batch
+// - nonces are mostly small numbers (mining starts at 0), so they are stored as variable-length
+//   integers (LEB128) instead of taking up all of their bytes
+//
+// Layout: <prev_hash of the first header (32 bytes)> followed by <nonce (LEB128)><merkle root
+// (32 bytes)> for each header. An empty batch is stored as zero bytes.
+
+/// Compresses the given consecutive headers (each one coming right after the one before it).
+///
+/// Important: the prev_hash of every header but the first is not stored, so if the headers are
+/// not consecutive, decompress_headers() does not return the same headers!
+pub fn compress_headers(headers: &[BlockHeader]) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    if let Some(first) = headers.first() {
+        bytes.extend_from_slice(first.prev_hash().as_bytes());
+    }
+    for header in headers {
+        let mut nonce = header.nonce();
+        loop {
+            let byte = (nonce & 0x7f) as u8;
+            nonce >>= 7;
+            if nonce == 0 {
+                bytes.push(byte);
+                break;
+            }
+            bytes.push(byte | 0x80);
+        }
+        bytes.extend_from_slice(header.merkle_root().as_bytes());
+    }
+    bytes
+}
+
+/// Restores the headers compressed by compress_headers().
+/// Returns None when the given bytes are not a valid batch of headers.
+pub fn decompress_headers(bytes: &[u8]) -> Option<Vec<BlockHeader>> {
+    let mut headers = Vec::new();
+    if bytes.is_empty() {
+        return Some(headers);
+    }
+    let mut prev_hash = read_hash(bytes.get(..32)?);
+    let mut rest = &bytes[32..];
+    while !rest.is_empty() {
+        // Read the nonce
+        let mut nonce : Nonce = 0;
+        let mut shift = 0;
+        loop {
+            let (&byte, remaining) = rest.split_first()?;
+            rest = remaining;
+            if shift >= Nonce::BITS || (shift == Nonce::BITS - 1 && byte > 1) {
+                // Too many bytes or bits for a nonce (the last byte only holds its highest bit)
+                return None;
+            }
+            nonce |= Nonce::from(byte & 0x7f) << shift;
+            if byte & 0x80 == 0 {
+                if byte == 0 && shift > 0 {
+                    // Overlong encoding: compress_headers() never writes a trailing zero byte,
+                    // so every nonce has a single encoding
+                    return None;
+                }
+                break;
+            }
+            shift += 7;
+        }
+        // Read the merkle root
+        let merkle_root = read_hash(rest.get(..32)?);
+        rest = &rest[32..];
+
+        let header = BlockHeader::new(prev_hash, nonce, merkle_root);
+        prev_hash = header.calculate_hash();
+        headers.push(header);
+    }
+    Some(headers)
+}
+
+/// Copies the given 32 bytes into a ShaHash.
+fn read_hash(bytes: &[u8]) -> ShaHash {
+    let mut hash = [0u8; 32];
+    hash.copy_from_slice(bytes);
+    hash.into()
+}
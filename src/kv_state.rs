@@ -0,0 +1,219 @@
+use sha2::Sha256;
+use sha2::Digest;
+use std::collections::BTreeMap;
+use std::convert::TryInto;
+use crate::block::Block;
+use crate::blockchain::Blockchain;
+use crate::merkle_tree::{hash_node, LeafCodec};
+use crate::ShaHash;
+
+/// The root hash of a KvState without any entries (and of every empty subtree of its sparse
+/// Merkle Tree).
+pub const EMPTY_STATE_ROOT : ShaHash = ShaHash::ZERO;
+
+/// An operation on the key-value state (see KvState), to be stored as a leaf.
+///
+/// Keys live in namespaces, so that different applications sharing a Blockchain can't overwrite
+/// each other's entries. The bytes of a leaf are:
+/// - Put: `0 <length of namespace (u32 big endian)> <namespace> <length of key (u32)> <key> <value>`
+/// - Delete: `1 <length of namespace (u32 big endian)> <namespace> <key>`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct KvOp {
+    bytes: Vec<u8>
+}
+
+impl KvOp {
+
+    /// Sets the given key of the given namespace to the given value.
+    pub fn put(namespace: &[u8], key: &[u8], value: &[u8]) -> KvOp {
+        let mut bytes = vec![0];
+        append_with_length(&mut bytes, namespace);
+        append_with_length(&mut bytes, key);
+        bytes.extend_from_slice(value);
+        KvOp { bytes }
+    }
+
+    /// Removes the given key of the given namespace.
+    pub fn delete(namespace: &[u8], key: &[u8]) -> KvOp {
+        let mut bytes = vec![1];
+        append_with_length(&mut bytes, namespace);
+        bytes.extend_from_slice(key);
+        KvOp { bytes }
+    }
+
+    /// Parses the bytes of a leaf, e.g. received from others.
+    /// Returns None when they are not a valid operation.
+    pub fn from_bytes(bytes: Vec<u8>) -> Option<KvOp> {
+        decode(&bytes)?;
+        Some(KvOp { bytes })
+    }
+
+    /// Returns the namespace of the key.
+    pub fn namespace(&self) -> &[u8] {
+        self.parts().0
+    }
+
+    /// Returns the key this operation is about.
+    pub fn key(&self) -> &[u8] {
+        self.parts().1
+    }
+
+    /// Returns the new value of a Put, or None for a Delete.
+    pub fn value(&self) -> Option<&[u8]> {
+        self.parts().2
+    }
+
+    fn parts(&self) -> Parts<'_> {
+        decode(&self.bytes).expect("KvOps are validated when they are created")
+    }
+}
+
+impl AsRef<[u8]> for KvOp {
+    fn as_ref(&self) -> &[u8] {
+        &self.bytes
+    }
+}
+
+impl LeafCodec for KvOp {
+    fn encode(&self) -> Vec<u8> {
+        self.bytes.clone()
+    }
+
+    fn decode(bytes: &[u8]) -> Option<Self> {
+        KvOp::from_bytes(bytes.to_vec())
+    }
+}
+
+/// Appends the length of the given bytes (u32 big endian) followed by the bytes themselves.
+fn append_with_length(bytes: &mut Vec<u8>, part: &[u8]) {
+    let length : u32 = part.len().try_into().expect("Namespaces and keys must be shorter than 4 GiB");
+    bytes.extend_from_slice(&length.to_be_bytes());
+    bytes.extend_from_slice(part);
+}
+
+/// Splits the given bytes into a part prefixed with its length and the rest.
+fn split_with_length(bytes: &[u8]) -> Option<(&[u8], &[u8])> {
+    let length = u32::from_be_bytes(bytes.get(..4)?.try_into().ok()?) as usize;
+    let rest = &bytes[4..];
+    (length <= rest.len()).then(|| rest.split_at(length))
+}
+
+/// The namespace, key and value (None for a Delete) of a KvOp.
+type Parts<'a> = (&'a [u8], &'a [u8], Option<&'a [u8]>);
+
+/// Splits the bytes of a KvOp into its parts.
+fn decode(bytes: &[u8]) -> Option<Parts<'_>> {
+    let (tag, rest) = bytes.split_first()?;
+    let (namespace, rest) = split_with_length(rest)?;
+    match tag {
+        0 => {
+            let (key, value) = split_with_length(rest)?;
+            Some((namespace, key, Some(value)))
+        },
+        1 => Some((namespace, rest, None)),
+        _ => None
+    }
+}
+
+/// The key-value state resulting from applying KvOps in order, e.g. those of the Blocks of a
+/// Blockchain<KvOp> (see state_roots()).
+///
+/// Applying the same operations always results in the same state, which is committed to by its
+/// root(), so everyone replaying a Blockchain agrees on the state after each Block.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct KvState {
+    /// The values by namespace and key.
+    entries: BTreeMap<(Vec<u8>, Vec<u8>), Vec<u8>>
+}
+
+impl KvState {
+
+    /// Creates an empty state.
+    pub fn new() -> KvState {
+        KvState::default()
+    }
+
+    /// Returns the value of the given key of the given namespace, or None when it is not set.
+    pub fn get(&self, namespace: &[u8], key: &[u8]) -> Option<&[u8]> {
+        self.entries.get(&(namespace.to_vec(), key.to_vec())).map(Vec::as_slice)
+    }
+
+    /// Returns the number of keys that are set (in all namespaces).
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns true when no key is set.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Applies the given operation.
+    pub fn apply(&mut self, op: &KvOp) {
+        let entry = (op.namespace().to_vec(), op.key().to_vec());
+        match op.value() {
+            Some(value) => self.entries.insert(entry, value.to_vec()),
+            None => self.entries.remove(&entry)
+        };
+    }
+
+    /// Applies the operations of the given Block, from left to right.
+    /// Returns false (without applying anything) when some of them are forgotten.
+    pub fn apply_block(&mut self, block: &Block<KvOp>) -> bool {
+        let mtree = block.get_merkle_tree();
+        if mtree.forgotten_leaf_count() > 0 {
+            return false;
+        }
+        for op in mtree.get_currently_stored_data() {
+            self.apply(&op);
+        }
+        true
+    }
+
+    /// Returns the root hash of the sparse Merkle Tree of this state.
+    ///
+    /// The tree has a leaf for each of the 2^256 possible slots, the slot of a key being the
+    /// SHA-256 hash of `<length of namespace (u32 big endian)> <namespace> <key>`. The leaf of a
+    /// slot whose key is set is the SHA-256 hash of the value, all other leaves (and all subtrees
+    /// without any key set) are EMPTY_STATE_ROOT.
+    pub fn root(&self) -> ShaHash {
+        let mut slots : Vec<(ShaHash, ShaHash)> = self.entries.iter()
+            .map(|((namespace, key), value)| {
+                let mut slot = Vec::new();
+                append_with_length(&mut slot, namespace);
+                slot.extend_from_slice(key);
+                (Sha256::digest(&slot).into(), Sha256::digest(value).into())
+            })
+            .collect();
+        slots.sort_unstable();
+        sparse_root(&slots, 0)
+    }
+}
+
+/// Returns the root hash of the subtree at the given depth of the sparse Merkle Tree containing
+/// the given (sorted) slots and their leaf hashes, see KvState::root().
+fn sparse_root(slots: &[(ShaHash, ShaHash)], depth: usize) -> ShaHash {
+    match slots {
+        [] => EMPTY_STATE_ROOT,
+        [(_, leaf_hash)] if depth == 256 => *leaf_hash,
+        _ => {
+            // Slots whose bit at this depth is 0 go left, the others go right
+            let bit = |slot : &ShaHash| slot.as_bytes()[depth / 8] & (0x80 >> (depth % 8)) != 0;
+            let split = slots.partition_point(|(slot, _)| !bit(slot));
+            let (left, right) = slots.split_at(split);
+            hash_node::<Sha256>(sparse_root(left, depth + 1), sparse_root(right, depth + 1))
+        }
+    }
+}
+
+/// Replays the given Blockchain and returns the root of the KvState after each of its Blocks.
+/// Returns None when some of the operations are forgotten.
+pub fn state_roots(chain: &Blockchain<KvOp>) -> Option<Vec<ShaHash>> {
+    let mut state = KvState::new();
+    (0..chain.length())
+        .map(|height| {
+            let block = chain.get_block(height)?;
+            state.apply_block(block).then(|| state.root())
+        })
+        .collect()
+}
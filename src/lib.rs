@@ -1,6 +1,61 @@
-mod block;
-mod blockchain;
-mod merkle_tree;
+// Everything but the Merkle Tree (and the helpers working on nothing but Merkle Trees) requires
+// the "blockchain" feature, which is enabled by default. Disable the default features to use the
+// Merkle Tree on its own ("merkle-only").
+pub mod merkle_tree;
+pub mod encrypted_leaf;
+pub mod payload;
+pub mod chunked_leaf;
+pub mod detached_leaf;
+pub mod bloom;
+pub mod mmr;
+pub mod flat_merkle_tree;
+pub mod sorted_merkle_tree;
+pub mod data_availability;
+pub mod namespace;
+#[cfg(feature = "blockchain")]
+pub mod block;
+#[cfg(feature = "blockchain")]
+pub mod blockchain;
+#[cfg(feature = "blockchain")]
+pub mod bridge;
+#[cfg(feature = "blockchain")]
+pub mod audit_log;
+#[cfg(feature = "blockchain")]
+pub mod header_batch;
+#[cfg(feature = "blockchain")]
+pub mod pow;
+#[cfg(feature = "blockchain")]
+pub mod chain_params;
+#[cfg(feature = "blockchain")]
+pub mod overlay;
+#[cfg(feature = "blockchain")]
+pub mod watchtower;
+#[cfg(feature = "blockchain")]
+pub mod observer;
+#[cfg(feature = "blockchain")]
+pub mod kv_state;
+#[cfg(feature = "blockchain")]
+pub mod beacon;
+#[cfg(feature = "blockchain")]
+pub mod governance;
+#[cfg(feature = "blockchain")]
+pub mod bulk_import;
+#[cfg(feature = "blockchain")]
+pub mod replica;
+#[cfg(feature = "stress")]
+pub mod stress;
+
+mod hash;
+pub use hash::ShaHash;
+
+/// The former name of ShaHash, from when hashes were plain byte arrays.
+#[deprecated(note = "use ShaHash instead")]
+pub type SHAHash = ShaHash;
+
+/// The Nonce of a Block is the thing that's incremented until the Block's hash has the
+/// required zero bits (as many as specified by the ZEROS constant).
+#[cfg(feature = "blockchain")]
+pub type Nonce = u64;
 
 // Implementing the thoughts of Satoshi Nakamoto in https://bitcoin.org/bitcoin.pdf:
 /*pub mod blockchain {
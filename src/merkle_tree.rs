@@ -1,6 +1,20 @@
 use sha2::Sha256;
 use sha2::Digest;
-use std::str::FromStr;
+use sha2::digest::consts::U32;
+use sha2::digest::FixedOutputDirty;
+use crate::ShaHash;
+use crate::mmr::Mmr;
+use std::collections::HashMap;
+use std::cell::{Cell, RefCell};
+use std::marker::PhantomData;
+use std::fmt;
+use std::convert::{TryFrom, TryInto};
+#[cfg(feature = "serde")]
+use serde::{Serialize, Deserialize};
+
+/// The root hash of the empty Merkle Tree (see MerkleTree::empty()). No data hashes to it (finding
+/// some would mean breaking the hash function), so it can't be confused with any other tree.
+pub const EMPTY_TREE_ROOT : ShaHash = ShaHash::ZERO;
 
 /// In order to be able to reduce the size of the Blockchain / to forget old
 /// no longer necessary to remember data, Blocks store their data in a Merkle Tree.
@@ -12,11 +26,12 @@ use std::str::FromStr;
 ///
 /// For graphics of Merkle Trees, see the Bitcoin paper (https://bitcoin.org/bitcoin.pdf), pp.4+5
 #[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum MerkleTree<T : AsRef<[u8]> + Clone> {
     // A node with a left and a right child and the hash of them.
     Node {
         /// The hash of both children `left` and `right` of this node.
-        hash: SHAHash,
+        hash: ShaHash,
         /// The left child of this node.
         left: Box<MerkleTree<T>>,
         /// The right child of this node.
@@ -26,12 +41,12 @@ pub enum MerkleTree<T : AsRef<[u8]> + Clone> {
     /// A leaf is a node without children, but with data
     Leaf {
         /// The hash of the data of this leaf.
-        hash: SHAHash,
+        hash: ShaHash,
         /// - A Leaf with the Option being 'Some' is an actual Leaf of the Merkle Tree storing some data T.
         /// - A Leaf with the Option being 'None' could be 2 things:
-        /// a) an actual Leaf of the full Merkle Tree with just the data T missing OR
-        /// b) the root of a Merkle Subtree that has been chopped off (when reinserting that,
-        ///    the Option shall never become 'Some' but the Leaf shall rather be replaced with a Node)
+        ///   a) an actual Leaf of the full Merkle Tree with just the data T missing OR
+        ///   b) the root of a Merkle Subtree that has been chopped off (when reinserting that,
+        ///   the Option shall never become 'Some' but the Leaf shall rather be replaced with a Node)
         data: Option<T>
     }
 }
@@ -41,44 +56,65 @@ impl<T: AsRef<[u8]> + Clone> MerkleTree<T> {
     /// Creates a new Merkle Tree with the data from the given Vector.
     /// If your data is in some other data structure, please collect() it into a Vec first.
     ///
-    /// Please note that no data may be added later on and that the data also cannot be changed.
-    /// Data can however be forgotten to save space and be restored later.
+    /// Please note that no data may be added later on and that the data can only be changed leaf
+    /// by leaf (see update_leaf()). Data can however be forgotten to save space and be restored later.
     ///
-    /// Important: this function panics when called on an empty Vec!
+    /// An empty Vec results in the empty Merkle Tree (see empty()).
     pub fn new(data: &[T]) -> MerkleTree<T> {
+        Self::new_with::<Sha256>(data)
+    }
+
+    /// Same as new(), but returns MerkleTreeError::Empty instead of the empty Merkle Tree when
+    /// called on an empty Vec.
+    pub fn try_new(data: &[T]) -> Result<MerkleTree<T>, MerkleTreeError> {
+        Self::try_new_with::<Sha256>(data)
+    }
+
+    /// Same as new(), but hashes the leaves and nodes using D instead of SHA-256: any hash
+    /// function with 256-bit outputs (e.g. sha3::Sha3_256) or DomainSeparated (see TreeHasher).
+    ///
+    /// Please note that a Merkle Tree does not remember its hash function: use the other *_with()
+    /// functions with the same D on it (e.g. verify_with()).
+    pub fn new_with<D : TreeHasher>(data: &[T]) -> MerkleTree<T> {
+        Self::try_new_with::<D>(data).unwrap_or_else(|_| Self::empty())
+    }
+
+    /// Same as new_with(), but returns MerkleTreeError::Empty instead of the empty Merkle Tree
+    /// when called on an empty Vec.
+    pub fn try_new_with<D : TreeHasher>(data: &[T]) -> Result<MerkleTree<T>, MerkleTreeError> {
         /*match vector.len() {
             0 => panic!("Cannot create a MerkleTree from an empty Vec!"),
-            1 => MerkleTree::Leaf(SHAHash::from(Sha256::new().chain(vector[0]).finalize()), Some(vector[0])),
+            1 => MerkleTree::Leaf(ShaHash::from(Sha256::new().chain(vector[0]).finalize()), Some(vector[0])),
             2 => {
-                let left_leaf = MerkleTree::Leaf(SHAHash::from(Sha256::new().chain(vector[0]).finalize()), Some(vector[0]));
-                let right_leaf = MerkleTree::Leaf(SHAHash::from(Sha256::new().chain(vector[1]).finalize()), Some(vector[1]));
-                MerkleTree::Node(SHAHash::from(Sha256::new().chain(left_leaf.get_root_hash()).chain(right_leaf.get_root_hash()).finalize()),
+                let left_leaf = MerkleTree::Leaf(ShaHash::from(Sha256::new().chain(vector[0]).finalize()), Some(vector[0]));
+                let right_leaf = MerkleTree::Leaf(ShaHash::from(Sha256::new().chain(vector[1]).finalize()), Some(vector[1]));
+                MerkleTree::Node(ShaHash::from(Sha256::new().chain(left_leaf.get_root_hash()).chain(right_leaf.get_root_hash()).finalize()),
                                  Box::new(left_leaf),
                                  Box::new(right_leaf))
             }
             3 => {
-                let leaf1 = MerkleTree::Leaf(SHAHash::from(Sha256::new().chain(vector[0]).finalize()), Some(vector[0]));
-                let leaf2 = MerkleTree::Leaf(SHAHash::from(Sha256::new().chain(vector[1]).finalize()), Some(vector[1]));
-                let leaf3 = MerkleTree::Leaf(SHAHash::from(Sha256::new().chain(vector[2]).finalize()), Some(vector[2]));
-                let left_subtree = MerkleTree::Node(SHAHash::from(Sha256::new().chain(leaf1.get_root_hash()).chain(leaf2.get_root_hash()).finalize()),
+                let leaf1 = MerkleTree::Leaf(ShaHash::from(Sha256::new().chain(vector[0]).finalize()), Some(vector[0]));
+                let leaf2 = MerkleTree::Leaf(ShaHash::from(Sha256::new().chain(vector[1]).finalize()), Some(vector[1]));
+                let leaf3 = MerkleTree::Leaf(ShaHash::from(Sha256::new().chain(vector[2]).finalize()), Some(vector[2]));
+                let left_subtree = MerkleTree::Node(ShaHash::from(Sha256::new().chain(leaf1.get_root_hash()).chain(leaf2.get_root_hash()).finalize()),
                                                     Box::new(leaf1),
                                                     Box::new(leaf2));
-                MerkleTree::Node(SHAHash::from(Sha256::new().chain(left_subtree.get_root_hash()).chain(leaf3.get_root_hash()).finalize()),
+                MerkleTree::Node(ShaHash::from(Sha256::new().chain(left_subtree.get_root_hash()).chain(leaf3.get_root_hash()).finalize()),
                                  Box::new(left_subtree),
                                  Box::new(leaf3))
             }
             4 => {
-                let leaf1 = MerkleTree::Leaf(SHAHash::from(Sha256::new().chain(vector[0]).finalize()), Some(vector[0]));
-                let leaf2 = MerkleTree::Leaf(SHAHash::from(Sha256::new().chain(vector[1]).finalize()), Some(vector[1]));
-                let leaf3 = MerkleTree::Leaf(SHAHash::from(Sha256::new().chain(vector[2]).finalize()), Some(vector[2]));
-                let leaf4 = MerkleTree::Leaf(SHAHash::from(Sha256::new().chain(vector[2]).finalize()), Some(vector[2]));
-                let left_subtree = MerkleTree::Node(SHAHash::from(Sha256::new().chain(leaf1.get_root_hash()).chain(leaf2.get_root_hash()).finalize()),
+                let leaf1 = MerkleTree::Leaf(ShaHash::from(Sha256::new().chain(vector[0]).finalize()), Some(vector[0]));
+                let leaf2 = MerkleTree::Leaf(ShaHash::from(Sha256::new().chain(vector[1]).finalize()), Some(vector[1]));
+                let leaf3 = MerkleTree::Leaf(ShaHash::from(Sha256::new().chain(vector[2]).finalize()), Some(vector[2]));
+                let leaf4 = MerkleTree::Leaf(ShaHash::from(Sha256::new().chain(vector[2]).finalize()), Some(vector[2]));
+                let left_subtree = MerkleTree::Node(ShaHash::from(Sha256::new().chain(leaf1.get_root_hash()).chain(leaf2.get_root_hash()).finalize()),
                                                     Box::new(leaf1),
                                                     Box::new(leaf2));
-                let right_subtree = MerkleTree::Node(SHAHash::from(Sha256::new().chain(leaf3.get_root_hash()).chain(leaf4.get_root_hash()).finalize()),
+                let right_subtree = MerkleTree::Node(ShaHash::from(Sha256::new().chain(leaf3.get_root_hash()).chain(leaf4.get_root_hash()).finalize()),
                                                      Box::new(leaf3),
                                                      Box::new(leaf4));
-                MerkleTree::Node(SHAHash::from(Sha256::new().chain(left_subtree.get_root_hash()).chain(right_subtree.get_root_hash()).finalize()),
+                MerkleTree::Node(ShaHash::from(Sha256::new().chain(left_subtree.get_root_hash()).chain(right_subtree.get_root_hash()).finalize()),
                                  Box::new(left_subtree),
                                  Box::new(right_subtree))
             }
@@ -88,41 +124,121 @@ impl<T: AsRef<[u8]> + Clone> MerkleTree<T> {
         }*/
 
         match data.len() {
-            0 => panic!("Cannot create a MerkleTree from an data array!"),
+            0 => Err(MerkleTreeError::Empty),
             1 => {
-                MerkleTree::Leaf {
-                    hash: Sha256::new().digest(data[0].clone()),
-                    data: data[0].clone()
-                }
+                Ok(MerkleTree::Leaf {
+                    hash: hash_leaf::<D>(data[0].as_ref()),
+                    data: Some(data[0].clone())
+                })
             },
             _ => {
                 // Split the data in two equally sized subtrees
-                let mut parts = data.chunks(data.len()/2);
+                let (left_part, right_part) = data.split_at(data.len()/2);
                 // Create subtrees for the two parts
-                let left_subtree = Self::new(parts.next().unwrap());
-                let right_subtree = Self::new(parts.next().unwrap());
+                let left_subtree = Self::try_new_with::<D>(left_part)?;
+                let right_subtree = Self::try_new_with::<D>(right_part)?;
                 // Return a node with these two parts as children
-                MerkleTree::Node {
-                    hash: Sha256::new()
-                        .chain(left_subtree.get_root_hash())
-                        .chain(right_subtree.get_root_hash()).finalize(),
+                Ok(MerkleTree::Node {
+                    hash: hash_node::<D>(left_subtree.get_root_hash(), right_subtree.get_root_hash()),
                     left: Box::new(left_subtree),
                     right: Box::new(right_subtree)
-                }
+                })
+            }
+        }
+    }
+
+    /// Returns the empty Merkle Tree, i.e. the Merkle Tree of no data at all (e.g. of a Block
+    /// without any data items). It consists of a single forgotten leaf with the root hash
+    /// EMPTY_TREE_ROOT, no matter which hash function is used, and has no leaves (see is_empty()).
+    pub fn empty() -> MerkleTree<T> {
+        MerkleTree::Leaf {
+            hash: EMPTY_TREE_ROOT,
+            data: None
+        }
+    }
+
+    /// Returns true when this is the empty Merkle Tree, see empty().
+    pub fn is_empty(&self) -> bool {
+        matches!(self, MerkleTree::Leaf{hash, data: None} if *hash == EMPTY_TREE_ROOT)
+    }
+
+    /// Creates a new Merkle Tree with the data from the given Vector the way Bitcoin does, so that
+    /// the root hash matches the Merkle root of a Bitcoin Block when given its raw transactions:
+    /// - leaves and nodes are hashed using double SHA-256 (Sha256d), i.e. the leaf hashes are the
+    ///   transaction ids,
+    /// - the tree is built level by level from the leaves up, pairing neighbouring nodes,
+    /// - on levels with an odd number of nodes the last node is paired with itself.
+    ///
+    /// The duplicated nodes are stored as forgotten subtrees (only their hash), so no data is
+    /// stored twice. Use the *_with::<Sha256d>() functions on the resulting Merkle Tree (e.g.
+    /// verify_with() or generate_proof_with()).
+    ///
+    /// Please note that, because of the duplication, different lists of data can result in the same
+    /// root hash (e.g. [a, b, c] and [a, b, c, c], see CVE-2012-2459).
+    ///
+    /// An empty Vec results in the empty Merkle Tree (see empty()), just like for new().
+    pub fn new_bitcoin(data: &[T]) -> MerkleTree<T> {
+        Self::pair_bitcoin(data.iter()
+            .map(|element| MerkleTree::Leaf {
+                hash: hash_leaf::<Sha256d>(element.as_ref()),
+                data: Some(element.clone())
+            })
+            .collect())
+    }
+
+    /// Same as new_bitcoin(), but creates the Merkle Tree from nothing but the hashes of its
+    /// leaves (e.g. the transaction ids of a Bitcoin Block, in internal byte order), i.e. all
+    /// of its leaves are forgotten. The data can be restored later using
+    /// restore_element_with::<Sha256d>().
+    ///
+    /// An empty Vec results in the empty Merkle Tree (see empty()).
+    pub fn new_bitcoin_from_leaf_hashes(leaf_hashes: &[ShaHash]) -> MerkleTree<T> {
+        Self::pair_bitcoin(leaf_hashes.iter()
+            .map(|hash| MerkleTree::Leaf { hash: *hash, data: None })
+            .collect())
+    }
+
+    /// Builds the levels of a Merkle Tree the way Bitcoin does (see new_bitcoin()) on top of the
+    /// given list of leaves, or returns the empty Merkle Tree when there are none.
+    fn pair_bitcoin(mut level: Vec<MerkleTree<T>>) -> MerkleTree<T> {
+        if level.is_empty() {
+            return MerkleTree::empty();
+        }
+        while level.len() > 1 {
+            if level.len() % 2 == 1 {
+                // Pair the last node with (a forgotten copy of) itself
+                let last_hash = level[level.len() - 1].get_root_hash();
+                level.push(MerkleTree::Leaf { hash: last_hash, data: None });
             }
+            let mut nodes = level.into_iter();
+            let mut next_level = Vec::new();
+            while let (Some(left), Some(right)) = (nodes.next(), nodes.next()) {
+                next_level.push(MerkleTree::Node {
+                    hash: hash_node::<Sha256d>(left.get_root_hash(), right.get_root_hash()),
+                    left: Box::new(left),
+                    right: Box::new(right)
+                });
+            }
+            level = next_level;
         }
+        level.remove(0)
     }
 
     /// Returns the hash of this MerkleTree.
-    pub fn get_root_hash(&self) -> SHAHash {
+    pub fn get_root_hash(&self) -> ShaHash {
         match self {
-            MerkleTree::Node{hash, ..} => hash,
-            MerkleTree::Leaf{hash, ..} => hash
+            MerkleTree::Node{hash, ..} => *hash,
+            MerkleTree::Leaf{hash, ..} => *hash
         }
     }
 
     /// Checks whether this Merkle Tree is valid, i.e. all hashes are correct.
     pub fn verify(&self) -> bool {
+        self.verify_with::<Sha256>()
+    }
+
+    /// Same as verify(), but for a Merkle Tree created using new_with() with the hash function D.
+    pub fn verify_with<D : TreeHasher>(&self) -> bool {
         match self {
             MerkleTree::Leaf{data: None, ..} => {
                 // No data stored at all -> no hashes to match
@@ -130,22 +246,31 @@ impl<T: AsRef<[u8]> + Clone> MerkleTree<T> {
             },
             MerkleTree::Leaf{hash, data: Some(t)} => {
                 // Check if the stored hash matches the one recalculated using data
-                *hash == SHAHash::from(Sha256::new().digest(t))
+                *hash == hash_leaf::<D>(t.as_ref())
             },
             MerkleTree::Node{hash, left, right} => {
                 // Check if the stored hash matches the one recalculated using the two subtrees
-                let valid_root = *hash == SHAHash::from(
-                    Sha256::new()
-                        .chain(left.get_root_hash())
-                        .chain(right.get_root_hash()).finalize()
-                );
+                let valid_root = *hash == hash_node::<D>(left.get_root_hash(), right.get_root_hash());
                 // Check if both subtrees are valid in themself
-                let valid_children = left.verify() && right.verify(); // -> recursion
-                return valid_root && valid_children
+                let valid_children = left.verify_with::<D>() && right.verify_with::<D>(); // -> recursion
+                valid_root && valid_children
             }
         }
     }
 
+    /// Checks whether this Merkle Tree is valid (see verify()) and has the given root hash, e.g.
+    /// one from a BlockHeader, when the Merkle Tree comes from an untrusted source.
+    pub fn verify_against(&self, expected_root: &ShaHash) -> bool {
+        self.verify_against_with::<Sha256>(expected_root)
+    }
+
+    /// Same as verify_against(), but for a Merkle Tree created using new_with() with the hash
+    /// function D.
+    pub fn verify_against_with<D : TreeHasher>(&self, expected_root: &ShaHash) -> bool {
+        // Comparing the root hash first is cheap and avoids hashing the whole tree of a wrong one
+        self.get_root_hash() == *expected_root && self.verify_with::<D>()
+    }
+
     // ----- Retrieve data from this MerkleTree: -----
 
     /// Returns all the data that's currently stored in this Merkle Tree.
@@ -158,12 +283,229 @@ impl<T: AsRef<[u8]> + Clone> MerkleTree<T> {
             },
             MerkleTree::Leaf{data: Some(data), ..} => {
                 // Self is a leaf, so only export its data
-                Vec::from(data)
+                vec![data.clone()]
             },
             MerkleTree::Node{left, right, ..} => {
                 // Collect the data from the left and right subtrees
-                left.get_currently_stored_data()
-                    .append(&mut right.get_currently_stored_data())
+                let mut data = left.get_currently_stored_data();
+                data.append(&mut right.get_currently_stored_data());
+                data
+            }
+        }
+    }
+
+    /// Returns the hashes of all the leaves of this Merkle Tree, from left to right, no matter
+    /// whether their data is currently stored or not.
+    ///
+    /// Please note that subtrees that were forgotten entirely (e.g. by forget_subtree()) show up
+    /// as a single leaf with the root hash of the subtree!
+    pub fn get_leaf_hashes(&self) -> Vec<ShaHash> {
+        match self {
+            _ if self.is_empty() => vec![],
+            MerkleTree::Leaf{hash, ..} => vec![*hash],
+            MerkleTree::Node{left, right, ..} => {
+                let mut hashes = left.get_leaf_hashes();
+                hashes.append(&mut right.get_leaf_hashes());
+                hashes
+            }
+        }
+    }
+
+    /// Returns the hashes of all the leaves of this Merkle Tree together with their data (if it is
+    /// currently stored), from left to right, see get_leaf_hashes().
+    pub(crate) fn get_leaves(&self) -> Vec<(ShaHash, Option<&T>)> {
+        match self {
+            _ if self.is_empty() => vec![],
+            MerkleTree::Leaf{hash, data} => vec![(*hash, data.as_ref())],
+            MerkleTree::Node{left, right, ..} => {
+                let mut leaves = left.get_leaves();
+                leaves.append(&mut right.get_leaves());
+                leaves
+            }
+        }
+    }
+
+    /// Returns the (sub)tree with the given root hash, or None when there is no such subtree.
+    #[cfg(feature = "blockchain")]
+    pub(crate) fn get_subtree(&self, search_hash: &ShaHash) -> Option<&MerkleTree<T>> {
+        match self {
+            _ if self.get_root_hash() == *search_hash => Some(self),
+            MerkleTree::Leaf{..} => None,
+            MerkleTree::Node{left, right, ..} =>
+                left.get_subtree(search_hash).or_else(|| right.get_subtree(search_hash))
+        }
+    }
+
+    /// Compares this Merkle Tree with another one, e.g. a differently pruned copy of the same
+    /// Merkle Tree held by someone else, see TreeDiff.
+    ///
+    /// Only the parts of both trees with equal hashes are compared in detail, so comparing
+    /// trees with the same root hash tells exactly which data has to be exchanged in order to
+    /// synchronize them (see restore_subtree() and restore_element()).
+    pub fn diff(&self, other: &MerkleTree<T>) -> TreeDiff {
+        let mut diff = TreeDiff::default();
+        self.collect_diff(other, &mut Vec::new(), &mut diff);
+        diff
+    }
+
+    /// Adds the differences between this subtree and the other one at the given path to `diff`.
+    fn collect_diff(&self, other: &MerkleTree<T>, path: &mut Vec<Direction>, diff: &mut TreeDiff) {
+        if self.get_root_hash() != other.get_root_hash() {
+            diff.differing_subtrees.push(path.clone());
+            return;
+        }
+        match (self, other) {
+            (MerkleTree::Node{left, right, ..}, MerkleTree::Node{left: other_left, right: other_right, ..}) => {
+                path.push(Direction::Left);
+                left.collect_diff(other_left, path, diff);
+                path.pop();
+                path.push(Direction::Right);
+                right.collect_diff(other_right, path, diff);
+                path.pop();
+            },
+            (MerkleTree::Leaf{data: None, ..}, _) => diff.missing_in_self.extend(other.get_stored_leaf_hashes()),
+            (_, MerkleTree::Leaf{data: None, ..}) => diff.missing_in_other.extend(self.get_stored_leaf_hashes()),
+            _ => {
+                // Both store the data of this leaf
+            }
+        }
+    }
+
+    /// Returns the hashes of all the leaves of this Merkle Tree whose data is currently stored.
+    fn get_stored_leaf_hashes(&self) -> Vec<ShaHash> {
+        self.get_leaves().into_iter()
+            .filter(|(_, data)| data.is_some())
+            .map(|(hash, _)| hash)
+            .collect()
+    }
+
+    // ----- Traversal: -----
+
+    /// Calls the given visitor for every node and leaf of this Merkle Tree, depth-first from left
+    /// to right with every node coming before its children (pre-order), e.g. for tools displaying
+    /// or inspecting Merkle Trees. Does not use recursion, so it works for trees of any depth.
+    pub fn visit_nodes<'a, F : FnMut(NodeInfo<'a, T>)>(&'a self, mut visitor: F) {
+        let mut pending = vec![(self, 0)];
+        while let Some((subtree, depth)) = pending.pop() {
+            match subtree {
+                MerkleTree::Node{hash, left, right} => {
+                    visitor(NodeInfo { hash: *hash, depth, kind: NodeKind::Inner });
+                    pending.push((right, depth + 1));
+                    pending.push((left, depth + 1));
+                },
+                MerkleTree::Leaf{hash, data} =>
+                    visitor(NodeInfo { hash: *hash, depth, kind: NodeKind::Leaf { data: data.as_ref() } })
+            }
+        }
+    }
+
+    // ----- Statistics: -----
+
+    /// Returns statistics about the shape of this Merkle Tree and about how much of its data is
+    /// still stored, see TreeStats.
+    pub fn stats(&self) -> TreeStats {
+        match self {
+            _ if self.is_empty() => TreeStats {
+                depth: 0,
+                node_count: 1,
+                ..TreeStats::default()
+            },
+            MerkleTree::Leaf{data, ..} => TreeStats {
+                depth: 0,
+                node_count: 1,
+                stored_leaf_count: usize::from(data.is_some()),
+                forgotten_leaf_count: usize::from(data.is_none()),
+                stored_bytes: data.as_ref().map_or(0, |data| data.as_ref().len())
+            },
+            MerkleTree::Node{left, right, ..} => {
+                let (left, right) = (left.stats(), right.stats());
+                TreeStats {
+                    depth: 1 + left.depth.max(right.depth),
+                    node_count: 1 + left.node_count + right.node_count,
+                    stored_leaf_count: left.stored_leaf_count + right.stored_leaf_count,
+                    forgotten_leaf_count: left.forgotten_leaf_count + right.forgotten_leaf_count,
+                    stored_bytes: left.stored_bytes + right.stored_bytes
+                }
+            }
+        }
+    }
+
+    /// Returns the number of edges on the longest path from the root to a leaf (0 for a single
+    /// leaf).
+    pub fn depth(&self) -> usize {
+        self.stats().depth
+    }
+
+    /// Returns the number of nodes of this Merkle Tree, including the leaves.
+    pub fn node_count(&self) -> usize {
+        self.stats().node_count
+    }
+
+    /// Returns the number of leaves currently storing their data.
+    pub fn stored_leaf_count(&self) -> usize {
+        self.stats().stored_leaf_count
+    }
+
+    /// Returns the number of leaves whose data is forgotten, see TreeStats::forgotten_leaf_count.
+    pub fn forgotten_leaf_count(&self) -> usize {
+        self.stats().forgotten_leaf_count
+    }
+
+    /// Estimates how much memory this Merkle Tree takes up in bytes: the nodes and leaves
+    /// themselves plus the size of the stored data as given by AsRef<[u8]>, which is about right
+    /// for e.g. Vec<u8> or String. Use approximate_size_bytes_with() for other types.
+    pub fn approximate_size_bytes(&self) -> usize {
+        self.approximate_size_bytes_with(|data| data.as_ref().len())
+    }
+
+    /// Same as approximate_size_bytes(), but uses the given function to determine how much memory
+    /// (in addition to size_of::<T>()) a stored element takes up, e.g. on the heap.
+    /// Overhead of the allocator is not taken into account.
+    pub fn approximate_size_bytes_with<F : Fn(&T) -> usize>(&self, data_size: F) -> usize {
+        let nodes = self.node_count() * std::mem::size_of::<MerkleTree<T>>();
+        let data : usize = self.get_leaves().into_iter()
+            .filter_map(|(_, data)| data)
+            .map(&data_size)
+            .sum();
+        nodes + data
+    }
+
+    // ----- Debugging: -----
+
+    /// Draws this Merkle Tree as ASCII art, one node per line, showing the first 4 bytes of each
+    /// hash. Leaves whose data is not stored (either forgotten leaves or the roots of forgotten
+    /// subtrees) are marked as "forgotten". Nodes deeper than `max_depth` are not drawn.
+    ///
+    /// Example:
+    /// ```text
+    /// node 1f0c9a3e
+    /// |-- leaf 5b2e77c1
+    /// `-- node 0a9d4e12
+    ///     |-- leaf 3c4d5e6f (forgotten)
+    ///     `-- leaf 77aa01bc
+    /// ```
+    pub fn render_ascii(&self, max_depth: usize) -> String {
+        let mut output = String::new();
+        self.render_ascii_lines(&mut output, "", max_depth);
+        output
+    }
+
+    /// Appends the lines of render_ascii() for this subtree to `output`, every line but the first
+    /// one starting with `prefix`.
+    fn render_ascii_lines(&self, output: &mut String, prefix: &str, remaining_depth: usize) {
+        let short_hash = hex::encode(&self.get_root_hash().as_bytes()[..4]);
+        match self {
+            MerkleTree::Leaf{data: None, ..} => output.push_str(&format!("leaf {} (forgotten)\n", short_hash)),
+            MerkleTree::Leaf{data: Some(_), ..} => output.push_str(&format!("leaf {}\n", short_hash)),
+            MerkleTree::Node{..} if remaining_depth == 0 => output.push_str(&format!("node {} ...\n", short_hash)),
+            MerkleTree::Node{left, right, ..} => {
+                output.push_str(&format!("node {}\n", short_hash));
+                output.push_str(prefix);
+                output.push_str("|-- ");
+                left.render_ascii_lines(output, &format!("{}|   ", prefix), remaining_depth - 1);
+                output.push_str(prefix);
+                output.push_str("`-- ");
+                right.render_ascii_lines(output, &format!("{}    ", prefix), remaining_depth - 1);
             }
         }
     }
@@ -173,59 +515,150 @@ impl<T: AsRef<[u8]> + Clone> MerkleTree<T> {
 
     /// Export this Merkle Tree in an XML format. The XML can be stored somewhere else and
     /// this Merkle Tree shrunk by calling shrink_to_minimum() to save memory.
-    pub fn export_xml(&self) -> String {
-        // TODO: You may need the trait `LowerHex` for self
+    ///
+    /// Hashes are hex-encoded, the data is stored using LeafCodec::encode_string().
+    /// Use import_xml() to get the Merkle Tree back.
+    pub fn export_xml(&self) -> String where T : LeafCodec {
         match self {
-            MerkleTree::Leaf{hash, data: None /* TODO May need None() here? */} => format!("<leaf hash=\"{:x?}\" />", hash),
-            MerkleTree::Leaf{hash, data: Some(data)} => format!("<leaf hash=\"{:x?}\" data=\"{}\" />", hash, data.to_string()),
+            MerkleTree::Leaf{hash, data: None} => format!("<leaf hash=\"{}\" />", hash),
+            MerkleTree::Leaf{hash, data: Some(data)} =>
+                format!("<leaf hash=\"{}\" data=\"{}\" />", hash, xml_escape(&data.encode_string())),
             MerkleTree::Node{hash, left, right} =>
-                format!("<node hash=\"{:x?}\"><left>{}</left><right>{}</right></node>", hash, left.export_xml(), right.export_xml())
+                format!("<node hash=\"{}\"><left>{}</left><right>{}</right></node>",
+                        hash, left.export_xml(), right.export_xml())
         }
     }
 
+    /// Imports a Merkle Tree exported using export_xml(), including its forgotten leaves and
+    /// subtrees. The data is decoded using LeafCodec::decode_string().
+    ///
     /// Please note that the imported MerkleTree is NOT verified!!!
     /// You have to call verify() afterwards - especially when the XML is coming from an
     /// unreliable source!!!
     ///
-    /// Returns None when the input XML was invalid.
-    /*pub fn import_xml(xml : String) -> Option<MerkleTree<T>> {
-        return if xml.starts_with("<leaf hash=") {
-            // Import leaf
-            // TODO: Type annotations needed?
-            let hash: SHAHash = SHAHash::from_hex(xml_helper_parse_attr(&xml, "hash")).ok()?;
-            if xml.contains("data=") {
-                let data : T = T::from_str(xml_helper_parse_attr(&xml, "data")
-                    .as_str())
-                    .ok()?;
-                Some(MerkleTree::Leaf{
-                    hash,
-                    data: Some(data)
-                })
-            } else {
-                Some(MerkleTree::Leaf{
-                    hash,
-                    data: None
-                })
+    /// Returns an XmlError when the input XML was invalid, including trees nested deeper than
+    /// MAX_XML_DEPTH (which no Merkle Tree built by this crate comes anywhere close to).
+    pub fn import_xml(xml : &str) -> Result<MerkleTree<T>, XmlError> where T : LeafCodec {
+        let mut parser = XmlParser { xml, position: 0 };
+        let mtree = parser.parse_tree(0)?;
+        parser.skip_whitespace();
+        if parser.position < xml.len() {
+            return Err(XmlError::Unexpected { position: parser.position, expected: "end of input" });
+        }
+        Ok(mtree)
+    }
+
+    // ----- Exporting & Importing a MerkleTree as bytes: -----
+
+    /// Exports this Merkle Tree in a compact binary format, a space-efficient alternative to
+    /// export_xml(). Use from_bytes() to get the Merkle Tree back.
+    ///
+    /// The first byte is BYTES_FORMAT_VERSION, followed by the nodes and leaves in pre-order
+    /// (each node followed by its left and then its right subtree):
+    /// - Node: `0 <hash>`
+    /// - Forgotten leaf (or subtree): `1 <hash>`
+    /// - Leaf with data: `2 <hash> <length of data (u32 big endian)> <data>`
+    ///
+    /// The data is encoded using LeafCodec::encode(). Returns None when the encoded data of a
+    /// leaf is 4 GiB or bigger, as its length wouldn't fit.
+    pub fn to_bytes(&self) -> Option<Vec<u8>> where T : LeafCodec {
+        let mut bytes = vec![BYTES_FORMAT_VERSION];
+        let mut pending = vec![self];
+        while let Some(subtree) = pending.pop() {
+            match subtree {
+                MerkleTree::Node{hash, left, right} => {
+                    bytes.push(0);
+                    bytes.extend_from_slice(hash.as_bytes());
+                    pending.push(right);
+                    pending.push(left);
+                },
+                MerkleTree::Leaf{hash, data: None} => {
+                    bytes.push(1);
+                    bytes.extend_from_slice(hash.as_bytes());
+                },
+                MerkleTree::Leaf{hash, data: Some(data)} => {
+                    let data = data.encode();
+                    let length = u32::try_from(data.len()).ok()?;
+                    bytes.push(2);
+                    bytes.extend_from_slice(hash.as_bytes());
+                    bytes.extend_from_slice(&length.to_be_bytes());
+                    bytes.extend_from_slice(&data);
+                }
             }
-        } else if xml.starts_with("<node hash=") {
-            // Import node
-            let hash: SHAHash = SHAHash::from_hex(xml_helper_parse_attr(&xml, "hash")).ok()?;
-            // TODO: Annotated types required?
-            let left: MerkleTree<T> = MerkleTree::import_xml(xml_helper_parse_tag(&xml, "left"))?;
-            let right: MerkleTree<T> = MerkleTree::import_xml(xml_helper_parse_tag(&xml, "right"))?;
-            Some(MerkleTree::Node{
-                hash,
-                left: Box::new(left),
-                right: Box::new(right)
-            })
-        } else {
-            // Illegal xml
-            // TODO: Maybe panic?
-            None
         }
-    }*/
+        Some(bytes)
+    }
+
+    /// Imports a Merkle Tree exported using to_bytes(), including its forgotten leaves and
+    /// subtrees. The data is decoded using LeafCodec::decode().
+    ///
+    /// Just like import_xml(), the imported MerkleTree is NOT verified, so call verify()
+    /// afterwards when the bytes are coming from an unreliable source.
+    ///
+    /// Returns a BytesError when the input is invalid. Trees of any depth can be imported, as
+    /// the parser does not use recursion.
+    pub fn from_bytes(bytes: &[u8]) -> Result<MerkleTree<T>, BytesError> where T : LeafCodec {
+        let (version, mut rest) = bytes.split_first().ok_or(BytesError::UnexpectedEnd)?;
+        if *version != BYTES_FORMAT_VERSION {
+            return Err(BytesError::UnknownVersion(*version));
+        }
+        // Read all entries first, counting the subtrees that still have to follow: each node
+        // replaces one of them by its two children, each leaf completes one
+        let mut entries = Vec::new();
+        let mut missing = 1usize;
+        while missing > 0 {
+            let position = bytes.len() - rest.len();
+            let (tag, hash, after_hash) = match rest {
+                [tag, after_tag @ ..] if after_tag.len() >= 32 => {
+                    let (hash, after_hash) = after_tag.split_at(32);
+                    (*tag, hash.try_into().unwrap(), after_hash)
+                },
+                _ => return Err(BytesError::UnexpectedEnd)
+            };
+            rest = after_hash;
+            let entry = match tag {
+                0 => {
+                    missing += 1;
+                    None
+                },
+                1 => {
+                    missing -= 1;
+                    Some(None)
+                },
+                2 => {
+                    missing -= 1;
+                    let length = rest.get(..4).ok_or(BytesError::UnexpectedEnd)?;
+                    let length = u32::from_be_bytes(length.try_into().unwrap()) as usize;
+                    let data = rest.get(4..4 + length).ok_or(BytesError::UnexpectedEnd)?;
+                    rest = &rest[4 + length..];
+                    let data = T::decode(data).ok_or(BytesError::InvalidData { position })?;
+                    Some(Some(data))
+                },
+                _ => return Err(BytesError::InvalidTag { position })
+            };
+            entries.push((hash, entry));
+        }
+        if !rest.is_empty() {
+            return Err(BytesError::TrailingBytes { position: bytes.len() - rest.len() });
+        }
+        // Build the tree from the back, so that the children of a node are always finished
+        // before it (the left one on top of the right one)
+        let mut finished : Vec<MerkleTree<T>> = Vec::new();
+        for (hash, entry) in entries.into_iter().rev() {
+            let subtree = match entry {
+                Some(data) => MerkleTree::Leaf { hash, data },
+                None => {
+                    let left = finished.pop().unwrap();
+                    let right = finished.pop().unwrap();
+                    MerkleTree::Node { hash, left: Box::new(left), right: Box::new(right) }
+                }
+            };
+            finished.push(subtree);
+        }
+        Ok(finished.pop().unwrap())
+    }
 
-    pub fn contains_hash(&self, search_hash: &SHAHash) -> bool {
+    pub fn contains_hash(&self, search_hash: &ShaHash) -> bool {
         match self {
             MerkleTree::Leaf{hash, ..} => hash == search_hash,
             MerkleTree::Node{hash, left, right} => {
@@ -236,32 +669,283 @@ impl<T: AsRef<[u8]> + Clone> MerkleTree<T> {
         }
     }
 
+    /// Returns the route from the root to the (leftmost) node or leaf with the given hash, i.e.
+    /// the side of its parent each node on the way is located on, starting below the root (so
+    /// the path to the root itself is empty). Returns None when there is no such node.
+    pub fn path_to(&self, search_hash: &ShaHash) -> Option<Vec<Direction>> {
+        let mut path = Vec::new();
+        if self.collect_path(search_hash, &mut path) {
+            path.reverse();
+            Some(path)
+        } else {
+            None
+        }
+    }
+
+    /// Looks for the node with the given hash and collects the directions on the way back up.
+    fn collect_path(&self, search_hash: &ShaHash, path: &mut Vec<Direction>) -> bool {
+        if self.get_root_hash() == *search_hash {
+            return true;
+        }
+        match self {
+            MerkleTree::Leaf{..} => false,
+            MerkleTree::Node{left, right, ..} => {
+                if left.collect_path(search_hash, path) {
+                    path.push(Direction::Left);
+                    true
+                } else if right.collect_path(search_hash, path) {
+                    path.push(Direction::Right);
+                    true
+                } else {
+                    false
+                }
+            }
+        }
+    }
+
+    // ----- Proofs: -----
+
+    /// Generates a proof that the given element is part of this Merkle Tree (see MerkleProof).
+    /// Returns None when the hash of the given element was not found in this Merkle Tree.
+    ///
+    /// The element itself does not have to be stored in this Merkle Tree anymore, its hash is
+    /// enough. Only the subtrees on the path to it must not have been forgotten.
+    pub fn generate_proof(&self, element: &T) -> Option<MerkleProof> {
+        self.generate_proof_with::<Sha256>(element)
+    }
+
+    /// Same as generate_proof(), but for a Merkle Tree created using new_with() with the hash
+    /// function D. Verify the proof using MerkleProof::verify_with() with the same D.
+    pub fn generate_proof_with<D : TreeHasher>(&self, element: &T) -> Option<MerkleProof> {
+        self.generate_proof_for_hash(&hash_leaf::<D>(element.as_ref()))
+    }
+
+    /// Same as generate_proof(), but for the leaf with the given hash, e.g. for a leaf whose data
+    /// is not known. Verify the proof using MerkleProof::calculate_root_hash().
+    pub fn generate_proof_for_hash(&self, leaf_hash: &ShaHash) -> Option<MerkleProof> {
+        let mut siblings = Vec::new();
+        if self.collect_proof(leaf_hash, &mut siblings) {
+            Some(MerkleProof { siblings })
+        } else {
+            None
+        }
+    }
+
+    /// Same as generate_proof_for_hash(), but for the leaf with the given index (counted from
+    /// left to right, as in get_leaf_hashes()), which also tells apart leaves storing equal data.
+    /// Returns None when there is no leaf with the given index.
+    pub fn generate_proof_for_index(&self, index: usize) -> Option<MerkleProof> {
+        if index >= self.leaf_count() {
+            return None;
+        }
+        // Walk down from the root, collecting the siblings in reverse order
+        let (mut node, mut index) = (self, index);
+        let mut siblings = Vec::new();
+        while let MerkleTree::Node{left, right, ..} = node {
+            let left_count = left.leaf_count();
+            if index < left_count {
+                siblings.push((Direction::Right, right.get_root_hash()));
+                node = left;
+            } else {
+                siblings.push((Direction::Left, left.get_root_hash()));
+                index -= left_count;
+                node = right;
+            }
+        }
+        siblings.reverse();
+        Some(MerkleProof { siblings })
+    }
+
+    /// Returns at most `limit` of the currently stored elements (see get_currently_stored_data()),
+    /// skipping the first `offset` ones, each together with a proof of its position (just like
+    /// generate_proof_for_index()). Only the leaves up to the last returned one are visited.
+    pub fn get_stored_data_page(&self, offset: usize, limit: usize) -> Vec<(T, MerkleProof)> {
+        let mut page = Vec::new();
+        let mut skip = offset;
+        self.collect_stored_data_page(&mut Vec::new(), &mut skip, limit, &mut page);
+        page
+    }
+
+    /// Walks the leaves from left to right for get_stored_data_page(), keeping the siblings of the
+    /// path from the root down to the current subtree in `path`.
+    fn collect_stored_data_page(&self, path: &mut Vec<(Direction, ShaHash)>, skip: &mut usize, limit: usize, page: &mut Vec<(T, MerkleProof)>) {
+        if page.len() >= limit {
+            return;
+        }
+        match self {
+            MerkleTree::Leaf{data: None, ..} => {},
+            MerkleTree::Leaf{data: Some(_), ..} if *skip > 0 => *skip -= 1,
+            MerkleTree::Leaf{data: Some(data), ..} => {
+                let siblings = path.iter().rev().copied().collect();
+                page.push((data.clone(), MerkleProof { siblings }));
+            },
+            MerkleTree::Node{left, right, ..} => {
+                path.push((Direction::Right, right.get_root_hash()));
+                left.collect_stored_data_page(path, skip, limit, page);
+                path.pop();
+                path.push((Direction::Left, left.get_root_hash()));
+                right.collect_stored_data_page(path, skip, limit, page);
+                path.pop();
+            }
+        }
+    }
+
+    /// Same as generate_proof_for_hash(), but also includes the data of the leaf in the proof
+    /// when it is stored and small enough according to the given ProofOptions (otherwise only its
+    /// hash), so a verifier asking for a small leaf does not have to fetch its data separately.
+    pub fn generate_leaf_proof(&self, leaf_hash: &ShaHash, options: &ProofOptions) -> Option<LeafProof> {
+        let proof = self.generate_proof_for_hash(leaf_hash)?;
+        let data = self.get_leaves().into_iter()
+            .find(|(hash, _)| hash == leaf_hash)
+            .and_then(|(_, data)| data)
+            .map(AsRef::as_ref)
+            .filter(|data| data.len() <= options.max_embedded_bytes);
+        let leaf = match data {
+            Some(data) => ProvenLeaf::Data(data.to_vec()),
+            None => ProvenLeaf::Hash(*leaf_hash)
+        };
+        Some(LeafProof { leaf, proof })
+    }
+
+    /// Generates a proof that the subtree with the given root hash is part of this Merkle Tree,
+    /// i.e. a proof like the one of a leaf, just starting higher up. Verify it by comparing
+    /// MerkleProof::calculate_root_hash() of the subtree's root hash to the root hash.
+    /// Returns None when there is no such subtree.
+    pub fn generate_proof_for_subtree(&self, subtree_hash: &ShaHash) -> Option<MerkleProof> {
+        let mut siblings = Vec::new();
+        if self.collect_subtree_proof(subtree_hash, &mut siblings) {
+            Some(MerkleProof { siblings })
+        } else {
+            None
+        }
+    }
+
+    /// Same as collect_proof(), but stops at any node with the given hash, not only at leaves.
+    fn collect_subtree_proof(&self, subtree_hash: &ShaHash, siblings: &mut Vec<(Direction, ShaHash)>) -> bool {
+        if self.get_root_hash() == *subtree_hash {
+            return true;
+        }
+        match self {
+            MerkleTree::Leaf{..} => false,
+            MerkleTree::Node{left, right, ..} => {
+                if left.collect_subtree_proof(subtree_hash, siblings) {
+                    siblings.push((Direction::Right, right.get_root_hash()));
+                    true
+                } else if right.collect_subtree_proof(subtree_hash, siblings) {
+                    siblings.push((Direction::Left, left.get_root_hash()));
+                    true
+                } else {
+                    false
+                }
+            }
+        }
+    }
+
+    /// Looks for the leaf with the given hash and collects the hashes of the siblings on the
+    /// way back up (from the leaf to the root).
+    /// Returns false when there is no leaf with the given hash in this Merkle Tree.
+    fn collect_proof(&self, leaf_hash: &ShaHash, siblings: &mut Vec<(Direction, ShaHash)>) -> bool {
+        match self {
+            MerkleTree::Leaf{hash, ..} => hash == leaf_hash,
+            MerkleTree::Node{left, right, ..} => {
+                if left.collect_proof(leaf_hash, siblings) {
+                    siblings.push((Direction::Right, right.get_root_hash()));
+                    true
+                } else if right.collect_proof(leaf_hash, siblings) {
+                    siblings.push((Direction::Left, left.get_root_hash()));
+                    true
+                } else {
+                    false
+                }
+            }
+        }
+    }
+
+    /// Generates a single proof that the leaves with the given indices (counted from left to
+    /// right, as in get_leaf_hashes()) are part of this Merkle Tree, see MultiProof.
+    /// Returns None when no index or an index beyond the last leaf was given.
+    ///
+    /// The order of the indices and duplicates do not matter: the proof always covers the
+    /// distinct leaves from left to right.
+    pub fn generate_multi_proof(&self, indices: &[usize]) -> Option<MultiProof> {
+        let mut indices = indices.to_vec();
+        indices.sort_unstable();
+        indices.dedup();
+        let mut steps = Vec::new();
+        let mut remaining = &indices[..];
+        self.collect_multi_proof(0, &mut remaining, &mut steps);
+        if indices.is_empty() || !remaining.is_empty() {
+            return None;
+        }
+        Some(MultiProof { steps })
+    }
+
+    /// Appends the steps of a MultiProof for this subtree, whose first leaf has the index
+    /// `offset`, to `steps`, consuming the (sorted) indices of the leaves in this subtree.
+    /// Returns the number of leaves of this subtree.
+    fn collect_multi_proof(&self, offset: usize, indices: &mut &[usize], steps: &mut Vec<ProofStep>) -> usize {
+        let before = (steps.len(), indices.len());
+        let leaf_count = match self {
+            MerkleTree::Leaf{..} => {
+                if indices.first() == Some(&offset) {
+                    *indices = &indices[1..];
+                    steps.push(ProofStep::Leaf);
+                }
+                1
+            },
+            MerkleTree::Node{left, right, ..} => {
+                steps.push(ProofStep::Node);
+                let left_count = left.collect_multi_proof(offset, indices, steps);
+                left_count + right.collect_multi_proof(offset + left_count, indices, steps)
+            }
+        };
+        if indices.len() == before.1 {
+            // No proven leaf in this subtree, so its hash is enough
+            steps.truncate(before.0);
+            steps.push(ProofStep::Hash(self.get_root_hash()));
+        }
+        leaf_count
+    }
+
     // ----- Grow/Restore: -----
 
     /// Tries to restore the given element back into this Merkle Tree.
     /// Returns true if the element was restored successfully or if it was already present.
     /// Returns false if the hash of the given element was not found in this Merkle Tree.
     /// If so, you probably have to use restore_subtree() instead.
-    pub fn restore_element(&mut self, element: &T) -> bool { // ToDo: avoid Copy
+    ///
+    /// The element is moved into this Merkle Tree (and dropped when it was already present), so
+    /// it is never copied.
+    pub fn restore_element(&mut self, element: T) -> bool {
+        self.restore_element_with::<Sha256>(element)
+    }
+
+    /// Same as restore_element(), but for a Merkle Tree created using new_with() with the hash
+    /// function D.
+    pub fn restore_element_with<D : TreeHasher>(&mut self, element: T) -> bool {
         // Calculate the hash of the given element
-        let element_hash = SHAHash::from(Sha256::new()
-            .chain(element.clone())
-            .finalize());
+        let element_hash = hash_leaf::<D>(element.as_ref());
+        self.restore_leaf(&element_hash, element).is_ok()
+    }
+
+    /// Stores the given element in the leftmost leaf with the given hash (unless it stores its
+    /// data already). Gives the element back when there is no such leaf.
+    fn restore_leaf(&mut self, element_hash: &ShaHash, element: T) -> Result<(), T> {
         match self {
-            MerkleTree::Leaf{hash, ..} if hash == element_hash => {
-                *self = MerkleTree::Leaf{
-                    hash,
-                    data: Some(element.clone())
-                };
-                true
+            MerkleTree::Leaf{hash, data} if hash == element_hash => {
+                if data.is_none() {
+                    *data = Some(element);
+                }
+                Ok(())
             },
             MerkleTree::Node{left, right, ..} => {
                 // Try to restore the element in the left, then in the right subtree
-                left.restore_element(element) || right.restore_element(element)
+                left.restore_leaf(element_hash, element)
+                    .or_else(|element| right.restore_leaf(element_hash, element))
             },
             _ => {
                 // No matching node or leaf found for restoring
-                false
+                Err(element)
             }
         }
     }
@@ -281,7 +965,7 @@ impl<T: AsRef<[u8]> + Clone> MerkleTree<T> {
             return true;
         }
 
-        return match self {
+        match self {
             MerkleTree::Leaf{..} => {
                 // The given subtree does *not* match with the hash of self. If the subtree
                 // is a MerkleTree::Leaf, then the hashes should have matched. If the subtree
@@ -291,14 +975,14 @@ impl<T: AsRef<[u8]> + Clone> MerkleTree<T> {
             },
             MerkleTree::Node{left, right, ..} => {
                 if left.get_root_hash() == subtree.get_root_hash() {
-                    *left = Box::new(subtree);
+                    **left = subtree;
                     true
                 } else if right.get_root_hash() == subtree.get_root_hash() {
-                    *right = Box::new(subtree);
+                    **right = subtree;
                     true
-                } else if left.contains_hash(subtree.get_root_hash()) {
+                } else if left.contains_hash(&subtree.get_root_hash()) {
                     left.insert_subtree(subtree)
-                } else if right.contains_hash(subtree.get_root_hash()) {
+                } else if right.contains_hash(&subtree.get_root_hash()) {
                     right.insert_subtree(subtree)
                 } else {
                     // Could not insert subtree as left or right subtree or in them.
@@ -319,44 +1003,194 @@ impl<T: AsRef<[u8]> + Clone> MerkleTree<T> {
     /// is lost!
     ///
     /// Returns false if the root hash of the Merkle Tree given was not found in this Merkle Tree.
-    /// If so, you probably have to restore a bigger subtree!
+    /// If so, you probably have to restore a bigger subtree! Also returns false when the given
+    /// subtree turns out to be inconsistent with this Merkle Tree (see MergeError::Inconsistent).
     ///
     /// Please note that the given MerkleTree is NOT checked for validity!
     /// That has to be done beforehand if it's coming from an unreliable source!
     pub fn restore_subtree(&mut self, subtree : MerkleTree<T>) -> bool {
-        false // ToDo
-    }
-
-    // ----- Shrink: -----
+        let subtree_hash = subtree.get_root_hash();
+        if self.get_root_hash() == subtree_hash {
+            return self.merge_from(subtree).is_ok();
+        }
 
-    /// Shrinks this MerkleTree to its minimum size, leaving only its root hash.
-    pub fn shrink_to_minimum(&mut self) {
-        *self = MerkleTree::Leaf{
-            hash: self.get_root_hash(),
-            data: None
-        };
+        match self {
+            MerkleTree::Leaf{..} => false, // hash not found!
+            MerkleTree::Node{left, right, ..} => {
+                if left.contains_hash(&subtree_hash) {
+                    left.restore_subtree(subtree)
+                } else if right.contains_hash(&subtree_hash) {
+                    right.restore_subtree(subtree)
+                } else {
+                    false
+                }
+            }
+        }
     }
 
-    /// Looks for an element in this Merkle Tree that's equal to the given element and 'forgets'
-    /// it, i.e. deletes it from this Merkle Tree (but keeps the hash).
-    /// The element can be restored later by calling restore_element().
-    /// However, when larger parts of this Merkle Tree were thrown away, a restore_subtree()
-    /// might be necessary.
+    /// Combines this Merkle Tree with another one with the same root hash (e.g. two differently
+    /// pruned copies of the same tree), taking the data from whichever of them stores it. This
+    /// complements restore_subtree() for the case that neither of them stores everything the
+    /// other one does.
     ///
-    /// Returns false when no element equal to the given element was found in this Merkle Tree or
-    /// when it already is forgotten (i.e. only its hash still being there).
+    /// This Merkle Tree is left unchanged when a MergeError is returned.
     ///
-    /// When there are multiple element in this Merkle Tree equal to the given one
-    /// (which actually shouldn't be the case for most sensible Blockchain applications)
+    /// Just like restore_subtree(), the trees are NOT checked for validity, so call verify() on
+    /// the result when one of them is coming from an unreliable source.
+    pub fn merge(&mut self, other: MerkleTree<T>) -> Result<(), MergeError> {
+        if self.get_root_hash() != other.get_root_hash() {
+            return Err(MergeError::DifferentRoots);
+        }
+        self.merge_from(other)
+    }
+
+    /// Merges the given Merkle Tree with the same root hash into this one, keeping everything
+    /// that is stored in either of them (see merge()). Nothing is changed when they turn out to
+    /// be inconsistent.
+    fn merge_from(&mut self, other : MerkleTree<T>) -> Result<(), MergeError> {
+        self.check_mergeable(&other)?;
+        self.merge_checked(other);
+        Ok(())
+    }
+
+    /// Checks whether the given Merkle Tree with the same root hash can be merged into this one,
+    /// i.e. whether the parts stored by both of them have the same structure and hashes.
+    fn check_mergeable(&self, other : &MerkleTree<T>) -> Result<(), MergeError> {
+        match (self, other) {
+            (MerkleTree::Leaf{data: None, ..}, _) | (_, MerkleTree::Leaf{data: None, ..}) => Ok(()),
+            (MerkleTree::Leaf{..}, MerkleTree::Leaf{..}) => Ok(()),
+            (MerkleTree::Node{left, right, ..}, MerkleTree::Node{left: other_left, right: other_right, ..}) => {
+                if left.get_root_hash() != other_left.get_root_hash() || right.get_root_hash() != other_right.get_root_hash() {
+                    return Err(MergeError::Inconsistent { hash: self.get_root_hash() });
+                }
+                left.check_mergeable(other_left)?;
+                right.check_mergeable(other_right)
+            },
+            _ => {
+                // A leaf storing data in one tree is a node in the other one
+                Err(MergeError::Inconsistent { hash: self.get_root_hash() })
+            }
+        }
+    }
+
+    /// Merges the given Merkle Tree into this one after check_mergeable() succeeded.
+    fn merge_checked(&mut self, other : MerkleTree<T>) {
+        match (&mut *self, other) {
+            (MerkleTree::Leaf{data: None, ..}, other) => {
+                // Self knows nothing but the hash, so the other tree knows at least as much
+                *self = other;
+            },
+            (MerkleTree::Node{left, right, ..}, MerkleTree::Node{left: other_left, right: other_right, ..}) => {
+                left.merge_checked(*other_left);
+                right.merge_checked(*other_right);
+            },
+            _ => {
+                // Either the other tree knows nothing but the hash of this subtree or both store
+                // the data of this leaf
+            }
+        }
+    }
+
+    /// Asks the given LeafProvider for the data of every forgotten leaf and stores whatever it
+    /// returns, as long as it matches the hash of the leaf (so the provider does not have to be
+    /// trusted). Returns the number of leaves restored.
+    pub fn restore_all<P : LeafProvider<T>>(&mut self, provider: &P) -> usize {
+        self.restore_all_with::<Sha256, P>(provider)
+    }
+
+    /// Same as restore_all(), but for a Merkle Tree created using new_with() with the hash
+    /// function D.
+    pub fn restore_all_with<D : TreeHasher, P : LeafProvider<T>>(&mut self, provider: &P) -> usize {
+        match self {
+            MerkleTree::Leaf{hash, data: data @ None} => {
+                match provider.fetch(hash) {
+                    Some(element) if hash_leaf::<D>(element.as_ref()) == *hash => {
+                        *data = Some(element);
+                        1
+                    },
+                    _ => 0
+                }
+            },
+            MerkleTree::Leaf{..} => 0,
+            MerkleTree::Node{left, right, ..} =>
+                left.restore_all_with::<D, P>(provider) + right.restore_all_with::<D, P>(provider)
+        }
+    }
+
+    // ----- Update: -----
+
+    /// Replaces the leaf with the given index (counted from left to right, as in
+    /// get_leaf_hashes()) by a leaf storing the given data, recalculating only the hashes on the
+    /// path to the root. Returns the new root hash, or None (without changing anything) when
+    /// there is no leaf with the given index.
+    ///
+    /// Please note that a forgotten subtree counts as a single leaf, so it is replaced as a whole.
+    pub fn update_leaf(&mut self, index: usize, new_data: T) -> Option<ShaHash> {
+        self.update_leaf_with::<Sha256>(index, new_data)
+    }
+
+    /// Same as update_leaf(), but for a Merkle Tree created using new_with() with the hash
+    /// function D.
+    pub fn update_leaf_with<D : TreeHasher>(&mut self, index: usize, new_data: T) -> Option<ShaHash> {
+        match self {
+            _ if self.is_empty() => return None,
+            MerkleTree::Leaf{hash, data} if index == 0 => {
+                *hash = hash_leaf::<D>(new_data.as_ref());
+                *data = Some(new_data);
+            },
+            MerkleTree::Leaf{..} => return None,
+            MerkleTree::Node{hash, left, right} => {
+                let left_count = left.leaf_count();
+                if index < left_count {
+                    left.update_leaf_with::<D>(index, new_data)?;
+                } else {
+                    right.update_leaf_with::<D>(index - left_count, new_data)?;
+                }
+                *hash = hash_node::<D>(left.get_root_hash(), right.get_root_hash());
+            }
+        }
+        Some(self.get_root_hash())
+    }
+
+    /// Returns the number of leaves of this Merkle Tree, a forgotten subtree counting as one.
+    fn leaf_count(&self) -> usize {
+        match self {
+            _ if self.is_empty() => 0,
+            MerkleTree::Leaf{..} => 1,
+            MerkleTree::Node{left, right, ..} => left.leaf_count() + right.leaf_count()
+        }
+    }
+
+    // ----- Shrink: -----
+
+    /// Shrinks this MerkleTree to its minimum size, leaving only its root hash.
+    pub fn shrink_to_minimum(&mut self) {
+        *self = MerkleTree::Leaf{
+            hash: self.get_root_hash(),
+            data: None
+        };
+    }
+
+    /// Looks for an element in this Merkle Tree that's equal to the given element and 'forgets'
+    /// it, i.e. deletes it from this Merkle Tree (but keeps the hash).
+    /// The element can be restored later by calling restore_element().
+    /// However, when larger parts of this Merkle Tree were thrown away, a restore_subtree()
+    /// might be necessary.
+    ///
+    /// Returns false when no element equal to the given element was found in this Merkle Tree or
+    /// when it already is forgotten (i.e. only its hash still being there).
+    ///
+    /// When there are multiple element in this Merkle Tree equal to the given one
+    /// (which actually shouldn't be the case for most sensible Blockchain applications)
     /// only the leftmost one is forgotten/deleted and true is returned.
-    pub fn forget_leaf(&mut self, element: &T) -> bool where T : PartialEq + Copy { // ToDo: avoid Copy
+    pub fn forget_leaf(&mut self, element: &T) -> bool where T : PartialEq {
         match self {
             MerkleTree::Leaf{data: None, ..} => {
                 // This MerkleTree is already forgotten
                 false
             },
-            MerkleTree::Leaf{hash, data: Some(data)} if data == element => {
-                *self = MerkleTree::Leaf{hash, data: None};
+            MerkleTree::Leaf{data, ..} if data.as_ref() == Some(element) => {
+                *data = None;
                 true
             },
             MerkleTree::Node{left, right, ..} => {
@@ -369,6 +1203,55 @@ impl<T: AsRef<[u8]> + Clone> MerkleTree<T> {
         }
     }
 
+    /// Forgets the data of all leaves for which the given predicate returns true (e.g. all
+    /// transactions older than a certain date), in a single traversal from left to right.
+    /// Returns the number of leaves forgotten.
+    pub fn forget_where<P : FnMut(&T) -> bool>(&mut self, mut predicate: P) -> usize {
+        self.forget_where_recursive(&mut predicate)
+    }
+
+    fn forget_where_recursive<P : FnMut(&T) -> bool>(&mut self, predicate: &mut P) -> usize {
+        match self {
+            MerkleTree::Leaf{data, ..} => {
+                if data.as_ref().is_some_and(&mut *predicate) {
+                    *data = None;
+                    1
+                } else {
+                    0
+                }
+            },
+            MerkleTree::Node{left, right, ..} =>
+                left.forget_where_recursive(predicate) + right.forget_where_recursive(predicate)
+        }
+    }
+
+    /// Forgets the data of all leaves that doesn't match the hash of its leaf (e.g. because it
+    /// was corrupted in storage), which leaves the hashes of the tree intact so the data can be
+    /// restored later. Returns the hashes of these leaves, from left to right.
+    pub fn forget_corrupted(&mut self) -> Vec<ShaHash> {
+        self.forget_corrupted_with::<Sha256>()
+    }
+
+    /// Same as forget_corrupted(), but for a Merkle Tree created using new_with() with the hash
+    /// function D.
+    pub fn forget_corrupted_with<D : TreeHasher>(&mut self) -> Vec<ShaHash> {
+        match self {
+            MerkleTree::Leaf{hash, data} => {
+                if data.as_ref().is_some_and(|element| hash_leaf::<D>(element.as_ref()) != *hash) {
+                    *data = None;
+                    vec![*hash]
+                } else {
+                    Vec::new()
+                }
+            },
+            MerkleTree::Node{left, right, ..} => {
+                let mut corrupted = left.forget_corrupted_with::<D>();
+                corrupted.append(&mut right.forget_corrupted_with::<D>());
+                corrupted
+            }
+        }
+    }
+
     /// Deletes all the data stored in the leaves of this Merkle Tree but leaves the entire
     /// tree structure and all the hashes intact.
     /// This is a less severe operation than shrink_to_minimum, enabling all of the forgotten
@@ -379,10 +1262,10 @@ impl<T: AsRef<[u8]> + Clone> MerkleTree<T> {
     pub fn forget_all_leaves(&mut self) {
         // Use recursion:
         match self {
-            MerkleTree::Leaf(hash, _) => {
-                *self = MerkleTree::Leaf{hash, data: None}; // Forget Leaf (data)!
+            MerkleTree::Leaf{data, ..} => {
+                *data = None; // Forget Leaf (data)!
             },
-            MerkleTree::Node(_, left, right) => {
+            MerkleTree::Node{left, right, ..} => {
                 // Forget all leaves of the left and of the right subtree:
                 left.forget_all_leaves();
                 right.forget_all_leaves();
@@ -390,24 +1273,900 @@ impl<T: AsRef<[u8]> + Clone> MerkleTree<T> {
         }
     }
 
+    /// Keeps the hashes of the top `depth` levels of this Merkle Tree (below the root) and
+    /// deletes everything underneath them, i.e. every subtree whose root is `depth` edges below
+    /// the root of this Merkle Tree is shrunk to its root hash, and the data of all leaves above
+    /// them is forgotten.
+    /// This is a middle ground between forget_all_leaves() (a depth of at least depth()) and
+    /// shrink_to_minimum() (a depth of 0): the forgotten subtrees can be restored using
+    /// restore_subtree().
+    pub fn forget_below_depth(&mut self, depth: usize) {
+        match self {
+            MerkleTree::Node{..} if depth == 0 => self.shrink_to_minimum(),
+            MerkleTree::Node{left, right, ..} => {
+                left.forget_below_depth(depth - 1);
+                right.forget_below_depth(depth - 1);
+            },
+            MerkleTree::Leaf{data, ..} => {
+                *data = None;
+            }
+        }
+    }
+
     /// Deletes the subtree of this Merkle Tree that has the given hash as its root hash.
     /// The root hash itself is kept!
     /// Returns false when this Merkle Tree (currently) does not have a subtree with that hash.
     ///
     /// Calling mtree.forget_subtree(mtree.get_root_hash()) is equivalent to calling
     /// mtree.shrink_to_minimum().
-    pub fn forget_subtree(&mut self, hash : SHAHash) -> bool {
+    pub fn forget_subtree(&mut self, hash : ShaHash) -> bool {
         if hash == self.get_root_hash() {
             self.shrink_to_minimum();
             true
         } else {
             match self {
-                MerkleTree::Leaf(_,_) => false, // hash not found!
-                MerkleTree::Node(_, left, right) =>
+                MerkleTree::Leaf{..} => false, // hash not found!
+                MerkleTree::Node{left, right, ..} =>
                     {
                         left.forget_subtree(hash) || right.forget_subtree(hash)
                     }
             }
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(feature = "parallel")]
+impl<T : AsRef<[u8]> + Clone + Send + Sync> MerkleTree<T> {
+
+    /// Same as new(), but builds the subtrees of big Merkle Trees in parallel on the rayon thread
+    /// pool (requires the "parallel" feature). The resulting Merkle Tree is exactly the same.
+    ///
+    /// An empty Vec results in the empty Merkle Tree (see empty()).
+    pub fn new_parallel(data: &[T]) -> MerkleTree<T> {
+        Self::try_new_parallel_with::<Sha256>(data).unwrap_or_else(|_| Self::empty())
+    }
+
+    /// Same as new_parallel(), but hashes the leaves and nodes using the hash function D (see
+    /// new_with()) and returns MerkleTreeError::Empty instead of the empty Merkle Tree when called
+    /// on an empty Vec.
+    pub fn try_new_parallel_with<D : TreeHasher>(data: &[T]) -> Result<MerkleTree<T>, MerkleTreeError> {
+        // Below this many leaves, handing the subtrees to other threads costs more than it saves
+        const MIN_PARALLEL_LEAVES : usize = 1024;
+
+        if data.len() < MIN_PARALLEL_LEAVES {
+            return Self::try_new_with::<D>(data);
+        }
+        // Split the data in two equally sized subtrees, just like try_new_with()
+        let (left_part, right_part) = data.split_at(data.len()/2);
+        let (left_subtree, right_subtree) = rayon::join(
+            || Self::try_new_parallel_with::<D>(left_part),
+            || Self::try_new_parallel_with::<D>(right_part)
+        );
+        let (left_subtree, right_subtree) = (left_subtree?, right_subtree?);
+        Ok(MerkleTree::Node {
+            hash: hash_node::<D>(left_subtree.get_root_hash(), right_subtree.get_root_hash()),
+            left: Box::new(left_subtree),
+            right: Box::new(right_subtree)
+        })
+    }
+}
+
+/// Builds a Merkle Tree from data that arrives one element at a time (e.g. from a stream),
+/// without collecting all of it into a Vec first.
+///
+/// The hashes are calculated right away using a Merkle Mountain Range (see Mmr), i.e. pushing an
+/// element takes O(log n) time, and build() finally joins its peaks from right to left.
+///
+/// The resulting tree has the largest possible complete subtree on the left at each level
+/// (like the Merkle Trees of RFC 6962), which is different from the shape of MerkleTree::new()
+/// unless the number of elements is a power of two. Its root hash therefore differs from the one
+/// of MerkleTree::new() for the same data in all other cases.
+#[derive(Clone, Debug)]
+pub struct MerkleTreeBuilder<T : AsRef<[u8]> + Clone> {
+    mmr: Mmr<T>
+}
+
+impl<T : AsRef<[u8]> + Clone> MerkleTreeBuilder<T> {
+
+    /// Creates a new builder without any elements.
+    pub fn new() -> MerkleTreeBuilder<T> {
+        MerkleTreeBuilder { mmr: Mmr::new() }
+    }
+
+    /// Appends the given element as the rightmost leaf.
+    pub fn push(&mut self, element: T) {
+        self.mmr.push(element);
+    }
+
+    /// Returns the number of elements pushed so far.
+    pub fn len(&self) -> usize {
+        self.mmr.len()
+    }
+
+    /// Returns true when no element was pushed yet.
+    pub fn is_empty(&self) -> bool {
+        self.mmr.is_empty()
+    }
+
+    /// Finishes the Merkle Tree.
+    /// Returns MerkleTreeError::Empty when no element was pushed.
+    pub fn build(self) -> Result<MerkleTree<T>, MerkleTreeError> {
+        self.mmr.into_merkle_tree().ok_or(MerkleTreeError::Empty)
+    }
+}
+
+impl<T : AsRef<[u8]> + Clone> Default for MerkleTreeBuilder<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Holds the data of a Merkle Tree, but only calculates its hashes once they are needed (by
+/// get_root_hash() or to_merkle_tree()) and caches them, so that changing the data in between
+/// doesn't cost any hashing - unlike MerkleTree::new() and update_leaf(), which hash right away.
+///
+/// Changing an element only invalidates the hashes of the subtrees containing it, so that the
+/// next root hash costs O(log n) hashes. The resulting Merkle Tree has the same shape and hashes as
+/// the one of MerkleTree::new_with() for the same data.
+#[derive(Clone, Debug)]
+pub struct LazyMerkleTree<T : AsRef<[u8]> + Clone> {
+    data: Vec<T>,
+    /// The hashes calculated so far, by the range of the data their subtree covers. Whenever a
+    /// hash is cached, so are the ones of all subtrees below it.
+    cache: RefCell<HashMap<(usize, usize), ShaHash>>,
+    /// Whether the number of elements changed since the hashes were calculated, so that some of
+    /// the cached hashes may belong to subtrees the tree doesn't have anymore.
+    reshaped: Cell<bool>,
+    hash_leaf: fn(&[u8]) -> ShaHash,
+    hash_node: fn(ShaHash, ShaHash) -> ShaHash
+}
+
+impl<T : AsRef<[u8]> + Clone> LazyMerkleTree<T> {
+
+    /// Creates a lazy Merkle Tree of the given data (which may be empty), without hashing
+    /// anything yet.
+    pub fn new(data: Vec<T>) -> LazyMerkleTree<T> {
+        Self::new_with::<Sha256>(data)
+    }
+
+    /// Same as new(), but hashes the leaves and nodes using D (see MerkleTree::new_with()).
+    pub fn new_with<D : TreeHasher>(data: Vec<T>) -> LazyMerkleTree<T> {
+        LazyMerkleTree {
+            data,
+            cache: RefCell::new(HashMap::new()),
+            reshaped: Cell::new(false),
+            hash_leaf: hash_leaf::<D>,
+            hash_node: hash_node::<D>
+        }
+    }
+
+    /// Returns the number of elements.
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    /// Returns true when there are no elements.
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    /// Returns all elements, from left to right.
+    pub fn data(&self) -> &[T] {
+        &self.data
+    }
+
+    /// Appends the given element as the rightmost leaf.
+    pub fn push(&mut self, element: T) {
+        self.data.push(element);
+        self.reshaped.set(true);
+    }
+
+    /// Replaces the element at the given index, returning the old one.
+    /// Returns None (and changes nothing) when the index is out of range.
+    pub fn update(&mut self, index: usize, element: T) -> Option<T> {
+        let old = std::mem::replace(self.data.get_mut(index)?, element);
+        self.cache.get_mut().retain(|(start, end), _| !(*start <= index && index < *end));
+        Some(old)
+    }
+
+    /// Returns the root hash, calculating only the hashes that aren't cached.
+    /// Returns None when there are no elements.
+    pub fn get_root_hash(&self) -> Option<ShaHash> {
+        if self.data.is_empty() {
+            return None;
+        }
+        let mut cache = self.cache.borrow_mut();
+        if self.reshaped.replace(false) {
+            // Only keep the hashes of the subtrees of the current shape
+            let previous = std::mem::take(&mut *cache);
+            Some(self.range_hash(&mut cache, Some(&previous), 0, self.data.len()))
+        } else {
+            Some(self.range_hash(&mut cache, None, 0, self.data.len()))
+        }
+    }
+
+    /// Creates the Merkle Tree of the data, reusing the cached hashes.
+    /// Returns MerkleTreeError::Empty when there are no elements.
+    pub fn to_merkle_tree(&self) -> Result<MerkleTree<T>, MerkleTreeError> {
+        self.get_root_hash().ok_or(MerkleTreeError::Empty)?;
+        Ok(self.build_subtree(&self.cache.borrow(), 0, self.data.len()))
+    }
+
+    /// Returns the hash of the subtree covering the given range of the data, looking it up in the
+    /// cache first and then in the previous cache (whose hashes are still correct, but which may
+    /// contain hashes of subtrees that don't exist anymore).
+    fn range_hash(&self, cache: &mut HashMap<(usize, usize), ShaHash>, previous: Option<&HashMap<(usize, usize), ShaHash>>,
+                  start: usize, end: usize) -> ShaHash {
+        if let Some(hash) = cache.get(&(start, end)) {
+            return *hash;
+        }
+        let cached = previous.and_then(|previous| previous.get(&(start, end)).copied());
+        let hash = if end - start == 1 {
+            cached.unwrap_or_else(|| (self.hash_leaf)(self.data[start].as_ref()))
+        } else {
+            // Split like MerkleTree::new() does
+            let middle = start + (end - start) / 2;
+            let left = self.range_hash(cache, previous, start, middle);
+            let right = self.range_hash(cache, previous, middle, end);
+            cached.unwrap_or_else(|| (self.hash_node)(left, right))
+        };
+        cache.insert((start, end), hash);
+        hash
+    }
+
+    fn build_subtree(&self, cache: &HashMap<(usize, usize), ShaHash>, start: usize, end: usize) -> MerkleTree<T> {
+        let hash = cache[&(start, end)];
+        if end - start == 1 {
+            return MerkleTree::Leaf { hash, data: Some(self.data[start].clone()) };
+        }
+        let middle = start + (end - start) / 2;
+        MerkleTree::Node {
+            hash,
+            left: Box::new(self.build_subtree(cache, start, middle)),
+            right: Box::new(self.build_subtree(cache, middle, end))
+        }
+    }
+}
+
+/// Something the data of forgotten leaves can be fetched from by the hash of the leaf, e.g. a
+/// disk cache or other nodes, see MerkleTree::restore_all().
+pub trait LeafProvider<T> {
+    /// Returns the data of the leaf with the given hash, or None when it is not available.
+    fn fetch(&self, leaf_hash: &ShaHash) -> Option<T>;
+}
+
+impl<T : Clone> LeafProvider<T> for HashMap<ShaHash, T> {
+    fn fetch(&self, leaf_hash: &ShaHash) -> Option<T> {
+        self.get(leaf_hash).cloned()
+    }
+}
+
+/// A node or leaf of a Merkle Tree, as passed to the visitor of MerkleTree::visit_nodes().
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct NodeInfo<'a, T> {
+    /// The hash of the node or leaf.
+    pub hash: ShaHash,
+    /// The number of edges from the root to it (0 for the root).
+    pub depth: usize,
+    /// Whether it is an inner node or a leaf.
+    pub kind: NodeKind<'a, T>
+}
+
+/// Whether a NodeInfo is about an inner node or about a leaf.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NodeKind<'a, T> {
+    /// A node with two children.
+    Inner,
+    /// A leaf, together with its data (None when it is forgotten, which may also be a forgotten
+    /// subtree, see MerkleTree::get_leaf_hashes()).
+    Leaf { data: Option<&'a T> }
+}
+
+/// Statistics about a Merkle Tree, see MerkleTree::stats().
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TreeStats {
+    /// The number of edges on the longest path from the root to a leaf.
+    pub depth: usize,
+    /// The number of nodes, including the leaves.
+    pub node_count: usize,
+    /// The number of leaves currently storing their data.
+    pub stored_leaf_count: usize,
+    /// The number of leaves whose data is forgotten. A subtree that was forgotten entirely counts
+    /// as a single leaf (see MerkleTree::get_leaf_hashes()).
+    pub forgotten_leaf_count: usize,
+    /// The total size of the stored data in bytes.
+    pub stored_bytes: usize
+}
+
+/// The differences between two Merkle Trees, see MerkleTree::diff().
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TreeDiff {
+    /// The paths from the root to the subtrees that have different hashes in both trees.
+    /// Their contents are not compared any further. Trees with different root hashes only have
+    /// a single differing subtree: the root itself (the empty path).
+    pub differing_subtrees: Vec<Vec<Direction>>,
+    /// The hashes of the leaves whose data is stored in the other tree but not in this one.
+    pub missing_in_self: Vec<ShaHash>,
+    /// The hashes of the leaves whose data is stored in this tree but not in the other one.
+    pub missing_in_other: Vec<ShaHash>
+}
+
+impl TreeDiff {
+    /// Returns true when the two trees are the same, i.e. have the same hashes and store the same
+    /// data (but not necessarily the same structure, e.g. forgotten subtrees without any data).
+    pub fn is_empty(&self) -> bool {
+        self.differing_subtrees.is_empty() && self.missing_in_self.is_empty() && self.missing_in_other.is_empty()
+    }
+}
+
+/// The side of its parent node on which a node of a Merkle Tree is located.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Direction {
+    Left,
+    Right
+}
+
+/// A proof that an element is part of a Merkle Tree with a certain root hash (also known as
+/// "audit path"), see MerkleTree::generate_proof().
+///
+/// The proof only consists of the hashes of the siblings on the path from the element up to the
+/// root, so it can be checked by someone who knows nothing but the root hash (e.g. from a
+/// Block header) and the element itself. This is the Simplified Payment Verification described
+/// in section 8 of the Bitcoin paper (https://bitcoin.org/bitcoin.pdf).
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct MerkleProof {
+    /// The hashes of the siblings on the path from the leaf up to the root, each together with
+    /// the side of the parent node the sibling is located on.
+    pub(crate) siblings: Vec<(Direction, ShaHash)>
+}
+
+impl MerkleProof {
+
+    /// Recalculates the root hash of the Merkle Tree from the hash of the proven leaf.
+    pub fn calculate_root_hash(&self, leaf_hash: ShaHash) -> ShaHash {
+        self.calculate_root_hash_with::<Sha256>(leaf_hash)
+    }
+
+    /// Same as calculate_root_hash(), but for a Merkle Tree using the hash function D
+    /// (see MerkleTree::new_with()).
+    pub fn calculate_root_hash_with<D : TreeHasher>(&self, leaf_hash: ShaHash) -> ShaHash {
+        self.siblings.iter().fold(leaf_hash, |hash, (side, sibling)| {
+            match side {
+                Direction::Left => hash_node::<D>(*sibling, hash),
+                Direction::Right => hash_node::<D>(hash, *sibling)
+            }
+        })
+    }
+
+    /// Checks whether this proof shows that the given element is part of the Merkle Tree with
+    /// the given root hash.
+    pub fn verify<T : AsRef<[u8]>>(&self, root_hash: ShaHash, element: &T) -> bool {
+        self.verify_with::<Sha256, T>(root_hash, element)
+    }
+
+    /// Same as verify(), but for a Merkle Tree using the hash function D
+    /// (see MerkleTree::new_with()).
+    pub fn verify_with<D : TreeHasher, T : AsRef<[u8]>>(&self, root_hash: ShaHash, element: &T) -> bool {
+        self.calculate_root_hash_with::<D>(hash_leaf::<D>(element.as_ref())) == root_hash
+    }
+
+    /// Returns the sides of the siblings from the root down (instead of from the leaf up), which
+    /// describes where the proven leaf is located in the tree.
+    pub(crate) fn sides_from_root(&self) -> Vec<Direction> {
+        self.siblings.iter().rev().map(|(side, _)| *side).collect()
+    }
+}
+
+/// Checks whether the leaves whose siblings are on the given sides (from the root down, see
+/// MerkleProof::sides_from_root()) are right next to each other: after splitting at a common
+/// node, the left leaf is the rightmost one of the left subtree and the right leaf the leftmost
+/// one of the right subtree.
+pub(crate) fn adjacent(left: &[Direction], right: &[Direction]) -> bool {
+    let common = left.iter().zip(right).take_while(|(a, b)| a == b).count();
+    match (left.get(common), right.get(common)) {
+        (Some(Direction::Right), Some(Direction::Left)) => {
+            left[common + 1..].iter().all(|side| *side == Direction::Left)
+                && right[common + 1..].iter().all(|side| *side == Direction::Right)
+        },
+        _ => false
+    }
+}
+
+/// Controls what MerkleTree::generate_leaf_proof() includes in a proof.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ProofOptions {
+    /// The data of leaves up to this size in bytes is included, bigger leaves only by their hash.
+    pub max_embedded_bytes: usize
+}
+
+impl Default for ProofOptions {
+    /// Includes the data of leaves up to 1 KiB.
+    fn default() -> Self {
+        ProofOptions { max_embedded_bytes: 1024 }
+    }
+}
+
+/// The leaf proven by a LeafProof: its data, or only its hash when the data is too big (or not
+/// stored by whoever generated the proof).
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum ProvenLeaf {
+    Data(Vec<u8>),
+    Hash(ShaHash)
+}
+
+/// A MerkleProof together with the leaf it proves, see MerkleTree::generate_leaf_proof().
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct LeafProof {
+    /// The proven leaf.
+    pub leaf: ProvenLeaf,
+    /// The proof of the leaf.
+    pub proof: MerkleProof
+}
+
+impl LeafProof {
+
+    /// Returns the data of the proven leaf, or None when only its hash is included.
+    /// The data is only trustworthy once verify() returned true.
+    pub fn data(&self) -> Option<&[u8]> {
+        match &self.leaf {
+            ProvenLeaf::Data(data) => Some(data),
+            ProvenLeaf::Hash(_) => None
+        }
+    }
+
+    /// Returns the hash of the proven leaf.
+    pub fn leaf_hash(&self) -> ShaHash {
+        self.leaf_hash_with::<Sha256>()
+    }
+
+    /// Same as leaf_hash(), but for a Merkle Tree using the hash function D.
+    pub fn leaf_hash_with<D : TreeHasher>(&self) -> ShaHash {
+        match &self.leaf {
+            ProvenLeaf::Data(data) => hash_leaf::<D>(data),
+            ProvenLeaf::Hash(hash) => *hash
+        }
+    }
+
+    /// Checks whether this proof shows that its leaf (whether its data or only its hash is
+    /// included) is part of the Merkle Tree with the given root hash.
+    pub fn verify(&self, root_hash: ShaHash) -> bool {
+        self.verify_with::<Sha256>(root_hash)
+    }
+
+    /// Same as verify(), but for a Merkle Tree using the hash function D
+    /// (see MerkleTree::new_with()).
+    pub fn verify_with<D : TreeHasher>(&self, root_hash: ShaHash) -> bool {
+        self.proof.calculate_root_hash_with::<D>(self.leaf_hash_with::<D>()) == root_hash
+    }
+}
+
+/// A proof that several leaves are part of a Merkle Tree, see MerkleTree::generate_multi_proof().
+///
+/// Compared to a MerkleProof per leaf, hashes shared by the paths of the leaves are only included
+/// once (and hashes of nodes on the paths not at all, as they are recalculated). The proof works
+/// for Merkle Trees of any shape: it describes the tree, pruned to the paths to the proven leaves,
+/// in pre-order.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct MultiProof {
+    steps: Vec<ProofStep>
+}
+
+/// A single node of the pruned tree described by a MultiProof.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+enum ProofStep {
+    /// A node on the path to a proven leaf, followed by its left and its right subtree.
+    Node,
+    /// A proven leaf, whose hash is calculated by the verifier.
+    Leaf,
+    /// A subtree not containing any of the proven leaves.
+    Hash(ShaHash)
+}
+
+impl MultiProof {
+
+    /// Returns the number of hashes included in this proof.
+    pub fn hash_count(&self) -> usize {
+        self.steps.iter().filter(|step| matches!(step, ProofStep::Hash(_))).count()
+    }
+
+    /// Recalculates the root hash of the Merkle Tree from the hashes of the proven leaves (from
+    /// left to right). Returns None when the number of leaf hashes does not match this proof.
+    pub fn calculate_root_hash(&self, leaf_hashes: &[ShaHash]) -> Option<ShaHash> {
+        self.calculate_root_hash_with::<Sha256>(leaf_hashes)
+    }
+
+    /// Same as calculate_root_hash(), but for a Merkle Tree using the hash function D
+    /// (see MerkleTree::new_with()).
+    pub fn calculate_root_hash_with<D : TreeHasher>(&self, leaf_hashes: &[ShaHash]) -> Option<ShaHash> {
+        // Going through the steps backwards, the subtrees of a node are known once it is reached
+        // (this avoids recursion, so a malicious proof cannot overflow the stack)
+        let mut leaf_hashes = leaf_hashes.iter().rev();
+        let mut stack = Vec::new();
+        for step in self.steps.iter().rev() {
+            let hash = match step {
+                ProofStep::Hash(hash) => *hash,
+                ProofStep::Leaf => *leaf_hashes.next()?,
+                ProofStep::Node => {
+                    let left = stack.pop()?;
+                    let right = stack.pop()?;
+                    hash_node::<D>(left, right)
+                }
+            };
+            stack.push(hash);
+        }
+        match (stack.pop(), stack.is_empty(), leaf_hashes.next()) {
+            (Some(root_hash), true, None) => Some(root_hash),
+            _ => None
+        }
+    }
+
+    /// Checks whether this proof shows that the given elements (from left to right) are part of
+    /// the Merkle Tree with the given root hash.
+    pub fn verify<T : AsRef<[u8]>>(&self, root_hash: ShaHash, elements: &[T]) -> bool {
+        self.verify_with::<Sha256, T>(root_hash, elements)
+    }
+
+    /// Same as verify(), but for a Merkle Tree using the hash function D
+    /// (see MerkleTree::new_with()).
+    pub fn verify_with<D : TreeHasher, T : AsRef<[u8]>>(&self, root_hash: ShaHash, elements: &[T]) -> bool {
+        let leaf_hashes : Vec<ShaHash> = elements.iter().map(|element| hash_leaf::<D>(element.as_ref())).collect();
+        self.calculate_root_hash_with::<D>(&leaf_hashes) == Some(root_hash)
+    }
+}
+
+/// Double SHA-256, i.e. SHA-256 applied to the SHA-256 hash, as used by Bitcoin
+/// (see MerkleTree::new_bitcoin()).
+#[derive(Clone, Debug, Default)]
+pub struct Sha256d(Sha256);
+
+impl sha2::digest::Update for Sha256d {
+    fn update(&mut self, data: impl AsRef<[u8]>) {
+        Digest::update(&mut self.0, data);
+    }
+}
+
+impl sha2::digest::Reset for Sha256d {
+    fn reset(&mut self) {
+        Digest::reset(&mut self.0);
+    }
+}
+
+impl FixedOutputDirty for Sha256d {
+    type OutputSize = U32;
+
+    fn finalize_into_dirty(&mut self, out: &mut sha2::digest::Output<Sha256>) {
+        *out = Sha256::digest(&self.0.finalize_reset());
+    }
+}
+
+/// How the leaves and nodes of a Merkle Tree are hashed, i.e. the D of MerkleTree::new_with()
+/// and the other *_with() functions.
+///
+/// Every hash function with 256-bit outputs implementing Digest is a TreeHasher, hashing a leaf
+/// as `D(data)` and a node as `D(left || right)` - just like MerkleTree::new() does with SHA-256.
+/// With this, a node is indistinguishable from a leaf storing the 64 bytes `left || right`,
+/// which allows passing off a node as a leaf (a second preimage). Use DomainSeparated to rule
+/// that out.
+pub trait TreeHasher {
+    /// Returns the hash of a leaf storing the given data.
+    fn hash_leaf(data: &[u8]) -> ShaHash;
+
+    /// Returns the hash of a node with children with the given hashes.
+    fn hash_node(left: ShaHash, right: ShaHash) -> ShaHash;
+}
+
+impl<D : Digest<OutputSize = U32>> TreeHasher for D {
+    fn hash_leaf(data: &[u8]) -> ShaHash {
+        D::digest(data).into()
+    }
+
+    fn hash_node(left: ShaHash, right: ShaHash) -> ShaHash {
+        D::new().chain(left).chain(right).finalize().into()
+    }
+}
+
+/// Hashes leaves and nodes using the hash function D, but with different prefixes (like the
+/// Merkle Trees of RFC 6962): a leaf as `D(0x00 || data)` and a node as
+/// `D(0x01 || left || right)`, so that no leaf can have the hash of a node.
+///
+/// Merkle Trees hashed this way have different hashes than the ones created by MerkleTree::new(),
+/// so all participants have to agree on using it, e.g. via ChainParams::tree_version.
+pub struct DomainSeparated<D> {
+    hash_function: PhantomData<fn() -> D>
+}
+
+impl<D : Digest<OutputSize = U32>> TreeHasher for DomainSeparated<D> {
+    fn hash_leaf(data: &[u8]) -> ShaHash {
+        D::new().chain([0x00]).chain(data).finalize().into()
+    }
+
+    fn hash_node(left: ShaHash, right: ShaHash) -> ShaHash {
+        D::new().chain([0x01]).chain(left).chain(right).finalize().into()
+    }
+}
+
+/// Returns the hash of a leaf storing the given data.
+pub(crate) fn hash_leaf<D : TreeHasher>(data: &[u8]) -> ShaHash {
+    D::hash_leaf(data)
+}
+
+/// Returns the hash of a node with children with the given hashes.
+pub(crate) fn hash_node<D : TreeHasher>(left: ShaHash, right: ShaHash) -> ShaHash {
+    D::hash_node(left, right)
+}
+
+/// The reason why a MerkleTree could not be created, see MerkleTree::try_new().
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MerkleTreeError {
+    /// There is no data to create the MerkleTree from.
+    Empty
+}
+
+impl fmt::Display for MerkleTreeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MerkleTreeError::Empty => write!(f, "the data array is empty")
+        }
+    }
+}
+
+impl std::error::Error for MerkleTreeError {}
+
+/// The reason why MerkleTree::merge() failed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MergeError {
+    /// The two Merkle Trees have different root hashes.
+    DifferentRoots,
+    /// The subtrees with the given hash differ in their structure or in the hashes of their
+    /// children, so at least one of the Merkle Trees is invalid.
+    Inconsistent { hash : ShaHash }
+}
+
+impl fmt::Display for MergeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MergeError::DifferentRoots => write!(f, "the Merkle Trees have different root hashes"),
+            MergeError::Inconsistent { hash } => write!(f, "the Merkle Trees differ in the subtree {}", hash)
+        }
+    }
+}
+
+impl std::error::Error for MergeError {}
+
+/// How the data of the leaves is written by the exports of a Merkle Tree (export_xml() and
+/// to_bytes()) and read back by the imports (import_xml() and from_bytes()).
+///
+/// The hash of a leaf only covers its as_ref() bytes, which often aren't all of its data (e.g.
+/// just the signature of a transaction), so the exports can't rely on them.
+pub trait LeafCodec : Sized {
+    /// Encodes this data into bytes.
+    fn encode(&self) -> Vec<u8>;
+
+    /// Decodes data encoded using encode(). Returns None when the bytes are invalid.
+    fn decode(bytes: &[u8]) -> Option<Self>;
+
+    /// Encodes this data as text, for export_xml(). Defaults to encode() in hex.
+    fn encode_string(&self) -> String {
+        hex::encode(self.encode())
+    }
+
+    /// Decodes data encoded using encode_string(). Returns None when the text is invalid.
+    fn decode_string(text: &str) -> Option<Self> {
+        Self::decode(&hex::decode(text).ok()?)
+    }
+}
+
+impl LeafCodec for Vec<u8> {
+    fn encode(&self) -> Vec<u8> {
+        self.clone()
+    }
+
+    fn decode(bytes: &[u8]) -> Option<Self> {
+        Some(bytes.to_vec())
+    }
+}
+
+impl LeafCodec for String {
+    fn encode(&self) -> Vec<u8> {
+        self.as_bytes().to_vec()
+    }
+
+    fn decode(bytes: &[u8]) -> Option<Self> {
+        String::from_utf8(bytes.to_vec()).ok()
+    }
+
+    /// Text is stored as it is, so the XML stays readable.
+    fn encode_string(&self) -> String {
+        self.clone()
+    }
+
+    fn decode_string(text: &str) -> Option<Self> {
+        Some(text.to_string())
+    }
+}
+
+/// The reason why MerkleTree::import_xml() failed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum XmlError {
+    /// The XML ended before the Merkle Tree was complete.
+    UnexpectedEnd,
+    /// Something else than `expected` was found at the given byte offset.
+    Unexpected { position : usize, expected : &'static str },
+    /// The hash at the given byte offset is not 32 hex-encoded bytes.
+    InvalidHash { position : usize },
+    /// The data at the given byte offset could not be parsed (or contains an unknown entity).
+    InvalidData { position : usize },
+    /// The node at the given byte offset is nested deeper than MAX_XML_DEPTH.
+    TooDeep { position : usize }
+}
+
+impl fmt::Display for XmlError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            XmlError::UnexpectedEnd => write!(f, "unexpected end of XML"),
+            XmlError::Unexpected { position, expected } => write!(f, "expected {} at byte {}", expected, position),
+            XmlError::InvalidHash { position } => write!(f, "invalid hash at byte {}", position),
+            XmlError::InvalidData { position } => write!(f, "invalid data at byte {}", position),
+            XmlError::TooDeep { position } => write!(f, "tree nested too deeply at byte {}", position)
+        }
+    }
+}
+
+impl std::error::Error for XmlError {}
+
+/// The version of the format written by MerkleTree::to_bytes(), stored as its first byte.
+pub const BYTES_FORMAT_VERSION : u8 = 1;
+
+/// The reason why MerkleTree::from_bytes() failed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum BytesError {
+    /// The bytes ended before the Merkle Tree was complete.
+    UnexpectedEnd,
+    /// The bytes were written in a format version this crate doesn't know.
+    UnknownVersion(u8),
+    /// The entry at the given byte offset is neither a node nor a leaf.
+    InvalidTag { position : usize },
+    /// The data of the leaf at the given byte offset could not be decoded.
+    InvalidData { position : usize },
+    /// The Merkle Tree was complete before the byte at the given offset.
+    TrailingBytes { position : usize }
+}
+
+impl fmt::Display for BytesError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BytesError::UnexpectedEnd => write!(f, "unexpected end of bytes"),
+            BytesError::UnknownVersion(version) => write!(f, "unknown format version {}", version),
+            BytesError::InvalidTag { position } => write!(f, "invalid tag at byte {}", position),
+            BytesError::InvalidData { position } => write!(f, "invalid data at byte {}", position),
+            BytesError::TrailingBytes { position } => write!(f, "trailing bytes after the tree at byte {}", position)
+        }
+    }
+}
+
+impl std::error::Error for BytesError {}
+
+/// Escapes the given text for use as an XML attribute value.
+fn xml_escape(text : &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Reverses xml_escape(). Returns None when the text contains an unknown or unterminated entity.
+fn xml_unescape(text : &str) -> Option<String> {
+    let mut unescaped = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        unescaped.push_str(&rest[..start]);
+        let end = start + rest[start..].find(';')?;
+        unescaped.push(match &rest[start + 1..end] {
+            "amp" => '&',
+            "lt" => '<',
+            "gt" => '>',
+            "quot" => '"',
+            "apos" => '\'',
+            _ => return None
+        });
+        rest = &rest[end + 1..];
+    }
+    unescaped.push_str(rest);
+    Some(unescaped)
+}
+
+/// The maximum depth of a Merkle Tree imported using MerkleTree::import_xml(). The parser is
+/// recursive, so without a limit, maliciously nested XML could overflow the stack.
+/// A balanced tree of this depth would have 2^128 leaves.
+pub const MAX_XML_DEPTH : usize = 128;
+
+/// A parser for the XML written by MerkleTree::export_xml(), see MerkleTree::import_xml().
+struct XmlParser<'a> {
+    xml : &'a str,
+    /// The byte offset of the next character to parse.
+    position : usize
+}
+
+impl<'a> XmlParser<'a> {
+
+    fn skip_whitespace(&mut self) {
+        let rest = &self.xml[self.position..];
+        self.position += rest.len() - rest.trim_start().len();
+    }
+
+    /// Skips whitespace and returns whether the given token comes next (without consuming it).
+    fn peek(&mut self, token : &str) -> bool {
+        self.skip_whitespace();
+        self.xml[self.position..].starts_with(token)
+    }
+
+    /// Consumes the given token (after skipping whitespace).
+    fn expect(&mut self, token : &'static str) -> Result<(), XmlError> {
+        if self.peek(token) {
+            self.position += token.len();
+            Ok(())
+        } else if token.starts_with(&self.xml[self.position..]) {
+            // The XML ends before or in the middle of the token
+            Err(XmlError::UnexpectedEnd)
+        } else {
+            Err(XmlError::Unexpected { position: self.position, expected: token })
+        }
+    }
+
+    /// Consumes the attribute with the given name and returns its (still escaped) value together
+    /// with the byte offset of the value.
+    fn attribute(&mut self, name : &'static str) -> Result<(&'a str, usize), XmlError> {
+        self.expect(name)?;
+        self.expect("=\"")?;
+        let start = self.position;
+        let length = self.xml[start..].find('"').ok_or(XmlError::UnexpectedEnd)?;
+        self.position += length + 1;
+        Ok((&self.xml[start..start + length], start))
+    }
+
+    fn parse_hash(&mut self) -> Result<ShaHash, XmlError> {
+        let (value, position) = self.attribute("hash")?;
+        value.parse().map_err(|_| XmlError::InvalidHash { position })
+    }
+
+    /// Parses the (sub)tree at the given depth (0 being the root).
+    fn parse_tree<T : AsRef<[u8]> + Clone + LeafCodec>(&mut self, depth : usize) -> Result<MerkleTree<T>, XmlError> {
+        if self.peek("<leaf") {
+            self.expect("<leaf")?;
+            let hash = self.parse_hash()?;
+            let data = if self.peek("data") {
+                let (value, position) = self.attribute("data")?;
+                let data = xml_unescape(value)
+                    .and_then(|value| T::decode_string(&value))
+                    .ok_or(XmlError::InvalidData { position })?;
+                Some(data)
+            } else {
+                None
+            };
+            self.expect("/>")?;
+            Ok(MerkleTree::Leaf { hash, data })
+        } else {
+            self.skip_whitespace();
+            if depth >= MAX_XML_DEPTH {
+                return Err(XmlError::TooDeep { position: self.position });
+            }
+            self.expect("<node")?;
+            let hash = self.parse_hash()?;
+            self.expect(">")?;
+            self.expect("<left>")?;
+            let left = self.parse_tree(depth + 1)?;
+            self.expect("</left>")?;
+            self.expect("<right>")?;
+            let right = self.parse_tree(depth + 1)?;
+            self.expect("</right>")?;
+            self.expect("</node>")?;
+            Ok(MerkleTree::Node { hash, left: Box::new(left), right: Box::new(right) })
+        }
+    }
+}
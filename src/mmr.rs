@@ -0,0 +1,126 @@
+use sha2::Sha256;
+use crate::merkle_tree::{MerkleTree, MerkleProof, Direction, hash_leaf, hash_node};
+use crate::ShaHash;
+
+/// A Merkle Mountain Range: an append-only list of elements committed to by a single root hash,
+/// e.g. for a continuously growing log.
+///
+/// The elements are stored in complete (perfectly balanced) Merkle Trees, the "peaks", from the
+/// biggest to the smallest one. Appending an element adds a peak of a single leaf and then merges
+/// the two rightmost peaks as long as they have the same height (just like carrying when
+/// incrementing a binary number), i.e. an append only calculates O(log n) hashes.
+///
+/// The root hash is calculated by "bagging" the peaks from right to left, see bag_peaks(). This is
+/// the root hash of into_merkle_tree(), so proofs can be verified using MerkleProof::verify() just
+/// like the ones of any other Merkle Tree.
+#[derive(Clone, Debug)]
+pub struct Mmr<T : AsRef<[u8]> + Clone> {
+    /// The peaks from left to right, together with their heights (strictly decreasing).
+    peaks: Vec<(u32, MerkleTree<T>)>,
+    /// The number of elements appended so far.
+    len: usize
+}
+
+impl<T : AsRef<[u8]> + Clone> Mmr<T> {
+
+    /// Creates an empty Merkle Mountain Range.
+    pub fn new() -> Mmr<T> {
+        Mmr {
+            peaks: Vec::new(),
+            len: 0
+        }
+    }
+
+    /// Appends the given element and returns its index (starting at 0).
+    pub fn push(&mut self, element: T) -> usize {
+        let mut height = 0;
+        let mut peak = MerkleTree::Leaf {
+            hash: hash_leaf::<Sha256>(element.as_ref()),
+            data: Some(element)
+        };
+        while matches!(self.peaks.last(), Some((last_height, _)) if *last_height == height) {
+            let (_, left) = self.peaks.pop().unwrap();
+            peak = MerkleTree::Node {
+                hash: hash_node::<Sha256>(left.get_root_hash(), peak.get_root_hash()),
+                left: Box::new(left),
+                right: Box::new(peak)
+            };
+            height += 1;
+        }
+        self.peaks.push((height, peak));
+        self.len += 1;
+        self.len - 1
+    }
+
+    /// Returns the number of elements appended so far.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns true when no element was appended yet.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the root hashes of the peaks, from left (biggest) to right (smallest).
+    pub fn peaks(&self) -> Vec<ShaHash> {
+        self.peaks.iter().map(|(_, peak)| peak.get_root_hash()).collect()
+    }
+
+    /// Returns the root hash committing to all elements, or None when this Merkle Mountain Range
+    /// is empty.
+    pub fn root(&self) -> Option<ShaHash> {
+        bag_peaks(&self.peaks())
+    }
+
+    /// Generates a proof that the given element is part of this Merkle Mountain Range, to be
+    /// verified against root() using MerkleProof::verify().
+    /// Returns None when the element was not found.
+    ///
+    /// Please note that the proof is only valid for the current root: every append (that changes
+    /// the peaks to the right of the element or merges its peak) changes the proof.
+    pub fn generate_proof(&self, element: &T) -> Option<MerkleProof> {
+        let leaf_hash = hash_leaf::<Sha256>(element.as_ref());
+        let index = self.peaks.iter().position(|(_, peak)| peak.contains_hash(&leaf_hash))?;
+        let mut proof = self.peaks[index].1.generate_proof_for_hash(&leaf_hash)?;
+        let peaks = self.peaks();
+        if let Some(right) = bag_peaks(&peaks[index + 1..]) {
+            proof.siblings.push((Direction::Right, right));
+        }
+        for left in peaks[..index].iter().rev() {
+            proof.siblings.push((Direction::Left, *left));
+        }
+        Some(proof)
+    }
+
+    /// Turns this Merkle Mountain Range into a single Merkle Tree with the same root hash
+    /// (by joining the peaks from right to left).
+    /// Returns None when this Merkle Mountain Range is empty.
+    pub fn into_merkle_tree(mut self) -> Option<MerkleTree<T>> {
+        let (_, mut mtree) = self.peaks.pop()?;
+        while let Some((_, left)) = self.peaks.pop() {
+            mtree = MerkleTree::Node {
+                hash: hash_node::<Sha256>(left.get_root_hash(), mtree.get_root_hash()),
+                left: Box::new(left),
+                right: Box::new(mtree)
+            };
+        }
+        Some(mtree)
+    }
+}
+
+impl<T : AsRef<[u8]> + Clone> Default for Mmr<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Bags the given peak hashes (from left to right) into a single root hash, from right to left:
+/// `bag_peaks(&[a, b, c]) = hash(a || hash(b || c))`.
+/// Returns None when there are no peaks.
+///
+/// This allows someone who only knows the peaks (e.g. a light client) to calculate the root hash.
+pub fn bag_peaks(peaks: &[ShaHash]) -> Option<ShaHash> {
+    let (last, rest) = peaks.split_last()?;
+    Some(rest.iter().rev().fold(*last, |right, left| hash_node::<Sha256>(*left, right)))
+}
@@ -0,0 +1,270 @@
+use sha2::Sha256;
+use std::convert::TryInto;
+use crate::merkle_tree::{MerkleTree, MerkleTreeError, MerkleProof, Direction, TreeHasher, LeafCodec, hash_node, adjacent};
+use crate::ShaHash;
+#[cfg(feature = "serde")]
+use serde::{Serialize, Deserialize};
+
+/// Identifies an application sharing a Blockchain with others, see NamespacedLeaf.
+pub type NamespaceId = u64;
+
+/// A leaf tagged with the namespace of the application it belongs to, so that several
+/// applications can share one Blockchain but each only has to sync its own data (see
+/// proof_for_namespace()).
+///
+/// The bytes of the leaf are the namespace (u64 big endian) followed by the data.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct NamespacedLeaf {
+    bytes: Vec<u8>
+}
+
+impl NamespacedLeaf {
+
+    /// Creates a leaf with the given data in the given namespace.
+    pub fn new(namespace: NamespaceId, data: &[u8]) -> NamespacedLeaf {
+        let mut bytes = namespace.to_be_bytes().to_vec();
+        bytes.extend_from_slice(data);
+        NamespacedLeaf { bytes }
+    }
+
+    /// Parses the bytes of a leaf, e.g. received from others.
+    /// Returns None when they are too short to contain a namespace.
+    pub fn from_bytes(bytes: Vec<u8>) -> Option<NamespacedLeaf> {
+        (bytes.len() >= 8).then_some(NamespacedLeaf { bytes })
+    }
+
+    /// Returns the namespace this leaf belongs to.
+    pub fn namespace(&self) -> NamespaceId {
+        NamespaceId::from_be_bytes(self.bytes[..8].try_into().unwrap())
+    }
+
+    /// Returns the data of this leaf (without the namespace).
+    pub fn data(&self) -> &[u8] {
+        &self.bytes[8..]
+    }
+}
+
+impl AsRef<[u8]> for NamespacedLeaf {
+    fn as_ref(&self) -> &[u8] {
+        &self.bytes
+    }
+}
+
+impl LeafCodec for NamespacedLeaf {
+    fn encode(&self) -> Vec<u8> {
+        self.bytes.clone()
+    }
+
+    fn decode(bytes: &[u8]) -> Option<Self> {
+        NamespacedLeaf::from_bytes(bytes.to_vec())
+    }
+}
+
+/// Creates a Merkle Tree of the given leaves (in any order) in which the leaves of each namespace
+/// form a subtree of their own, with the subtrees sorted by namespace. The root hashes of these
+/// subtrees are the "sub-roots" of the namespaces. Within a namespace, the order of the leaves
+/// is kept.
+/// Returns MerkleTreeError::Empty when called on an empty Vec.
+pub fn namespaced_tree(leaves: &[NamespacedLeaf]) -> Result<MerkleTree<NamespacedLeaf>, MerkleTreeError> {
+    namespaced_tree_with::<Sha256>(leaves)
+}
+
+/// Same as namespaced_tree(), but hashes using D (see MerkleTree::new_with()), e.g.
+/// DomainSeparated<Sha256> for a Blockchain using TreeVersion::V2.
+pub fn namespaced_tree_with<D : TreeHasher>(leaves: &[NamespacedLeaf]) -> Result<MerkleTree<NamespacedLeaf>, MerkleTreeError> {
+    let mut sorted = leaves.to_vec();
+    sorted.sort_by_key(NamespacedLeaf::namespace);
+    let subtrees = sorted.chunk_by(|a, b| a.namespace() == b.namespace())
+        .map(MerkleTree::try_new_with::<D>)
+        .collect::<Result<Vec<MerkleTree<NamespacedLeaf>>, MerkleTreeError>>()?;
+    join_subtrees::<D>(subtrees).ok_or(MerkleTreeError::Empty)
+}
+
+/// Joins the given subtrees into a single Merkle Tree, splitting them in half just like
+/// MerkleTree::new() splits the data.
+fn join_subtrees<D : TreeHasher>(mut subtrees: Vec<MerkleTree<NamespacedLeaf>>) -> Option<MerkleTree<NamespacedLeaf>> {
+    if subtrees.len() <= 1 {
+        return subtrees.pop();
+    }
+    let right = subtrees.split_off(subtrees.len() / 2);
+    let (left, right) = (join_subtrees::<D>(subtrees)?, join_subtrees::<D>(right)?);
+    Some(MerkleTree::Node {
+        hash: hash_node::<D>(left.get_root_hash(), right.get_root_hash()),
+        left: Box::new(left),
+        right: Box::new(right)
+    })
+}
+
+/// Checks whether the given Merkle Tree was created using namespaced_tree(), which is what makes
+/// the proofs of proof_for_namespace() complete - a network whose applications rely on them has
+/// to reject Blocks whose Merkle Trees fail this check.
+/// Returns false when some of its leaves are forgotten, as they can't be checked then.
+pub fn is_namespaced_tree(mtree: &MerkleTree<NamespacedLeaf>) -> bool {
+    is_namespaced_tree_with::<Sha256>(mtree)
+}
+
+/// Same as is_namespaced_tree(), but for a Merkle Tree using D (see namespaced_tree_with()).
+pub fn is_namespaced_tree_with<D : TreeHasher>(mtree: &MerkleTree<NamespacedLeaf>) -> bool {
+    if mtree.forgotten_leaf_count() > 0 {
+        return false;
+    }
+    let leaves = mtree.get_currently_stored_data();
+    leaves.windows(2).all(|pair| pair[0].namespace() <= pair[1].namespace())
+        && namespaced_tree_with::<D>(&leaves).is_ok_and(|rebuilt| rebuilt.get_root_hash() == mtree.get_root_hash())
+}
+
+/// Generates a proof of all leaves of the given namespace in the given Merkle Tree created using
+/// namespaced_tree(), which also proves that there are no others (see NamespaceProof).
+/// Returns None when some of the leaves are forgotten or the tree is not a namespaced tree.
+pub fn proof_for_namespace(mtree: &MerkleTree<NamespacedLeaf>, namespace: NamespaceId) -> Option<NamespaceProof> {
+    proof_for_namespace_with::<Sha256>(mtree, namespace)
+}
+
+/// Same as proof_for_namespace(), but for a Merkle Tree using D (see namespaced_tree_with()).
+pub fn proof_for_namespace_with<D : TreeHasher>(mtree: &MerkleTree<NamespacedLeaf>, namespace: NamespaceId) -> Option<NamespaceProof> {
+    if mtree.forgotten_leaf_count() > 0 {
+        return None;
+    }
+    let all_leaves = mtree.get_currently_stored_data();
+    let (start, end) = namespace_range(&all_leaves, namespace);
+    let leaves = all_leaves[start..end].to_vec();
+    let subtree_proof = match leaves.is_empty() {
+        true => None,
+        false => Some(mtree.generate_proof_for_subtree(&MerkleTree::try_new_with::<D>(&leaves).ok()?.get_root_hash())?)
+    };
+    // The neighbours are the last leaf of the namespace before and the first one of the next one
+    let neighbour = |namespace : Option<NamespaceId>, rightmost : bool| -> Option<Option<(NamespacedLeaf, MerkleProof)>> {
+        let namespace = match namespace {
+            Some(namespace) => namespace,
+            None => return Some(None)
+        };
+        let (start, end) = namespace_range(&all_leaves, namespace);
+        let subtree = MerkleTree::try_new_with::<D>(&all_leaves[start..end]).ok()?;
+        let (leaf, mut proof) = edge_leaf(&subtree, rightmost)?;
+        proof.siblings.extend(mtree.generate_proof_for_subtree(&subtree.get_root_hash())?.siblings);
+        Some(Some((leaf, proof)))
+    };
+    Some(NamespaceProof {
+        leaves,
+        subtree_proof,
+        left: neighbour(start.checked_sub(1).map(|index| all_leaves[index].namespace()), true)?,
+        right: neighbour(all_leaves.get(end).map(NamespacedLeaf::namespace), false)?
+    })
+}
+
+/// Returns the range of the leaves of the given namespace within the given sorted leaves.
+fn namespace_range(leaves: &[NamespacedLeaf], namespace: NamespaceId) -> (usize, usize) {
+    (leaves.partition_point(|leaf| leaf.namespace() < namespace),
+     leaves.partition_point(|leaf| leaf.namespace() <= namespace))
+}
+
+/// Returns the rightmost (or leftmost) leaf of the given Merkle Tree together with its proof.
+fn edge_leaf(mtree: &MerkleTree<NamespacedLeaf>, rightmost: bool) -> Option<(NamespacedLeaf, MerkleProof)> {
+    let mut siblings = Vec::new();
+    let mut subtree = mtree;
+    loop {
+        match subtree {
+            MerkleTree::Leaf{data, ..} => {
+                // The siblings were collected from the root down, but proofs start at the leaf
+                siblings.reverse();
+                return Some((data.clone()?, MerkleProof { siblings }));
+            },
+            MerkleTree::Node{left, right, ..} if rightmost => {
+                siblings.push((Direction::Left, left.get_root_hash()));
+                subtree = right;
+            },
+            MerkleTree::Node{left, right, ..} => {
+                siblings.push((Direction::Right, right.get_root_hash()));
+                subtree = left;
+            }
+        }
+    }
+}
+
+/// A proof of all leaves of a namespace in a Merkle Tree created using namespaced_tree(), see
+/// proof_for_namespace().
+///
+/// It consists of the leaves of the namespace (from which the verifier rebuilds the sub-root)
+/// with a proof of their subtree, and of the leaves right before and after them (the last leaf of
+/// the previous namespace and the first one of the next namespace) with their proofs. As these
+/// neighbours belong to other namespaces and are adjacent to the subtree, there can't be any other
+/// leaves of the namespace - given that the tree is a namespaced tree (see is_namespaced_tree()).
+/// For a namespace without leaves, the neighbours are adjacent to each other.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct NamespaceProof {
+    /// All leaves of the namespace, in the order they are stored in.
+    leaves: Vec<NamespacedLeaf>,
+    /// The proof of the subtree of the leaves, None when there are none.
+    subtree_proof: Option<MerkleProof>,
+    /// The last leaf of the previous namespace, None when there is none.
+    left: Option<(NamespacedLeaf, MerkleProof)>,
+    /// The first leaf of the next namespace, None when there is none.
+    right: Option<(NamespacedLeaf, MerkleProof)>
+}
+
+impl NamespaceProof {
+
+    /// Returns the proven leaves of the namespace. They are only trustworthy once verify()
+    /// returned true.
+    pub fn leaves(&self) -> &[NamespacedLeaf] {
+        &self.leaves
+    }
+
+    /// Checks whether this proof shows that its leaves are all leaves of the given namespace in
+    /// the namespaced tree with the given root hash.
+    pub fn verify(&self, root_hash: ShaHash, namespace: NamespaceId) -> bool {
+        self.verify_with::<Sha256>(root_hash, namespace)
+    }
+
+    /// Same as verify(), but for a Merkle Tree using D (see namespaced_tree_with()).
+    pub fn verify_with<D : TreeHasher>(&self, root_hash: ShaHash, namespace: NamespaceId) -> bool {
+        // The sides of the siblings of the first and the last leaf of the namespace, from the root
+        // down, or None when the namespace has no leaves
+        let edges = match (&self.subtree_proof, self.leaves.is_empty()) {
+            (None, true) => None,
+            (Some(subtree_proof), false) => {
+                if self.leaves.iter().any(|leaf| leaf.namespace() != namespace) {
+                    return false;
+                }
+                let subtree = match MerkleTree::try_new_with::<D>(&self.leaves) {
+                    Ok(subtree) => subtree,
+                    Err(_) => return false
+                };
+                if subtree_proof.calculate_root_hash_with::<D>(subtree.get_root_hash()) != root_hash {
+                    return false;
+                }
+                let path = subtree_proof.sides_from_root();
+                let (first, last) = match (edge_leaf(&subtree, false), edge_leaf(&subtree, true)) {
+                    (Some((_, first)), Some((_, last))) => (first, last),
+                    _ => return false
+                };
+                Some(([path.clone(), first.sides_from_root()].concat(), [path, last.sides_from_root()].concat()))
+            },
+            _ => return false
+        };
+        // The neighbours have to be part of the tree and belong to the namespaces before and after
+        let valid_neighbour = |neighbour : &(NamespacedLeaf, MerkleProof), is_left : bool| {
+            let (leaf, proof) = neighbour;
+            let correct_side = if is_left { leaf.namespace() < namespace } else { leaf.namespace() > namespace };
+            correct_side && proof.verify_with::<D, NamespacedLeaf>(root_hash, leaf)
+        };
+        let left_ok = match (&self.left, &edges) {
+            (Some(left), Some((first, _))) => valid_neighbour(left, true) && adjacent(&left.1.sides_from_root(), first),
+            (Some(left), None) => valid_neighbour(left, true),
+            // Nothing before the first leaf of the tree
+            (None, Some((first, _))) => first.iter().all(|side| *side == Direction::Right),
+            (None, None) => self.right.as_ref().is_some_and(|right| right.1.sides_from_root().iter().all(|side| *side == Direction::Right))
+        };
+        let right_ok = match (&self.right, &edges) {
+            (Some(right), Some((_, last))) => valid_neighbour(right, false) && adjacent(last, &right.1.sides_from_root()),
+            (Some(right), None) => valid_neighbour(right, false)
+                && self.left.as_ref().is_none_or(|left| adjacent(&left.1.sides_from_root(), &right.1.sides_from_root())),
+            // Nothing after the last leaf of the tree
+            (None, Some((_, last))) => last.iter().all(|side| *side == Direction::Left),
+            (None, None) => self.left.as_ref().is_some_and(|left| left.1.sides_from_root().iter().all(|side| *side == Direction::Left))
+        };
+        left_ok && right_ok
+    }
+}
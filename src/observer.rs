@@ -0,0 +1,23 @@
+use crate::blockchain::{PruneReport, ScrubReport};
+use crate::ShaHash;
+use std::fmt::Debug;
+
+/// Gets notified about what happens to a Blockchain, e.g. to collect metrics or to log it,
+/// see Blockchain::set_observer().
+///
+/// All functions do nothing by default, so only the interesting ones have to be implemented.
+/// They are called while the Blockchain is being changed, so they should return quickly.
+pub trait Observer : Debug + Send + Sync {
+    /// A Block was validated and appended to the Blockchain at the given height.
+    fn on_block_validated(&self, _height : usize, _block_hash : ShaHash) {}
+
+    /// The Blockchain started "mining" a new Block coming after the Block with the given hash
+    /// (see Blockchain::append_data()).
+    fn on_mining_started(&self, _prev_hash : ShaHash) {}
+
+    /// Blocks of the Blockchain were pruned (see Blockchain::prune()).
+    fn on_prune(&self, _report : &PruneReport) {}
+
+    /// Blocks of the Blockchain were scrubbed (see Blockchain::scrub()).
+    fn on_scrub(&self, _report : &ScrubReport) {}
+}
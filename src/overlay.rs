@@ -0,0 +1,115 @@
+use crate::block::Block;
+use crate::blockchain::{Blockchain, BlockchainError, check_block, check_unmined, schedule};
+use crate::chain_params::ChainParams;
+use crate::governance::ParamUpdate;
+use crate::merkle_tree::MerkleTree;
+use crate::ShaHash;
+
+/// A speculative continuation of a Blockchain, see Blockchain::overlay().
+///
+/// Blocks appended to an Overlay are validated just like Blocks appended to the Blockchain
+/// itself, but the Blockchain is never changed. This makes it possible to try out candidate
+/// Blocks (e.g. ones announced by others) and throw them away again, or to apply them to the
+/// Blockchain afterwards using into_blocks().
+#[derive(Debug)]
+pub struct Overlay<'a, T : AsRef<[u8]> + Clone> {
+    /// The Blockchain this Overlay continues.
+    base: &'a Blockchain<T>,
+    /// The Blocks appended on top of the base Blockchain.
+    blocks: Vec<Block<T>>,
+    /// The authorized updates of the ChainParams confirmed by the Blocks of this Overlay, each
+    /// with the height it takes effect at and its sequence number (see Blockchain::set_governance()).
+    param_updates: Vec<(usize, u64, ParamUpdate)>
+}
+
+impl<'a, T : AsRef<[u8]> + Clone> Overlay<'a, T> {
+
+    /// Creates a new, empty Overlay on top of the given Blockchain.
+    pub(crate) fn new(base: &'a Blockchain<T>) -> Overlay<'a, T> {
+        Overlay {
+            base,
+            blocks: Vec::new(),
+            param_updates: Vec::new()
+        }
+    }
+
+    /// Returns the total number of Blocks, i.e. those of the base Blockchain plus those appended
+    /// to this Overlay.
+    pub fn length(&self) -> usize {
+        self.base.length() + self.blocks.len()
+    }
+
+    /// Returns the Block at the given height, no matter whether it's part of the base Blockchain
+    /// or was appended to this Overlay.
+    pub fn get_block(&self, height : usize) -> Option<&Block<T>> {
+        match height.checked_sub(self.base.length()) {
+            None => self.base.get_block(height),
+            Some(overlay_height) => self.blocks.get(overlay_height)
+        }
+    }
+
+    /// Returns the hash of the last Block appended to this Overlay or of the last Block of the
+    /// base Blockchain when nothing was appended yet.
+    pub fn hash_of_last_block(&self) -> ShaHash {
+        match self.blocks.last() {
+            Some(last_block) => last_block.calculate_hash(),
+            None => self.base.hash_of_last_block()
+        }
+    }
+
+    /// Same as Blockchain::params_at(), but also applies the governance updates confirmed by the
+    /// Blocks of this Overlay.
+    pub fn params_at(&self, height : usize) -> ChainParams {
+        let mut params = self.base.params_at(height);
+        // The updates of this Overlay were confirmed after all updates of the base Blockchain
+        for (_, _, update) in self.param_updates.iter().take_while(|(activation, _, _)| *activation <= height) {
+            update.apply(&mut params);
+        }
+        params
+    }
+
+    /// Same as Blockchain::append_block(), but only appends the given Block to this Overlay.
+    pub fn append_block(&mut self, block : Block<T>) -> bool {
+        self.append_checked(block).is_ok()
+    }
+
+    /// Same as append_block(), but returns why the given Block was not appended.
+    fn append_checked(&mut self, block : Block<T>) -> Result<(), BlockchainError> {
+        let height = self.length();
+        check_block(&self.params_at(height), &block)?;
+        self.base.check_complete(&block)?;
+        if block.prev_hash != self.hash_of_last_block() {
+            return Err(BlockchainError::InvalidPrevHash);
+        }
+        // The updates of this Overlay are confirmed after all updates of the base Blockchain
+        let updates = self.base.authorized_updates(&block, height);
+        schedule(&mut self.param_updates, self.base.last_update_sequence(), updates);
+        self.blocks.push(block);
+        Ok(())
+    }
+
+    /// Same as Blockchain::append_data(), but only appends the new Block to this Overlay.
+    pub fn append_data(&mut self, mtree : MerkleTree<T>) -> Block<T> {
+        let mut new_block = Block::new(self.hash_of_last_block(), mtree);
+        new_block.calculate_nonce_with(self.params_at(self.length()).pow);
+        self.append_block(new_block.clone());
+        new_block
+    }
+
+    /// Same as Blockchain::try_append_data(), but only appends the new Block to this Overlay.
+    pub fn try_append_data(&mut self, mtree : MerkleTree<T>) -> Result<Block<T>, BlockchainError> {
+        let mut new_block = Block::new(self.hash_of_last_block(), mtree);
+        let params = self.params_at(self.length());
+        check_unmined(&params, &new_block)?;
+        self.base.check_complete(&new_block)?;
+        new_block.calculate_nonce_with(params.pow);
+        self.append_checked(new_block.clone())?;
+        Ok(new_block)
+    }
+
+    /// Returns the Blocks appended to this Overlay, in order, e.g. for appending them to the
+    /// base Blockchain using Blockchain::append_block().
+    pub fn into_blocks(self) -> Vec<Block<T>> {
+        self.blocks
+    }
+}
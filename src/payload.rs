@@ -0,0 +1,63 @@
+use std::convert::TryFrom;
+
+/// The data of a leaf that is either still in its raw form or already parsed into a T.
+///
+/// Leaves are hashed using nothing but their bytes (AsRef<[u8]>), so a MerkleTree<Payload<T>>
+/// can mix data received from others that was not parsed (yet) with values created locally,
+/// without having to parse everything just to hash it. A raw leaf and a parsed leaf with the same
+/// bytes have the same hash and are therefore interchangeable, e.g. for proofs or for restoring
+/// forgotten leaves.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Payload<T> {
+    /// Bytes that were not parsed (yet).
+    Raw(Vec<u8>),
+    /// An already parsed value.
+    Typed(T)
+}
+
+impl<T> Payload<T> {
+
+    /// Returns the value if this payload was already parsed.
+    pub fn as_typed(&self) -> Option<&T> {
+        match self {
+            Payload::Raw(_) => None,
+            Payload::Typed(value) => Some(value),
+        }
+    }
+
+    /// Returns the value of this payload, parsing it first if it is still raw.
+    /// Returns None when the raw bytes could not be parsed into a T.
+    pub fn into_typed(self) -> Option<T> where T : TryFrom<Vec<u8>> {
+        match self {
+            Payload::Raw(bytes) => T::try_from(bytes).ok(),
+            Payload::Typed(value) => Some(value),
+        }
+    }
+
+    /// Parses this payload in place if it is still raw, see into_typed().
+    /// Returns false (and keeps the raw bytes) when they could not be parsed into a T.
+    pub fn parse(&mut self) -> bool where T : TryFrom<Vec<u8>> {
+        if let Payload::Raw(bytes) = self {
+            match T::try_from(bytes.clone()) {
+                Ok(value) => *self = Payload::Typed(value),
+                Err(_) => return false,
+            }
+        }
+        true
+    }
+}
+
+impl<T : AsRef<[u8]>> AsRef<[u8]> for Payload<T> {
+    fn as_ref(&self) -> &[u8] {
+        match self {
+            Payload::Raw(bytes) => bytes,
+            Payload::Typed(value) => value.as_ref(),
+        }
+    }
+}
+
+impl<T> From<T> for Payload<T> {
+    fn from(value: T) -> Payload<T> {
+        Payload::Typed(value)
+    }
+}
@@ -0,0 +1,82 @@
+use sha2::Sha256;
+use sha2::Digest;
+use sha2::digest::consts::U32;
+use std::fmt;
+use std::fmt::Debug;
+use std::marker::PhantomData;
+use crate::ShaHash;
+
+/// The hash function used for the proof of work ("mining"), selected in the ChainParams.
+///
+/// A Block is only valid when the proof of work hash of its header starts with ZEROS 0's.
+/// Please note that this is only about the proof of work: Blocks are always identified (and
+/// linked to each other via prev_hash) by their SHA-256 hash, see Block::calculate_hash().
+pub trait PowFunction : Debug + Sync {
+    /// Hashes the given serialized BlockHeader.
+    fn hash(&self, header: &[u8]) -> ShaHash;
+}
+
+/// SHA-256, the default proof of work function. Using it, the proof of work hash of a Block is
+/// the same as its hash.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Sha256Pow;
+
+impl PowFunction for Sha256Pow {
+    fn hash(&self, header: &[u8]) -> ShaHash {
+        Sha256::digest(header).into()
+    }
+}
+
+/// SHA-256 applied twice (as used by Bitcoin).
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Sha256dPow;
+
+impl PowFunction for Sha256dPow {
+    fn hash(&self, header: &[u8]) -> ShaHash {
+        Sha256::digest(&Sha256::digest(header)).into()
+    }
+}
+
+/// Any hash function D with 256-bit outputs implementing Digest (e.g. sha2::Sha512Trunc256 or
+/// sha3::Sha3_256). As ChainParams need a `&'static dyn PowFunction`, put it into a static:
+/// `static SHA3_POW: DigestPow<Sha3_256> = DigestPow::new();`
+pub struct DigestPow<D> {
+    hash_function: PhantomData<fn() -> D>
+}
+
+impl<D> DigestPow<D> {
+    /// Creates the proof of work function for the hash function D.
+    pub const fn new() -> DigestPow<D> {
+        DigestPow { hash_function: PhantomData }
+    }
+}
+
+impl<D> Default for DigestPow<D> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<D> Debug for DigestPow<D> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "DigestPow<{}>", std::any::type_name::<D>())
+    }
+}
+
+impl<D : Digest<OutputSize = U32>> PowFunction for DigestPow<D> {
+    fn hash(&self, header: &[u8]) -> ShaHash {
+        D::digest(header).into()
+    }
+}
+
+/// BLAKE3 (requires the "blake3" feature).
+#[cfg(feature = "blake3")]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Blake3Pow;
+
+#[cfg(feature = "blake3")]
+impl PowFunction for Blake3Pow {
+    fn hash(&self, header: &[u8]) -> ShaHash {
+        ShaHash::new(blake3::hash(header).into())
+    }
+}
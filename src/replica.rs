@@ -0,0 +1,208 @@
+use crate::block::Block;
+use crate::blockchain::{Blockchain, BlockchainError};
+use crate::chain_params::{BlockLimits, ChainParams};
+use crate::merkle_tree::{MerkleTree, LeafCodec};
+use crate::{ShaHash, Nonce};
+use std::convert::{TryFrom, TryInto};
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::ops::Range;
+
+// Read replicas follow a Blockchain by reading its Blocks from a log written by a LogShipper,
+// e.g. a file that is tailed or a TCP stream, so that queries (e.g. of a block explorer) can be
+// served by them instead of the node appending the Blocks.
+//
+// Layout of the log: one record per Block, in the order of the Blockchain, each being
+// <length of the rest of the record (u32 big endian)><prev_hash (32 bytes)><nonce (u64 big
+// endian)><Merkle Tree written by MerkleTree::to_bytes()>.
+
+/// Writes the Blocks of a Blockchain to a log (e.g. a file or a TCP stream) that Replicas read.
+#[derive(Debug)]
+pub struct LogShipper<W : Write> {
+    output : W,
+    shipped : usize
+}
+
+impl<W : Write> LogShipper<W> {
+
+    /// Creates a LogShipper writing to the given output, starting at the very first Block.
+    pub fn new(output : W) -> LogShipper<W> {
+        Self::resume(output, 0)
+    }
+
+    /// Creates a LogShipper writing to the given output, starting at the Block at the given
+    /// height, e.g. when appending to an existing log or when a Replica reconnected that already
+    /// has that many Blocks.
+    pub fn resume(output : W, shipped : usize) -> LogShipper<W> {
+        LogShipper { output, shipped }
+    }
+
+    /// Returns the number of Blocks shipped so far (including the ones skipped by resume()).
+    pub fn shipped(&self) -> usize {
+        self.shipped
+    }
+
+    /// Writes all Blocks of the given Blockchain that were not shipped yet to the log and flushes
+    /// it. Returns the number of Blocks written.
+    ///
+    /// The Blocks are shipped as they are currently stored, so the Replicas only get the data of
+    /// Blocks that was not pruned yet. Call this function after appending Blocks, e.g. from a
+    /// background thread.
+    pub fn ship<T : AsRef<[u8]> + Clone + LeafCodec>(&mut self, chain : &Blockchain<T>) -> io::Result<usize> {
+        let start = self.shipped;
+        while let Some(block) = chain.get_block(self.shipped) {
+            let record = encode_block(block)
+                .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "Block too big for the log"))?;
+            self.output.write_all(&record)?;
+            self.shipped += 1;
+        }
+        self.output.flush()?;
+        Ok(self.shipped - start)
+    }
+
+    /// Returns the output written to.
+    pub fn into_inner(self) -> W {
+        self.output
+    }
+}
+
+/// The reason why Replica::poll() failed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ReplicaError {
+    /// Reading the log failed.
+    Io(io::ErrorKind),
+    /// A record of the log is not a valid Block.
+    Undecodable,
+    /// A Block of the log could not be appended to the Blockchain of the Replica.
+    Invalid(BlockchainError)
+}
+
+impl fmt::Display for ReplicaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReplicaError::Io(kind) => write!(f, "reading the log failed: {}", io::Error::from(*kind)),
+            ReplicaError::Undecodable => write!(f, "record could not be decoded"),
+            ReplicaError::Invalid(error) => write!(f, "invalid Block: {}", error)
+        }
+    }
+}
+
+impl std::error::Error for ReplicaError {}
+
+/// A read-only copy of a Blockchain following the log written by a LogShipper.
+///
+/// Every Block read from the log is validated and appended just like by append_block(), so a
+/// Replica can be used like any other Blockchain for queries (see chain()). To maintain indexes
+/// of its own, set an Observer or enable sequence numbers on the Blockchain passed to
+/// with_chain(), or process the Blocks returned by poll().
+#[derive(Debug)]
+pub struct Replica<T : AsRef<[u8]> + Clone, R : Read> {
+    chain : Blockchain<T>,
+    input : R,
+    /// The bytes read from the input that don't make up a whole record yet.
+    buffer : Vec<u8>
+}
+
+impl<T : AsRef<[u8]> + Clone + LeafCodec, R : Read> Replica<T, R> {
+
+    /// Creates a Replica with an empty Blockchain with the given ChainParams (the same as the
+    /// ones of the shipped Blockchain), reading the log from the given input.
+    pub fn new(params : ChainParams, input : R) -> Replica<T, R> {
+        Self::with_chain(Blockchain::with_params(params), input)
+    }
+
+    /// Creates a Replica continuing the given Blockchain, e.g. one with an Observer maintaining
+    /// indexes or one that was shipped partially already (see LogShipper::resume()).
+    pub fn with_chain(chain : Blockchain<T>, input : R) -> Replica<T, R> {
+        Replica { chain, input, buffer: Vec::new() }
+    }
+
+    /// Returns the Blockchain of this Replica.
+    pub fn chain(&self) -> &Blockchain<T> {
+        &self.chain
+    }
+
+    /// Reads the log until at least one Block was appended or until its end is reached (a read
+    /// returning no bytes, e.g. at the end of a file that is still being written) and returns the
+    /// heights of the appended Blocks. Reading a blocking input (e.g. a TCP stream) waits for the
+    /// next Block.
+    ///
+    /// Stops at the first record that can't be decoded or appended, keeping the Blocks appended
+    /// before it. A record longer than any Block within the BlockLimits of its height can be is
+    /// rejected (as LimitExceeded) before it is read, so a corrupt log can't make the Replica
+    /// buffer gigabytes. Polling again returns the same error, as the Replica can't continue the
+    /// Blockchain without that Block.
+    pub fn poll(&mut self) -> Result<Range<usize>, ReplicaError> {
+        let start = self.chain.length();
+        let mut chunk = [0u8; 8192];
+        loop {
+            self.append_records()?;
+            if self.chain.length() > start {
+                return Ok(start..self.chain.length());
+            }
+            match self.input.read(&mut chunk) {
+                Ok(0) => return Ok(start..start),
+                Ok(read) => self.buffer.extend_from_slice(&chunk[..read]),
+                Err(error) if error.kind() == io::ErrorKind::Interrupted => {},
+                Err(error) => return Err(ReplicaError::Io(error.kind()))
+            }
+        }
+    }
+
+    /// Appends the Blocks of all whole records in the buffer and removes them from it.
+    fn append_records(&mut self) -> Result<(), ReplicaError> {
+        let mut consumed = 0;
+        let mut result = Ok(());
+        while let Some(length) = self.buffer.get(consumed..consumed + 4) {
+            let length = u32::from_be_bytes(length.try_into().unwrap()) as usize;
+            if length > max_record_length(&self.chain.params_at(self.chain.length()).limits) {
+                result = Err(ReplicaError::Invalid(BlockchainError::LimitExceeded));
+                break;
+            }
+            let record = match self.buffer.get(consumed + 4..consumed + 4 + length) {
+                Some(record) => record,
+                None => break // The rest of the record wasn't read yet
+            };
+            result = decode_block(record).ok_or(ReplicaError::Undecodable)
+                .and_then(|block| self.chain.append_checked(block, false).map_err(ReplicaError::Invalid));
+            if result.is_err() {
+                break;
+            }
+            consumed += 4 + length;
+        }
+        self.buffer.drain(..consumed);
+        result
+    }
+}
+
+/// Returns the record of the given Block, see the layout above, or None when it is 4 GiB or bigger.
+fn encode_block<T : AsRef<[u8]> + Clone + LeafCodec>(block : &Block<T>) -> Option<Vec<u8>> {
+    let tree = block.get_merkle_tree().to_bytes()?;
+    let length = 32 + std::mem::size_of::<Nonce>() + tree.len();
+    let mut record = Vec::with_capacity(4 + length);
+    record.extend_from_slice(&u32::try_from(length).ok()?.to_be_bytes());
+    record.extend_from_slice(block.prev_hash.as_ref());
+    record.extend_from_slice(&block.header().nonce().to_be_bytes());
+    record.extend_from_slice(&tree);
+    Some(record)
+}
+
+/// Returns the maximum length of the record (without its length) of a Block within the given
+/// limits: every leaf and node of its Merkle Tree taking 1 + 32 bytes, with another 4 bytes for
+/// the length of the data of a leaf, plus the data itself (assuming LeafCodec::encode() writes
+/// the as_ref() bytes, as all encodings of this crate do).
+fn max_record_length(limits : &BlockLimits) -> usize {
+    let nodes = limits.max_leaves.saturating_mul(2);
+    (32 + std::mem::size_of::<Nonce>() + 1)
+        .saturating_add(nodes.saturating_mul(1 + 32 + 4))
+        .saturating_add(limits.max_bytes)
+}
+
+/// Parses a record written by encode_block() (without its length).
+fn decode_block<T : AsRef<[u8]> + Clone + LeafCodec>(record : &[u8]) -> Option<Block<T>> {
+    let prev_hash = ShaHash::try_from(record.get(..32)?).ok()?;
+    let nonce = Nonce::from_be_bytes(record.get(32..40)?.try_into().ok()?);
+    let mut block = Block::new(prev_hash, MerkleTree::from_bytes(&record[40..]).ok()?);
+    block.nonce = nonce;
+    Some(block)
+}
@@ -0,0 +1,132 @@
+use sha2::Sha256;
+use crate::merkle_tree::{MerkleTree, MerkleTreeError, MerkleProof, Direction, DomainSeparated, hash_leaf, adjacent};
+use crate::ShaHash;
+#[cfg(feature = "serde")]
+use serde::{Serialize, Deserialize};
+
+/// The hash function of a SortedMerkleTree. Leaves and nodes have to be told apart for
+/// NonInclusionProofs, as otherwise a node could be passed off as a leaf next to the missing one.
+/// This is also the hash function of TreeVersion::V2, so a SortedMerkleTree can be appended to a
+/// Blockchain using it.
+type Hasher = DomainSeparated<Sha256>;
+
+/// A Merkle Tree whose leaves are sorted by their hashes (without duplicates), which allows
+/// proving that something is NOT contained in it, e.g. that a transaction was excluded from a
+/// Block (see NonInclusionProof).
+///
+/// Please note that a NonInclusionProof only means something when the root hash is known to
+/// belong to a sorted tree, e.g. because the rules of the network require the Merkle Trees of all
+/// Blocks to be sorted - a tree with unsorted leaves can "prove" anything.
+#[derive(Clone, Debug)]
+pub struct SortedMerkleTree<T : AsRef<[u8]> + Clone> {
+    mtree: MerkleTree<T>
+}
+
+impl<T : AsRef<[u8]> + Clone> SortedMerkleTree<T> {
+
+    /// Creates a sorted Merkle Tree of the given data, in any order. Data with the same hash is
+    /// only stored once.
+    /// Returns MerkleTreeError::Empty when called on an empty Vec.
+    pub fn new(data: &[T]) -> Result<SortedMerkleTree<T>, MerkleTreeError> {
+        let mut sorted : Vec<(ShaHash, &T)> = data.iter()
+            .map(|element| (hash_leaf::<Hasher>(element.as_ref()), element))
+            .collect();
+        sorted.sort_unstable_by_key(|(hash, _)| *hash);
+        sorted.dedup_by(|(a, _), (b, _)| a == b);
+        let sorted : Vec<T> = sorted.into_iter().map(|(_, element)| element.clone()).collect();
+        Ok(SortedMerkleTree { mtree: MerkleTree::try_new_with::<Hasher>(&sorted)? })
+    }
+
+    /// Returns the root hash of this Merkle Tree.
+    pub fn get_root_hash(&self) -> ShaHash {
+        self.mtree.get_root_hash()
+    }
+
+    /// Returns the underlying Merkle Tree (using DomainSeparated<Sha256>, i.e. TreeVersion::V2).
+    pub fn merkle_tree(&self) -> &MerkleTree<T> {
+        &self.mtree
+    }
+
+    /// Turns this into the underlying Merkle Tree, e.g. to append it to a Blockchain.
+    pub fn into_merkle_tree(self) -> MerkleTree<T> {
+        self.mtree
+    }
+
+    /// Returns the hash the given element has as a leaf of a SortedMerkleTree, which is what
+    /// NonInclusionProofs are about.
+    pub fn leaf_hash(element: &T) -> ShaHash {
+        hash_leaf::<Hasher>(element.as_ref())
+    }
+
+    /// Generates a proof that the given element is part of this Merkle Tree, to be verified
+    /// using MerkleProof::verify_with::<DomainSeparated<Sha256>, T>().
+    /// Returns None when the element is not contained.
+    pub fn generate_proof(&self, element: &T) -> Option<MerkleProof> {
+        self.mtree.generate_proof_with::<Hasher>(element)
+    }
+
+    /// Generates a proof that no leaf with the given hash (see leaf_hash()) is part of this
+    /// Merkle Tree, consisting of the leaves right before and after where it would have to be.
+    /// Returns None when the hash is contained, or when the data of these leaves was forgotten.
+    pub fn generate_non_inclusion_proof(&self, leaf_hash: &ShaHash) -> Option<NonInclusionProof<T>> {
+        let leaves = self.mtree.get_leaves();
+        let split = leaves.iter().position(|(hash, _)| hash >= leaf_hash).unwrap_or(leaves.len());
+        if leaves.get(split).is_some_and(|(hash, _)| hash == leaf_hash) {
+            return None;
+        }
+        let neighbour = |index : Option<usize>| -> Option<Option<(T, MerkleProof)>> {
+            match index.and_then(|index| leaves.get(index)) {
+                None => Some(None),
+                Some((hash, data)) => {
+                    let element = (*data)?.clone();
+                    Some(Some((element, self.mtree.generate_proof_for_hash(hash)?)))
+                }
+            }
+        };
+        Some(NonInclusionProof {
+            left: neighbour(split.checked_sub(1))?,
+            right: neighbour(Some(split))?
+        })
+    }
+}
+
+/// A proof that a leaf hash is not part of a SortedMerkleTree with a certain root hash, see
+/// SortedMerkleTree::generate_non_inclusion_proof().
+///
+/// It consists of the two adjacent leaves whose hashes are right before and after the missing
+/// one (or only one of them when the missing hash would be the first or last one), each with a
+/// MerkleProof showing where it is located in the tree.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct NonInclusionProof<T> {
+    /// The leaf right before the missing one, None when it would be the first leaf.
+    left: Option<(T, MerkleProof)>,
+    /// The leaf right after the missing one, None when it would be the last leaf.
+    right: Option<(T, MerkleProof)>
+}
+
+impl<T : AsRef<[u8]>> NonInclusionProof<T> {
+
+    /// Checks whether this proof shows that no leaf with the given hash (see
+    /// SortedMerkleTree::leaf_hash()) is part of the SortedMerkleTree with the given root hash.
+    pub fn verify(&self, root_hash: ShaHash, leaf_hash: &ShaHash) -> bool {
+        // Each leaf has to be part of the tree, and on the correct side of the missing hash
+        let valid_leaf = |leaf : &(T, MerkleProof), is_left : bool| {
+            let (element, proof) = leaf;
+            let hash = hash_leaf::<Hasher>(element.as_ref());
+            let correct_side = if is_left { hash < *leaf_hash } else { hash > *leaf_hash };
+            correct_side && proof.verify_with::<Hasher, T>(root_hash, element)
+        };
+        match (&self.left, &self.right) {
+            (None, None) => false,
+            // The first leaf has no siblings to its left
+            (None, Some(right)) => valid_leaf(right, false)
+                && right.1.sides_from_root().iter().all(|side| *side == Direction::Right),
+            // The last leaf has no siblings to its right
+            (Some(left), None) => valid_leaf(left, true)
+                && left.1.sides_from_root().iter().all(|side| *side == Direction::Left),
+            (Some(left), Some(right)) => valid_leaf(left, true) && valid_leaf(right, false)
+                && adjacent(&left.1.sides_from_root(), &right.1.sides_from_root())
+        }
+    }
+}
@@ -0,0 +1,138 @@
+use crate::block::Block;
+use crate::blockchain::Blockchain;
+use crate::merkle_tree::MerkleTree;
+use std::sync::RwLock;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+
+/// How many threads of each kind a stress test runs, see run().
+#[derive(Clone, Copy, Debug)]
+pub struct StressConfig {
+    /// Threads "mining" Blocks without holding a lock and appending them using append_block(),
+    /// i.e. racing against each other and against the appenders.
+    pub miners : usize,
+    /// Threads appending data using append_data().
+    pub appenders : usize,
+    /// Threads forgetting the data of the Blocks again and again.
+    pub pruners : usize,
+    /// Threads checking the invariants of the Blockchain again and again.
+    pub readers : usize,
+    /// The stress test stops as soon as the Blockchain has (at least) this many Blocks.
+    pub blocks : usize
+}
+
+impl Default for StressConfig {
+    fn default() -> Self {
+        StressConfig {
+            miners: 2,
+            appenders: 2,
+            pruners: 1,
+            readers: 2,
+            blocks: 20
+        }
+    }
+}
+
+/// What happened during a stress test, see run().
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct StressReport {
+    /// The number of Blocks of the Blockchain at the end.
+    pub blocks : usize,
+    /// The number of mined Blocks that were rejected because another Block was appended while
+    /// they were being mined.
+    pub rejected : usize,
+    /// The number of times the invariants were checked.
+    pub checks : usize,
+    /// The number of Blocks pruned.
+    pub prunes : usize
+}
+
+/// Runs the threads given by the config against a single Blockchain until it has
+/// `config.blocks` Blocks, and panics as soon as one of these invariants is violated:
+/// - the Blockchain is valid (Blockchain::verify()), no matter whether its data was pruned,
+/// - the Blockchain only ever grows, i.e. every tip seen before is still part of it,
+/// - the amount of reclaimed data never decreases.
+pub fn run(config : StressConfig) -> StressReport {
+    let chain : RwLock<Blockchain<Vec<u8>>> = RwLock::new(Blockchain::new());
+    let rejected = AtomicUsize::new(0);
+    let checks = AtomicUsize::new(0);
+    let prunes = AtomicUsize::new(0);
+    let done = || chain.read().unwrap().length() >= config.blocks;
+    let data = |role : &str, thread : usize, round : usize| -> MerkleTree<Vec<u8>> {
+        let leaves : Vec<Vec<u8>> = (0..3)
+            .map(|leaf| format!("{} {} round {} leaf {}", role, thread, round, leaf).into_bytes())
+            .collect();
+        MerkleTree::new(&leaves)
+    };
+
+    thread::scope(|scope| {
+        for miner in 0..config.miners {
+            let (chain, rejected, done) = (&chain, &rejected, &done);
+            scope.spawn(move || {
+                for round in 0.. {
+                    if done() {
+                        break;
+                    }
+                    let prev_hash = chain.read().unwrap().hash_of_last_block();
+                    let mut block = Block::new(prev_hash, data("miner", miner, round));
+                    block.calculate_nonce_seeded((miner * 1_000_000 + round) as u64);
+                    if !chain.write().unwrap().append_block(block) {
+                        rejected.fetch_add(1, Ordering::Relaxed);
+                    }
+                }
+            });
+        }
+        for appender in 0..config.appenders {
+            let chain = &chain;
+            scope.spawn(move || {
+                for round in 0.. {
+                    let mut chain = chain.write().unwrap();
+                    if chain.length() >= config.blocks {
+                        break;
+                    }
+                    chain.try_append_data(data("appender", appender, round)).expect("the appender holds the write lock");
+                }
+            });
+        }
+        for pruner in 0..config.pruners {
+            let (chain, prunes, done) = (&chain, &prunes, &done);
+            scope.spawn(move || {
+                let mut reclaimed_bytes = 0;
+                for round in 0.. {
+                    if done() {
+                        break;
+                    }
+                    let mut chain = chain.write().unwrap();
+                    let height = (pruner + round) % (chain.length() + 1);
+                    let report = chain.prune(height..height + 1, |mtree| mtree.forget_all_leaves());
+                    prunes.fetch_add(report.per_block.len(), Ordering::Relaxed);
+                    let stats = chain.stats();
+                    assert!(stats.reclaimed.bytes >= reclaimed_bytes, "reclaimed data decreased");
+                    reclaimed_bytes = stats.reclaimed.bytes;
+                    assert!(chain.verify(), "pruning invalidated the Blockchain");
+                }
+            });
+        }
+        for _ in 0..config.readers {
+            let (chain, checks, done) = (&chain, &checks, &done);
+            scope.spawn(move || {
+                let mut last_tip = chain.read().unwrap().get_params().initial_hash;
+                while !done() {
+                    let chain = chain.read().unwrap();
+                    assert!(chain.verify(), "the Blockchain became invalid");
+                    let tip = chain.hash_of_last_block();
+                    assert!(chain.consistency_proof(last_tip, tip).is_some(), "a Block seen before vanished");
+                    last_tip = tip;
+                    checks.fetch_add(1, Ordering::Relaxed);
+                }
+            });
+        }
+    });
+
+    StressReport {
+        blocks: chain.into_inner().unwrap().length(),
+        rejected: rejected.into_inner(),
+        checks: checks.into_inner(),
+        prunes: prunes.into_inner()
+    }
+}
@@ -0,0 +1,181 @@
+use crate::block::{Block, BlockHeader};
+use crate::blockchain::{check_block, prevalidate, BlockchainError};
+use crate::chain_params::ChainParams;
+use crate::governance::ParamUpdate;
+use crate::ShaHash;
+use std::collections::HashMap;
+
+/// Something suspicious a Watchtower noticed about the Blocks it has seen.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Alert {
+    /// The proof of work hash of the Block with this hash does not start with ZEROS 0's.
+    InvalidNonce { hash : ShaHash },
+    /// Some of the hashes in the Merkle Tree of the Block with this hash are incorrect.
+    InvalidMerkleTree { hash : ShaHash },
+    /// The Block with this hash exceeds the BlockLimits of its height.
+    LimitExceeded { hash : ShaHash },
+    /// The Block with this hash comes after a Block the Watchtower has never seen.
+    UnknownParent { hash : ShaHash },
+    /// Another Block at the same height was seen before, i.e. the Blockchain forked.
+    ConflictingBlocks { height : usize, existing : ShaHash, new : ShaHash },
+    /// The Block with this hash belongs to a fork that would replace at least `depth` Blocks of the
+    /// longest Blockchain seen so far (see Watchtower::new()).
+    DeepFork { hash : ShaHash, depth : usize }
+}
+
+/// A Block seen by a Watchtower.
+#[derive(Clone, Copy, Debug)]
+struct SeenBlock {
+    prev_hash : ShaHash,
+    height : usize
+}
+
+/// A passive observer of the Blocks of a Blockchain, e.g. all the Blocks announced by others.
+///
+/// Unlike a Blockchain, a Watchtower never decides which Blocks to build on: it keeps track of all
+/// the valid Blocks it has seen, including every fork, and raises Alerts about anything invalid or
+/// conflicting instead.
+#[derive(Debug)]
+pub struct Watchtower {
+    /// The parameters of the watched Blockchain.
+    params : ChainParams,
+    /// The updates of the parameters with the heights they take effect at, see schedule_update().
+    param_updates : Vec<(usize, ParamUpdate)>,
+    /// Forks at least this deep raise an Alert::DeepFork.
+    deep_fork_depth : usize,
+    /// All the valid Blocks seen so far by their hashes.
+    seen : HashMap<ShaHash, SeenBlock>,
+    /// The first Block seen at each height.
+    first_at_height : HashMap<usize, ShaHash>,
+    /// The hash of the last Block of the longest Blockchain seen so far.
+    best_tip : Option<ShaHash>
+}
+
+impl Watchtower {
+
+    /// Creates a new Watchtower for a Blockchain with the given ChainParams, raising an
+    /// Alert::DeepFork for forks that would replace `deep_fork_depth` or more Blocks.
+    pub fn new(params : ChainParams, deep_fork_depth : usize) -> Watchtower {
+        Watchtower {
+            params,
+            param_updates: Vec::new(),
+            deep_fork_depth,
+            seen: HashMap::new(),
+            first_at_height: HashMap::new(),
+            best_tip: None
+        }
+    }
+
+    /// Returns the hash of the last Block of the longest Blockchain seen so far
+    /// or the initial hash of the ChainParams when no Block has been seen yet.
+    pub fn best_tip(&self) -> ShaHash {
+        self.best_tip.unwrap_or(self.params.initial_hash)
+    }
+
+    /// Makes the given update of the ChainParams take effect at the given height, e.g. one that
+    /// was confirmed by a Blockchain with a Governance (see Blockchain::set_governance()), so that
+    /// the Blocks after it are checked against the new parameters. Updates with the same
+    /// activation height are applied in the order they were scheduled in.
+    pub fn schedule_update(&mut self, activation : usize, update : ParamUpdate) {
+        let index = self.param_updates.partition_point(|(other, _)| *other <= activation);
+        self.param_updates.insert(index, (activation, update));
+    }
+
+    /// Returns the ChainParams the Block at the given height is checked against, see
+    /// schedule_update() and Blockchain::params_at().
+    pub fn params_at(&self, height : usize) -> ChainParams {
+        let mut params = self.params;
+        for (_, update) in self.param_updates.iter().take_while(|(activation, _)| *activation <= height) {
+            update.apply(&mut params);
+        }
+        params
+    }
+
+    /// Checks the given Block and all of its data just like a Blockchain appending it would (with
+    /// the ChainParams of its height, see params_at()), then see observe_header(). The BlockLimits
+    /// of Blocks after an unknown Block can't be checked, so they only raise an UnknownParent.
+    pub fn observe_block<T : AsRef<[u8]> + Clone>(&mut self, block : &Block<T>) -> Vec<Alert> {
+        let hash = block.calculate_hash();
+        if self.seen.contains_key(&hash) {
+            return Vec::new();
+        }
+        let checked = match self.height_after(block.prev_hash) {
+            Some(height) => check_block(&self.params_at(height), block),
+            None => prevalidate(&self.params, block)
+        };
+        match checked {
+            Ok(()) => self.observe_header(&block.header()),
+            Err(BlockchainError::LimitExceeded) => vec![Alert::LimitExceeded { hash }],
+            Err(BlockchainError::InvalidMerkleTree) => vec![Alert::InvalidMerkleTree { hash }],
+            Err(_) => vec![Alert::InvalidNonce { hash }]
+        }
+    }
+
+    /// Checks the Block of the given header and returns the Alerts it raised (if any).
+    ///
+    /// Valid Blocks are remembered, no matter whether they continue the longest Blockchain seen
+    /// so far or not. Blocks that were seen before are ignored.
+    pub fn observe_header(&mut self, header : &BlockHeader) -> Vec<Alert> {
+        let hash = header.calculate_hash();
+        if self.seen.contains_key(&hash) {
+            return Vec::new();
+        }
+        if !header.verify_nonce_with(self.params.pow) {
+            return vec![Alert::InvalidNonce { hash }];
+        }
+        let prev_hash = header.prev_hash();
+        let height = match self.height_after(prev_hash) {
+            Some(height) => height,
+            None => return vec![Alert::UnknownParent { hash }],
+        };
+        self.seen.insert(hash, SeenBlock { prev_hash, height });
+
+        let mut alerts = Vec::new();
+        let existing = *self.first_at_height.entry(height).or_insert(hash);
+        if existing != hash {
+            alerts.push(Alert::ConflictingBlocks { height, existing, new: hash });
+        }
+        match self.best_tip {
+            Some(best_tip) if height <= self.seen[&best_tip].height => {
+                let depth = self.fork_depth(hash, best_tip);
+                if depth >= self.deep_fork_depth {
+                    alerts.push(Alert::DeepFork { hash, depth });
+                }
+            }
+            _ => self.best_tip = Some(hash),
+        }
+        alerts
+    }
+
+    /// Returns the height of a Block coming after the Block with the given hash (or the initial
+    /// hash), or None when that Block was never seen.
+    fn height_after(&self, prev_hash : ShaHash) -> Option<usize> {
+        if prev_hash == self.params.initial_hash {
+            Some(0)
+        } else {
+            self.seen.get(&prev_hash).map(|parent| parent.height + 1)
+        }
+    }
+
+    /// Returns the number of Blocks of the Blockchain ending with `best_tip` that come after the
+    /// last Block it shares with the Blockchain ending with the Block with the given hash.
+    fn fork_depth(&self, mut hash : ShaHash, best_tip : ShaHash) -> usize {
+        let best_height = self.seen[&best_tip].height;
+        let mut best = best_tip;
+        while self.seen[&best].height > self.seen[&hash].height {
+            best = self.seen[&best].prev_hash;
+        }
+        loop {
+            if best == hash {
+                return best_height - self.seen[&hash].height;
+            }
+            let (prev_best, prev_hash) = (self.seen[&best].prev_hash, self.seen[&hash].prev_hash);
+            if !self.seen.contains_key(&prev_best) {
+                // Both Blockchains start right after the initial hash
+                return best_height + 1;
+            }
+            best = prev_best;
+            hash = prev_hash;
+        }
+    }
+}
@@ -1,11 +1,17 @@
 #[cfg(test)]
 mod tests {
-    use rust_blockchain::blockchain::*;
-
-    #[test]
-    fn it_works() {
-        assert_eq!(2 + 2, 4);
-    }
+    use rust_blockchain::ShaHash;
+    use rust_blockchain::merkle_tree::*;
+    use rust_blockchain::encrypted_leaf::*;
+    use rust_blockchain::payload::*;
+    use rust_blockchain::chunked_leaf::*;
+    use rust_blockchain::detached_leaf::*;
+    use rust_blockchain::bloom::*;
+    use rust_blockchain::mmr::*;
+    use rust_blockchain::flat_merkle_tree::*;
+    use rust_blockchain::sorted_merkle_tree::*;
+    use rust_blockchain::data_availability::*;
+    use rust_blockchain::namespace::*;
 
     #[derive(Copy, Clone, Debug, PartialEq)]
     struct Transaction<'a> {
@@ -18,7 +24,7 @@ mod tests {
 
     impl AsRef<[u8]> for Transaction<'_> {
         fn as_ref(&self) -> &[u8] {
-            unimplemented!()
+            &self.signature
         }
     }
 
@@ -30,26 +36,2194 @@ mod tests {
         signature: [34u8; 32]
     };
 
+    /// Returns `n` different Transactions, the i-th one with the signature [i; 32].
+    fn transactions(n : u8) -> Vec<Transaction<'static>> {
+        (0..n).map(|i| Transaction { signature: [i; 32], ..TRANSACTION_1 }).collect()
+    }
+
+    /// Not a real encryption, XORs every byte with the key.
+    struct XorCipher {
+        key : u8
+    }
+
+    impl Cipher for XorCipher {
+        fn encrypt(&self, plaintext: &[u8]) -> Vec<u8> {
+            plaintext.iter().map(|byte| byte ^ self.key).collect()
+        }
+
+        fn decrypt(&self, ciphertext: &[u8]) -> Option<Vec<u8>> {
+            Some(self.encrypt(ciphertext))
+        }
+    }
+
+    #[test]
+    fn it_works() {
+        assert_eq!(2 + 2, 4);
+    }
+
     #[test]
     fn test_merkle_tree() {
-        let tree1 : MerkleTree<Transaction> = MerkleTree::new(vec![TRANSACTION_1]);
+        let tree1 : MerkleTree<Transaction> = MerkleTree::new(&[TRANSACTION_1]);
         assert!(tree1.verify());
         assert_eq!(vec![TRANSACTION_1], tree1.get_currently_stored_data());
     }
 
     #[test]
-    fn test_block() {
-        let previous_hash : SHAHash = [11u8; 32];
-        let data : MerkleTree<Transaction> = MerkleTree::new(vec![TRANSACTION_1]);
-        let mut test_block : Block<Transaction> = Block::new(previous_hash, data);
-        assert!(!test_block.verify());
-        test_block.calculate_nonce();
-        assert!(test_block.verify());
+    fn test_blockchain() {
+
     }
 
     #[test]
-    fn test_blockchain() {
+    fn test_encrypted_leaf() {
+        use sha2::Sha256;
+        let cipher = XorCipher { key: 42 };
+        let plaintexts : Vec<Vec<u8>> = vec![b"Alice".to_vec(), b"Bob".to_vec(), b"Carol".to_vec()];
+        let leaves : Vec<EncryptedLeaf<Vec<u8>>> = plaintexts.iter()
+            .map(|plaintext| EncryptedLeaf::encrypt(plaintext, &cipher))
+            .collect();
+        let mut tree = MerkleTree::new(&leaves);
+        assert!(tree.verify());
+
+        // Proofs work on the ciphertext only
+        let proof = tree.generate_proof(&leaves[1]).unwrap();
+        assert!(proof.verify(tree.get_root_hash(), &leaves[1]));
+        assert_eq!(proof.calculate_root_hash(leaves[1].hash()), tree.get_root_hash());
+        let v2 = MerkleTree::new_with::<DomainSeparated<Sha256>>(&leaves);
+        let proof = v2.generate_proof_with::<DomainSeparated<Sha256>>(&leaves[1]).unwrap();
+        assert_eq!(proof.calculate_root_hash_with::<DomainSeparated<Sha256>>(leaves[1].hash_with::<DomainSeparated<Sha256>>()),
+                   v2.get_root_hash());
+
+        // Forgotten leaves are restored from the ciphertext and can be decrypted again
+        tree.forget_all_leaves();
+        assert!(tree.restore_element(EncryptedLeaf::from_ciphertext(leaves[2].as_ref().to_vec())));
+        assert_eq!(tree.get_currently_stored_data()[0].decrypt(&cipher), Some(plaintexts[2].clone()));
+    }
+
+    #[test]
+    fn test_bloom_filter() {
+        use sha2::Sha256;
+        let transactions = transactions(20);
+        let mtree = MerkleTree::new(&transactions);
+
+        let mut filter = BloomFilter::new(64, 3);
+        filter.insert(&transactions[3]);
+        filter.insert(&transactions[17]);
+        let matching = filter.matching_leaves(&mtree);
+
+        // All inserted leaves match (false positives are possible, but not with these values)
+        assert_eq!(matching.iter().map(|(transaction, _)| *transaction).collect::<Vec<Transaction>>(),
+                   vec![transactions[3], transactions[17]]);
+        for (transaction, proof) in &matching {
+            assert!(proof.verify(mtree.get_root_hash(), transaction));
+        }
+        assert!(BloomFilter::new(64, 3).matching_leaves(&mtree).is_empty());
+
+        // Merkle Trees with domain separation (TreeVersion::V2) hash their leaves differently
+        let v2 = MerkleTree::new_with::<DomainSeparated<Sha256>>(&transactions);
+        assert!(filter.matching_leaves_with::<DomainSeparated<Sha256>, _>(&v2).is_empty());
+        let mut filter = BloomFilter::new(64, 3);
+        filter.insert_with::<DomainSeparated<Sha256>, _>(&transactions[5]);
+        let matching = filter.matching_leaves_with::<DomainSeparated<Sha256>, _>(&v2);
+        assert_eq!(matching.len(), 1);
+        assert!(matching[0].1.verify_with::<DomainSeparated<Sha256>, _>(v2.get_root_hash(), &transactions[5]));
+    }
+
+    #[test]
+    fn test_render_ascii() {
+        let transactions = transactions(3);
+        let mut mtree = MerkleTree::new(&transactions);
+        mtree.forget_leaf(&transactions[2]);
+        let short = |hash : ShaHash| hex::encode(&hash.as_bytes()[..4]);
+        let leaf = |transaction : &Transaction| short(MerkleTree::new(&[*transaction]).get_root_hash());
+        let right = match &mtree {
+            MerkleTree::Node { right, .. } => right.get_root_hash(),
+            _ => panic!("expected a node"),
+        };
+        assert_eq!(mtree.render_ascii(2), format!(
+            "node {}\n|-- leaf {}\n`-- node {}\n    |-- leaf {}\n    `-- leaf {} (forgotten)\n",
+            short(mtree.get_root_hash()), leaf(&transactions[0]), short(right),
+            leaf(&transactions[1]), leaf(&transactions[2])
+        ));
+        assert_eq!(mtree.render_ascii(0), format!("node {} ...\n", short(mtree.get_root_hash())));
+    }
+
+    #[test]
+    fn test_payload() {
+        let leaves : Vec<Payload<[u8; 4]>> = vec![
+            Payload::Typed(*b"abcd"),
+            Payload::Raw(b"efgh".to_vec()),
+            Payload::Raw(b"too long".to_vec())
+        ];
+        let mut tree = MerkleTree::new(&leaves);
+        assert!(tree.verify());
+
+        // Raw and parsed leaves with the same bytes are interchangeable
+        let bytes = vec![b"abcd".to_vec(), b"efgh".to_vec(), b"too long".to_vec()];
+        assert_eq!(tree.get_root_hash(), MerkleTree::new(&bytes).get_root_hash());
+        let proof = tree.generate_proof(&Payload::Raw(b"abcd".to_vec())).unwrap();
+        assert!(proof.verify(tree.get_root_hash(), &Payload::Typed(*b"abcd")));
+        tree.forget_all_leaves();
+        assert!(tree.restore_element(Payload::Typed(*b"efgh")));
+
+        // Parsing
+        let mut raw = leaves[1].clone();
+        assert_eq!(raw.as_typed(), None);
+        assert!(raw.parse());
+        assert_eq!(raw.as_typed(), Some(b"efgh"));
+        let mut invalid = leaves[2].clone();
+        assert!(!invalid.parse());
+        assert_eq!(invalid, leaves[2]);
+        assert_eq!(leaves[2].clone().into_typed(), None);
+        assert_eq!(leaves[0].clone().into_typed(), Some(*b"abcd"));
+    }
+
+    #[test]
+    fn test_chunked_leaf() {
+        let document : Vec<u8> = (0..100u8).collect();
+        let leaves = vec![ChunkedLeaf::new(&document, 16), ChunkedLeaf::new(b"small", 16)];
+        let mut tree = MerkleTree::new(&leaves);
+        assert!(tree.verify());
+        assert_eq!(leaves[0].get_chunks().get_leaf_hashes().len(), 7);
+        assert_eq!(leaves[1].get_data(), Some(b"small".to_vec()));
+
+        // Prove a single chunk of the document against the root of the tree
+        let leaf_proof = tree.generate_proof(&leaves[0]).unwrap();
+        let chunk_proof = leaves[0].chunk_proof(&document[32..48]).unwrap();
+        assert!(leaf_proof.verify(tree.get_root_hash(), &leaves[0]));
+        assert!(chunk_proof.verify(leaves[0].chunk_root(), &document[32..48].to_vec()));
+
+        // Restore a forgotten leaf from its chunk root and then retrieve some of its chunks
+        tree.forget_all_leaves();
+        let mut partial = ChunkedLeaf::from_chunk_root(leaves[0].chunk_root());
+        assert!(tree.restore_element(partial.clone()));
+        let mut chunk_tree = leaves[0].get_chunks().clone();
+        chunk_tree.forget_all_leaves();
+        assert!(partial.restore_chunk_tree(chunk_tree));
+        assert!(partial.restore_chunk(&document[96..]));
+        assert!(!partial.restore_chunk(b"not a chunk"));
+        assert!(partial.verify());
+        assert_eq!(partial.get_data(), None);
+        for chunk in document.chunks(16) {
+            assert!(partial.restore_chunk(chunk));
+        }
+        assert_eq!(partial.get_data(), Some(document.clone()));
+        assert!(partial.forget_chunk(&document[..16]));
+        assert_eq!(partial.get_data(), None);
+    }
+
+    #[test]
+    fn test_detached_leaf() {
+        let mut store = MemoryStore::default();
+        let documents : Vec<Vec<u8>> = vec![vec![1; 1000], vec![2; 2000]];
+        let leaves : Vec<DetachedLeaf> = documents.iter()
+            .map(|document| DetachedLeaf::store(document, &mut store))
+            .collect();
+        let mtree = MerkleTree::new(&leaves);
+        assert!(mtree.verify());
+
+        // The Merkle Tree only stores the CIDs, the content is fetched and checked on access
+        let stored = mtree.get_currently_stored_data();
+        assert_eq!(stored[1].fetch(&store), Some(documents[1].clone()));
+
+        // Content not matching its CID is rejected, missing content is None
+        let mut forged = MemoryStore::default();
+        forged.put(leaves[0].cid(), vec![3; 1000]);
+        assert_eq!(leaves[0].fetch(&forged), None);
+        assert_eq!(DetachedLeaf::from_cid(ShaHash::ZERO).fetch(&store), None);
+    }
+
+    #[test]
+    fn test_restore_subtree() {
+        let transactions = transactions(8);
+        let full = MerkleTree::new(&transactions);
+        let (left, right) = match &full {
+            MerkleTree::Node { left, right, .. } => ((**left).clone(), (**right).clone()),
+            _ => panic!("expected a node"),
+        };
+
+        // Self stores transactions 0 and 5, the restored subtree (sparser) only transaction 6
+        let mut mtree = full.clone();
+        mtree.forget_subtree(left.get_root_hash());
+        assert!(mtree.restore_subtree(left.clone()));
+        for i in [1, 2, 3, 4, 6, 7] {
+            mtree.forget_leaf(&transactions[i]);
+        }
+        let mut sparse_right = right.clone();
+        for i in [4, 5, 7] {
+            sparse_right.forget_leaf(&transactions[i]);
+        }
+        sparse_right.forget_subtree(MerkleTree::new(&transactions[4..6]).get_root_hash());
+        assert!(mtree.restore_subtree(sparse_right));
+        assert!(mtree.verify());
+        let stored : Vec<u8> = mtree.get_currently_stored_data().iter().map(|t| t.signature[0]).collect();
+        assert_eq!(stored, vec![0, 5, 6]);
+
+        // Restoring the whole tree restores everything, unknown subtrees are rejected
+        assert!(mtree.restore_subtree(full.clone()));
+        assert_eq!(mtree.get_currently_stored_data().len(), 8);
+        assert!(!mtree.restore_subtree(MerkleTree::new(&[TRANSACTION_1])));
+        assert_eq!(mtree.get_root_hash(), full.get_root_hash());
+    }
+
+    #[test]
+    fn test_xml_import() {
+        let data : Vec<String> = vec!["Alice".into(), "Bob said \"<hi> & bye\"".into(), "Carol".into(), "Dave".into(), "Eve".into()];
+        let mut mtree = MerkleTree::new(&data);
+        mtree.forget_subtree(MerkleTree::new(&data[2..3]).get_root_hash());
+        mtree.forget_subtree(MerkleTree::new(&data[3..]).get_root_hash());
+
+        // Round trip, including forgotten leaves and subtrees
+        let xml = mtree.export_xml();
+        let imported : MerkleTree<String> = MerkleTree::import_xml(&xml).unwrap();
+        assert!(imported.verify());
+        assert_eq!(imported.get_root_hash(), mtree.get_root_hash());
+        assert_eq!(imported.get_currently_stored_data(), vec![data[0].clone(), data[1].clone()]);
+        assert_eq!(imported.export_xml(), xml);
+
+        // Malformed input
+        assert_eq!(MerkleTree::<String>::import_xml(&xml[..xml.len() - 3]).unwrap_err(), XmlError::UnexpectedEnd);
+        assert_eq!(MerkleTree::<String>::import_xml(&format!("{} <leaf", xml)).unwrap_err(),
+            XmlError::Unexpected { position: xml.len() + 1, expected: "end of input" });
+        assert_eq!(MerkleTree::<String>::import_xml("<leaf hash=\"00ff\" />").unwrap_err(),
+            XmlError::InvalidHash { position: 12 });
+        let unknown_entity = format!("<leaf hash=\"{}\" data=\"&nbsp;\" />", hex::encode([0u8; 32]));
+        assert_eq!(MerkleTree::<String>::import_xml(&unknown_entity).unwrap_err(), XmlError::InvalidData { position: 84 });
+        assert_eq!(MerkleTree::<String>::import_xml("<tree />").unwrap_err(),
+            XmlError::Unexpected { position: 0, expected: "<node" });
+        // Maliciously nested XML is rejected instead of overflowing the stack
+        let opening = format!("<node hash=\"{}\"><left>", "00".repeat(32));
+        let nested = opening.repeat(100_000);
+        assert_eq!(MerkleTree::<String>::import_xml(&nested).unwrap_err(),
+            XmlError::TooDeep { position: MAX_XML_DEPTH * opening.len() });
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn test_serde() {
+        let data : Vec<String> = vec!["Alice".into(), "Bob".into(), "Carol".into()];
+        let mut mtree = MerkleTree::new(&data);
+        mtree.forget_subtree(MerkleTree::new(&data[1..2]).get_root_hash());
+
+        // Round trip through JSON, keeping the structure and the forgotten leaf
+        let json = serde_json::to_string(&mtree).unwrap();
+        let deserialized : MerkleTree<String> = serde_json::from_str(&json).unwrap();
+        assert!(deserialized.verify());
+        assert_eq!(deserialized.export_xml(), mtree.export_xml());
+
+        let proof = mtree.generate_proof(&data[2]).unwrap();
+        let deserialized : MerkleProof = serde_json::from_str(&serde_json::to_string(&proof).unwrap()).unwrap();
+        assert!(deserialized.verify(mtree.get_root_hash(), &data[2]));
+    }
+
+    #[test]
+    fn test_other_digest() {
+        use sha2::Sha512Trunc256;
+
+        let transactions = transactions(5);
+        let mut mtree = MerkleTree::new_with::<Sha512Trunc256>(&transactions);
+        assert_ne!(mtree.get_root_hash(), MerkleTree::new(&transactions).get_root_hash());
+        assert!(mtree.verify_with::<Sha512Trunc256>());
+        assert!(!mtree.verify());
+
+        // Proofs and restoring elements have to use the same hash function
+        let proof = mtree.generate_proof_with::<Sha512Trunc256>(&transactions[3]).unwrap();
+        assert!(proof.verify_with::<Sha512Trunc256, _>(mtree.get_root_hash(), &transactions[3]));
+        assert!(!proof.verify(mtree.get_root_hash(), &transactions[3]));
+        assert!(mtree.generate_proof(&transactions[3]).is_none());
+        mtree.forget_all_leaves();
+        assert!(!mtree.restore_element(transactions[1]));
+        assert!(mtree.restore_element_with::<Sha512Trunc256>(transactions[1]));
+    }
+
+    #[test]
+    fn test_bitcoin_merkle_tree() {
+        use sha2::{Digest, Sha256};
+        let sha256d = |data : &[u8]| ShaHash::new(Sha256::digest(&Sha256::digest(data)).into());
+        let transactions = transactions(3);
+        let txid = |transaction : &Transaction| sha256d(transaction.as_ref());
+        let node = |left : ShaHash, right : ShaHash| sha256d(&[left.as_ref(), right.as_ref()].concat());
+
+        // The odd level duplicates the last transaction id
+        let mut mtree = MerkleTree::new_bitcoin(&transactions);
+        let expected_root = node(
+            node(txid(&transactions[0]), txid(&transactions[1])),
+            node(txid(&transactions[2]), txid(&transactions[2]))
+        );
+        assert_eq!(mtree.get_root_hash(), expected_root);
+        assert_eq!(MerkleTree::new_bitcoin(&transactions[..1]).get_root_hash(), txid(&transactions[0]));
+        assert!(mtree.verify_with::<Sha256d>());
+        assert!(!mtree.verify());
+
+        // The duplicate stores no data, proofs and restoring work with Sha256d
+        assert_eq!(mtree.get_currently_stored_data().len(), 3);
+        assert_eq!(mtree.get_leaf_hashes().len(), 4);
+        let proof = mtree.generate_proof_with::<Sha256d>(&transactions[2]).unwrap();
+        assert!(proof.verify_with::<Sha256d, _>(expected_root, &transactions[2]));
+        mtree.forget_all_leaves();
+        assert!(mtree.restore_element_with::<Sha256d>(transactions[2]));
+        assert_eq!(mtree.get_currently_stored_data().len(), 1);
+    }
+
+    #[test]
+    fn test_try_new() {
+        let transactions = [TRANSACTION_1];
+        assert_eq!(MerkleTree::try_new(&transactions).unwrap().get_root_hash(),
+                   MerkleTree::new(&transactions).get_root_hash());
+        let empty : [Transaction; 0] = [];
+        assert_eq!(MerkleTree::try_new(&empty).unwrap_err(), MerkleTreeError::Empty);
+        assert!(MerkleTree::new(&empty).is_empty());
+    }
+
+    #[test]
+    fn test_bitcoin_merkle_roots() {
+        // Hashes are shown in reversed byte order by Bitcoin tools
+        let parse_hash = |hex_hash : &str| -> ShaHash {
+            let mut hash = [0u8; 32];
+            hex::decode_to_slice(hex_hash, &mut hash).unwrap();
+            hash.reverse();
+            hash.into()
+        };
+        let vectors = include_str!("data/bitcoin_merkle_roots.txt").lines()
+            .filter(|line| !line.is_empty() && !line.starts_with('#'));
+        let mut count = 0;
+        for vector in vectors {
+            let mut fields = vector.split_whitespace();
+            let block = fields.next().unwrap();
+            let merkle_root = parse_hash(fields.next().unwrap());
+            let txids : Vec<ShaHash> = fields.map(parse_hash).collect();
+
+            let mtree : MerkleTree<Vec<u8>> = MerkleTree::new_bitcoin_from_leaf_hashes(&txids);
+            assert_eq!(mtree.get_root_hash(), merkle_root, "Merkle root of block {}", block);
+            assert!(mtree.verify_with::<Sha256d>());
+            for txid in &txids {
+                let proof = mtree.generate_proof_for_hash(txid).unwrap();
+                assert_eq!(proof.calculate_root_hash_with::<Sha256d>(*txid), merkle_root, "proof in block {}", block);
+            }
+            count += 1;
+        }
+        assert_eq!(count, 3);
+    }
+
+    #[test]
+    fn test_tree_diff() {
+        let transactions = transactions(4);
+        let hash_of = |transaction : &Transaction| MerkleTree::new(&[*transaction]).get_root_hash();
+        let full = MerkleTree::new(&transactions);
+        assert!(full.diff(&full.clone()).is_empty());
+
+        // Two different prunings of the same tree
+        let mut mine = full.clone();
+        mine.forget_leaf(&transactions[0]);
+        let mut theirs = full.clone();
+        theirs.forget_subtree(MerkleTree::new(&transactions[2..]).get_root_hash());
+        let diff = mine.diff(&theirs);
+        assert_eq!(diff.differing_subtrees, Vec::<Vec<Direction>>::new());
+        assert_eq!(diff.missing_in_self, vec![hash_of(&transactions[0])]);
+        assert_eq!(diff.missing_in_other, vec![hash_of(&transactions[2]), hash_of(&transactions[3])]);
+
+        // Different trees only differ at their roots
+        let other = MerkleTree::new(&transactions[..3]);
+        assert_eq!(full.diff(&other).differing_subtrees, vec![vec![]]);
+    }
+
+    #[test]
+    fn test_merkle_tree_builder() {
+        use sha2::Sha256;
+        let transactions = transactions(7);
+        let mut builder = MerkleTreeBuilder::new();
+        assert_eq!(builder.clone().build().unwrap_err(), MerkleTreeError::Empty);
+        for transaction in &transactions[..4] {
+            builder.push(*transaction);
+        }
+        // Same as MerkleTree::new() for a power of two
+        assert_eq!(builder.clone().build().unwrap().get_root_hash(), MerkleTree::new(&transactions[..4]).get_root_hash());
+
+        // Otherwise, the largest complete subtree is on the left: ((0 1) (2 3)) ((4 5) 6)
+        for transaction in &transactions[4..] {
+            builder.push(*transaction);
+        }
+        assert_eq!(builder.len(), 7);
+        let mtree = builder.build().unwrap();
+        assert!(mtree.verify());
+        assert_eq!(mtree.get_currently_stored_data().len(), 7);
+        let node = <Sha256 as TreeHasher>::hash_node;
+        let expected = node(
+            MerkleTree::new(&transactions[..4]).get_root_hash(),
+            node(MerkleTree::new(&transactions[4..6]).get_root_hash(), MerkleTree::new(&transactions[6..]).get_root_hash())
+        );
+        assert_eq!(mtree.get_root_hash(), expected);
+    }
+
+    #[test]
+    fn test_mmr() {
+        let unknown = transactions(10)[9];
+        let transactions = transactions(7);
+        let mut mmr = Mmr::new();
+        assert_eq!(mmr.root(), None);
+        for (index, transaction) in transactions.iter().enumerate() {
+            assert_eq!(mmr.push(*transaction), index);
+            // Every element appended so far can be proven against the current root
+            let root = mmr.root().unwrap();
+            for transaction in &transactions[..=index] {
+                assert!(mmr.generate_proof(transaction).unwrap().verify(root, transaction));
+            }
+        }
+        assert_eq!(mmr.len(), 7);
+        assert!(mmr.generate_proof(&unknown).is_none());
+
+        // 7 elements make up peaks of 4, 2 and 1 elements
+        let peaks = mmr.peaks();
+        assert_eq!(peaks, vec![
+            MerkleTree::new(&transactions[..4]).get_root_hash(),
+            MerkleTree::new(&transactions[4..6]).get_root_hash(),
+            MerkleTree::new(&transactions[6..]).get_root_hash()
+        ]);
+        assert_eq!(bag_peaks(&peaks), mmr.root());
+        let mut builder = MerkleTreeBuilder::new();
+        transactions.iter().for_each(|transaction| builder.push(*transaction));
+        assert_eq!(mmr.root(), Some(builder.build().unwrap().get_root_hash()));
+        assert_eq!(mmr.into_merkle_tree().unwrap().get_root_hash(), bag_peaks(&peaks).unwrap());
+    }
+
+    #[test]
+    fn test_multi_proof() {
+        let transactions = transactions(7);
+        let mtree = MerkleTree::new(&transactions);
+        let root = mtree.get_root_hash();
+        let proven = [transactions[1], transactions[4], transactions[5]];
+
+        // Indices may be given in any order
+        let proof = mtree.generate_multi_proof(&[5, 1, 4, 1]).unwrap();
+        assert!(proof.verify(root, &proven));
+        assert!(!proof.verify(root, &[transactions[1], transactions[4], transactions[6]]));
+        assert!(!proof.verify(root, &proven[..2]));
+        assert_eq!(proof.calculate_root_hash(&[]), None);
+
+        // The tree is (0 (1 2)) ((3 4) (5 6)), so only the hashes of 0, 2, 3 and 6 are needed
+        // (instead of 3 hashes for each of the separate proofs)
+        assert_eq!(proof.hash_count(), 4);
+
+        // Works for other shapes of trees, too
+        let bitcoin = MerkleTree::new_bitcoin(&transactions);
+        let proof = bitcoin.generate_multi_proof(&[1, 4, 5]).unwrap();
+        assert!(proof.verify_with::<Sha256d, _>(bitcoin.get_root_hash(), &proven));
+
+        assert!(mtree.generate_multi_proof(&[]).is_none());
+        assert!(mtree.generate_multi_proof(&[1, 7]).is_none());
+    }
+
+    #[test]
+    #[cfg(feature = "parallel")]
+    fn test_new_parallel() {
+        let leaves : Vec<Vec<u8>> = (0..5000u32).map(|i| i.to_be_bytes().to_vec()).collect();
+        let mtree = MerkleTree::new_parallel(&leaves);
+        assert_eq!(mtree.get_root_hash(), MerkleTree::new(&leaves).get_root_hash());
+        assert!(mtree.verify());
+        assert_eq!(MerkleTree::try_new_parallel_with::<Sha256d>(&leaves).unwrap().get_root_hash(),
+                   MerkleTree::new_with::<Sha256d>(&leaves).get_root_hash());
+        assert_eq!(MerkleTree::<Vec<u8>>::try_new_parallel_with::<Sha256d>(&[]).unwrap_err(), MerkleTreeError::Empty);
+    }
+
+    #[test]
+    fn test_forget_and_restore_without_copy() {
+        let documents : Vec<String> = (0..3).map(|i| format!("document {}", i)).collect();
+        let mut mtree = MerkleTree::new(&documents);
+        assert!(mtree.forget_leaf(&documents[1]));
+        assert!(!mtree.forget_leaf(&documents[1]));
+        assert_eq!(mtree.get_currently_stored_data(), vec![documents[0].clone(), documents[2].clone()]);
+
+        // The element is moved back into the tree
+        assert!(mtree.restore_element(documents[1].clone()));
+        assert!(mtree.restore_element(documents[1].clone()));
+        assert!(!mtree.restore_element(String::from("unknown")));
+        assert_eq!(mtree.get_currently_stored_data(), documents);
+        assert!(mtree.verify());
+    }
+
+    #[test]
+    fn test_tree_stats() {
+        let transactions = transactions(5);
+        // (0 1) (2 (3 4))
+        let mut mtree = MerkleTree::new(&transactions);
+        assert_eq!(mtree.stats(), TreeStats {
+            depth: 3,
+            node_count: 9,
+            stored_leaf_count: 5,
+            forgotten_leaf_count: 0,
+            stored_bytes: 5 * 32
+        });
+
+        // A forgotten subtree counts as a single forgotten leaf
+        mtree.forget_leaf(&transactions[0]);
+        mtree.forget_subtree(MerkleTree::new(&transactions[3..]).get_root_hash());
+        assert_eq!(mtree.depth(), 2);
+        assert_eq!(mtree.node_count(), 7);
+        assert_eq!(mtree.stored_leaf_count(), 2);
+        assert_eq!(mtree.forgotten_leaf_count(), 2);
+        assert_eq!(mtree.stats().stored_bytes, 2 * 32);
+    }
+
+    #[test]
+    fn test_forget_where() {
+        let transactions : Vec<Transaction> = (0..5u32)
+            .map(|i| Transaction { index: i, signature: [i as u8; 32], ..TRANSACTION_1 })
+            .collect();
+        let mut mtree = MerkleTree::new(&transactions);
+        assert_eq!(mtree.forget_where(|transaction| transaction.index % 2 == 0), 3);
+        assert_eq!(mtree.get_currently_stored_data(), vec![transactions[1], transactions[3]]);
+        // Forgotten leaves are not passed to the predicate again
+        let mut calls = 0;
+        assert_eq!(mtree.forget_where(|_| { calls += 1; false }), 0);
+        assert_eq!(calls, 2);
+        assert!(mtree.verify());
+    }
+
+    #[test]
+    fn test_restore_all() {
+        use std::collections::HashMap;
+
+        let transactions = transactions(4);
+        let hash_of = |transaction : &Transaction| MerkleTree::new(&[*transaction]).get_root_hash();
+        let mut mtree = MerkleTree::new(&transactions);
+        mtree.forget_all_leaves();
+
+        // The provider knows two of the leaves, and lies about a third one
+        let mut provider : HashMap<ShaHash, Transaction> = HashMap::new();
+        provider.insert(hash_of(&transactions[0]), transactions[0]);
+        provider.insert(hash_of(&transactions[2]), transactions[2]);
+        provider.insert(hash_of(&transactions[3]), transactions[1]);
+        assert_eq!(mtree.restore_all(&provider), 2);
+        assert_eq!(mtree.get_currently_stored_data(), vec![transactions[0], transactions[2]]);
+        assert!(mtree.verify());
+    }
+
+    #[test]
+    fn test_flat_merkle_tree() {
+        use sha2::Sha256;
+        let transactions = transactions(5);
+        let mut mtree = MerkleTree::new(&transactions);
+        mtree.forget_leaf(&transactions[3]);
+        let flat = FlatMerkleTree::from(&mtree);
+        assert_eq!(flat.get_root_hash(), mtree.get_root_hash());
+        assert_eq!(flat.node_count(), mtree.node_count());
+        assert_eq!(flat.get_currently_stored_data(), mtree.get_currently_stored_data());
+        assert!(flat.verify());
+        assert!(!flat.verify_with::<Sha256d>());
+
+        // Converting back results in the same tree
+        let back = MerkleTree::from(flat);
+        assert!(back.diff(&mtree).is_empty());
+        assert_eq!(back.get_leaf_hashes(), mtree.get_leaf_hashes());
+
+        // Deep trees can be verified without recursion
+        let mut deep = MerkleTree::new(&transactions[..1]);
+        for transaction in &transactions[1..] {
+            let leaf = MerkleTree::new(std::slice::from_ref(transaction));
+            let hash = <Sha256 as TreeHasher>::hash_node(deep.get_root_hash(), leaf.get_root_hash());
+            deep = MerkleTree::Node { hash, left: Box::new(deep), right: Box::new(leaf) };
+        }
+        assert!(FlatMerkleTree::from(&deep).verify());
+    }
+
+    #[test]
+    fn test_verify_against() {
+        let transactions = transactions(4);
+        let mtree = MerkleTree::new(&transactions);
+        let root = mtree.get_root_hash();
+        assert!(mtree.verify_against(&root));
+        assert!(!mtree.verify_against(&ShaHash::ZERO));
+        assert!(!mtree.verify_against_with::<Sha256d>(&root));
+
+        // A consistent Merkle Tree of other data doesn't match the trusted root
+        let other = MerkleTree::new(&transactions[..3]);
+        assert!(other.verify());
+        assert!(!other.verify_against(&root));
+    }
+
+    #[test]
+    fn test_bytes_export() {
+        let data : Vec<Vec<u8>> = (0..5u8).map(|i| vec![i; i as usize]).collect();
+        let mut mtree = MerkleTree::new(&data);
+        mtree.forget_leaf(&data[1]);
+        let bytes = mtree.to_bytes().unwrap();
+        let imported : MerkleTree<Vec<u8>> = MerkleTree::from_bytes(&bytes).unwrap();
+        assert!(imported.verify_against(&mtree.get_root_hash()));
+        assert_eq!(imported.get_currently_stored_data(), mtree.get_currently_stored_data());
+        assert!(imported.diff(&mtree).is_empty());
+        assert_eq!(imported.to_bytes().unwrap(), bytes);
+
+        assert_eq!(MerkleTree::<Vec<u8>>::from_bytes(&bytes[..bytes.len() - 1]).unwrap_err(), BytesError::UnexpectedEnd);
+        assert_eq!(MerkleTree::<Vec<u8>>::from_bytes(&[2]).unwrap_err(), BytesError::UnknownVersion(2));
+        assert_eq!(MerkleTree::<Vec<u8>>::from_bytes(&[BYTES_FORMAT_VERSION, 3]).unwrap_err(), BytesError::UnexpectedEnd);
+        let mut invalid_tag = bytes.clone();
+        invalid_tag[1] = 3;
+        assert_eq!(MerkleTree::<Vec<u8>>::from_bytes(&invalid_tag).unwrap_err(), BytesError::InvalidTag { position: 1 });
+        let mut trailing = bytes.clone();
+        trailing.push(0);
+        assert_eq!(MerkleTree::<Vec<u8>>::from_bytes(&trailing).unwrap_err(), BytesError::TrailingBytes { position: bytes.len() });
+    }
+
+    #[test]
+    fn test_sorted_merkle_tree() {
+        use sha2::Sha256;
+        let transactions = transactions(20);
+        let (included, excluded) = transactions.split_at(10);
+        let sorted = SortedMerkleTree::new(included).unwrap();
+        let root = sorted.get_root_hash();
+        let mut leaf_hashes = sorted.merkle_tree().get_leaf_hashes();
+        assert_eq!(leaf_hashes.len(), 10);
+        leaf_hashes.sort_unstable();
+        assert_eq!(sorted.merkle_tree().get_leaf_hashes(), leaf_hashes);
+        let reversed : Vec<Transaction> = included.iter().rev().cloned().collect();
+        assert_eq!(SortedMerkleTree::new(&reversed).unwrap().get_root_hash(), root);
 
+        let proof = sorted.generate_proof(&included[3]).unwrap();
+        assert!(proof.verify_with::<DomainSeparated<Sha256>, Transaction>(root, &included[3]));
+        assert!(sorted.generate_non_inclusion_proof(&SortedMerkleTree::leaf_hash(&included[3])).is_none());
+
+        // Missing hashes between, before and after all leaves
+        for missing in excluded.iter().map(SortedMerkleTree::leaf_hash).chain(vec![ShaHash::ZERO, ShaHash::new([0xff; 32])]) {
+            let proof = sorted.generate_non_inclusion_proof(&missing).unwrap();
+            assert!(proof.verify(root, &missing));
+            assert!(!proof.verify(ShaHash::ZERO, &missing));
+        }
+        // A proof for one missing hash does not work for others, nor for contained ones
+        let missing = SortedMerkleTree::leaf_hash(&excluded[0]);
+        let proof = sorted.generate_non_inclusion_proof(&missing).unwrap();
+        for leaf_hash in &leaf_hashes {
+            assert!(!proof.verify(root, leaf_hash));
+        }
+        assert!(SortedMerkleTree::new(&Vec::<Transaction>::new()).is_err());
+    }
+
+    #[test]
+    fn test_update_leaf() {
+        let updated = transactions(6)[5];
+        let mut transactions = transactions(5);
+        let mut mtree = MerkleTree::new(&transactions);
+        transactions[3] = updated;
+        assert_eq!(mtree.update_leaf(3, updated), Some(MerkleTree::new(&transactions).get_root_hash()));
+        assert!(mtree.verify());
+        assert_eq!(mtree.get_currently_stored_data(), transactions);
+        assert_eq!(mtree.update_leaf(5, updated), None);
+        assert_eq!(mtree.get_root_hash(), MerkleTree::new(&transactions).get_root_hash());
+
+        // Forgotten leaves can be replaced as well
+        mtree.forget_all_leaves();
+        transactions[0] = updated;
+        assert_eq!(mtree.update_leaf(0, updated), Some(MerkleTree::new(&transactions).get_root_hash()));
+        assert!(mtree.verify());
+
+        let mut other = MerkleTree::new_with::<Sha256d>(&transactions);
+        transactions[4] = updated;
+        other.update_leaf_with::<Sha256d>(4, updated);
+        assert_eq!(other.get_root_hash(), MerkleTree::new_with::<Sha256d>(&transactions).get_root_hash());
+    }
+
+    #[test]
+    fn test_merge() {
+        let transactions = transactions(6);
+        let full = MerkleTree::new(&transactions);
+        let mut a = full.clone();
+        let mut b = full.clone();
+        a.forget_where(|transaction| transaction.signature[0] % 2 == 0);
+        b.forget_where(|transaction| transaction.signature[0] % 2 == 1);
+        let mut merged = a.clone();
+        merged.merge(b.clone()).unwrap();
+        assert!(merged.verify());
+        assert_eq!(merged.get_currently_stored_data(), transactions);
+        let mut merged = b.clone();
+        merged.merge(a.clone()).unwrap();
+        assert_eq!(merged.get_currently_stored_data(), transactions);
+
+        let mut shrunk = full.clone();
+        shrunk.shrink_to_minimum();
+        shrunk.merge(a.clone()).unwrap();
+        assert_eq!(shrunk.get_currently_stored_data(), a.get_currently_stored_data());
+        assert_eq!(a.clone().merge(MerkleTree::new(&transactions[1..])).unwrap_err(), MergeError::DifferentRoots);
+
+        // A tree claiming the same root hash but with other children
+        let fake = MerkleTree::Node {
+            hash: full.get_root_hash(),
+            left: Box::new(MerkleTree::Leaf { hash: ShaHash::new([1; 32]), data: None }),
+            right: Box::new(MerkleTree::Leaf { hash: ShaHash::new([2; 32]), data: None })
+        };
+        let mut unchanged = a.clone();
+        assert_eq!(unchanged.merge(fake).unwrap_err(), MergeError::Inconsistent { hash: full.get_root_hash() });
+        assert_eq!(unchanged.get_currently_stored_data(), a.get_currently_stored_data());
+
+        // Nothing is merged when only the right subtree is inconsistent, not even the left one
+        let (left, right) = match b.clone() {
+            MerkleTree::Node { left, right, .. } => (left, right),
+            MerkleTree::Leaf { .. } => unreachable!()
+        };
+        let fake_right = MerkleTree::Node {
+            hash: right.get_root_hash(),
+            left: Box::new(MerkleTree::Leaf { hash: ShaHash::new([1; 32]), data: None }),
+            right: Box::new(MerkleTree::Leaf { hash: ShaHash::new([2; 32]), data: None })
+        };
+        let partly_fake = MerkleTree::Node { hash: full.get_root_hash(), left, right: Box::new(fake_right) };
+        let mut unchanged = a.clone();
+        assert!(unchanged.merge(partly_fake.clone()).is_err());
+        assert!(!unchanged.restore_subtree(partly_fake));
+        assert_eq!(unchanged.get_currently_stored_data(), a.get_currently_stored_data());
+    }
+
+    #[test]
+    fn test_leaf_proof() {
+        let data : Vec<Vec<u8>> = vec![vec![1; 10], vec![2; 2000], vec![3; 10]];
+        let mut mtree = MerkleTree::new(&data);
+        let root = mtree.get_root_hash();
+        let leaf_hashes = mtree.get_leaf_hashes();
+
+        let small = mtree.generate_leaf_proof(&leaf_hashes[0], &ProofOptions::default()).unwrap();
+        assert_eq!(small.data(), Some(&data[0][..]));
+        assert!(small.verify(root));
+        let big = mtree.generate_leaf_proof(&leaf_hashes[1], &ProofOptions::default()).unwrap();
+        assert_eq!(big.leaf, ProvenLeaf::Hash(leaf_hashes[1]));
+        assert!(big.verify(root));
+        let options = ProofOptions { max_embedded_bytes: 2000 };
+        assert_eq!(mtree.generate_leaf_proof(&leaf_hashes[1], &options).unwrap().data(), Some(&data[1][..]));
+
+        // Tampered data and forgotten leaves
+        let mut tampered = small.clone();
+        tampered.leaf = ProvenLeaf::Data(vec![1; 11]);
+        assert!(!tampered.verify(root));
+        mtree.forget_leaf(&data[2]);
+        let forgotten = mtree.generate_leaf_proof(&leaf_hashes[2], &ProofOptions::default()).unwrap();
+        assert_eq!(forgotten.data(), None);
+        assert!(forgotten.verify(root));
+        assert!(mtree.generate_leaf_proof(&ShaHash::ZERO, &ProofOptions::default()).is_none());
+    }
+
+    #[test]
+    fn test_visit_nodes() {
+        let transactions = transactions(3);
+        let mut mtree = MerkleTree::new(&transactions);
+        mtree.forget_leaf(&transactions[1]);
+        let mut visited = Vec::new();
+        mtree.visit_nodes(|node| visited.push(node));
+        assert_eq!(visited.len(), mtree.node_count());
+        assert_eq!(visited[0], NodeInfo { hash: mtree.get_root_hash(), depth: 0, kind: NodeKind::Inner });
+        assert_eq!(visited.iter().map(|node| node.depth).max(), Some(mtree.depth()));
+
+        // The leaves come from left to right
+        let leaves : Vec<(ShaHash, Option<&Transaction>)> = visited.iter()
+            .filter_map(|node| match node.kind {
+                NodeKind::Leaf { data } => Some((node.hash, data)),
+                NodeKind::Inner => None
+            })
+            .collect();
+        assert_eq!(leaves.iter().map(|(hash, _)| *hash).collect::<Vec<ShaHash>>(), mtree.get_leaf_hashes());
+        assert_eq!(leaves.iter().map(|(_, data)| data.is_some()).collect::<Vec<bool>>(), vec![true, false, true]);
+    }
+
+    #[test]
+    fn test_data_availability() {
+        let leaves : Vec<Vec<u8>> = (0..7u8).map(|i| vec![i; 3 * i as usize]).collect();
+        let mtree = MerkleTree::new(&leaves);
+        let coded = ErasureCoded::encode_leaves(&mtree, 4).unwrap();
+        assert_eq!(coded.share_count(), 8);
+        let commitment = coded.commitment();
+
+        // Light clients sample shares chosen by a seed
+        let indices = sample_indices(&mtree.get_root_hash(), 3, coded.share_count());
+        assert_eq!(indices.len(), 3);
+        assert_eq!(indices, sample_indices(&mtree.get_root_hash(), 3, coded.share_count()));
+        for index in indices {
+            let (share, proof) = coded.sample(index).unwrap();
+            assert!(verify_sample(commitment, index, &share, &proof));
+            assert!(!verify_sample(commitment, (index + 1) % 8, &share, &proof));
+        }
+        assert!(coded.sample(8).is_none());
+        assert_eq!(sample_indices(&ShaHash::ZERO, 20, 8).len(), 8);
+
+        // Any half of the shares is enough to reconstruct the leaves, e.g. only the parity shares
+        let parity = &coded.shares()[4..];
+        let reconstructed : Vec<Vec<u8>> = reconstruct_leaves(parity, 4).unwrap();
+        assert!(MerkleTree::new(&reconstructed).verify_against(&mtree.get_root_hash()));
+        let mixed = vec![coded.shares()[1].clone(), coded.shares()[6].clone(), coded.shares()[3].clone(), coded.shares()[7].clone()];
+        assert_eq!(reconstruct_leaves::<Vec<u8>>(&mixed, 4).unwrap(), leaves);
+        assert!(reconstruct(&mixed[..3], 4).is_none());
+
+        let data = b"Hello, world!";
+        let coded = ErasureCoded::encode(data, 1).unwrap();
+        assert_eq!(reconstruct(&coded.shares()[1..], 1).unwrap(), data.to_vec());
+        assert!(ErasureCoded::encode(data, MAX_DATA_SHARES + 1).is_none());
+
+        // Shares received from others need at least their index
+        assert!(Share::from_bytes(Vec::new()).is_none());
+        assert_eq!(Share::from_bytes(coded.shares()[1].as_ref().to_vec()).as_ref(), Some(&coded.shares()[1]));
+        #[cfg(feature = "serde")]
+        {
+            let json = serde_json::to_string(&coded.shares()[1]).unwrap();
+            assert_eq!(serde_json::from_str::<Share>(&json).unwrap(), coded.shares()[1]);
+            assert!(serde_json::from_str::<Share>(r#"{"bytes":[]}"#).is_err());
+        }
+    }
+
+    #[test]
+    fn test_path_to() {
+        let transactions = transactions(4);
+        let mtree = MerkleTree::new(&transactions);
+        let leaf_hashes = mtree.get_leaf_hashes();
+        assert_eq!(mtree.path_to(&mtree.get_root_hash()), Some(Vec::new()));
+        assert_eq!(mtree.path_to(&leaf_hashes[0]), Some(vec![Direction::Left, Direction::Left]));
+        assert_eq!(mtree.path_to(&leaf_hashes[2]), Some(vec![Direction::Right, Direction::Left]));
+        assert_eq!(mtree.path_to(&ShaHash::ZERO), None);
+
+        // Leaves inside a forgotten subtree can't be found anymore
+        let mut pruned = mtree.clone();
+        pruned.forget_subtree(MerkleTree::new(&transactions[2..]).get_root_hash());
+        assert_eq!(pruned.path_to(&leaf_hashes[3]), None);
+        assert_eq!(pruned.path_to(&MerkleTree::new(&transactions[2..]).get_root_hash()), Some(vec![Direction::Right]));
     }
 
+    #[test]
+    fn test_namespaces() {
+        let leaves = vec![
+            NamespacedLeaf::new(3, b"c1"), NamespacedLeaf::new(1, b"a1"), NamespacedLeaf::new(5, b"e1"),
+            NamespacedLeaf::new(3, b"c2"), NamespacedLeaf::new(1, b"a2"), NamespacedLeaf::new(3, b"c3")
+        ];
+        let mtree = namespaced_tree(&leaves).unwrap();
+        let root = mtree.get_root_hash();
+        assert!(mtree.verify());
+        assert!(is_namespaced_tree(&mtree));
+        assert!(!is_namespaced_tree(&MerkleTree::new(&leaves)));
+
+        // Namespaces in the middle, first and last, each with all of their leaves
+        for (namespace, data) in [(3, vec![&b"c1"[..], b"c2", b"c3"]), (1, vec![b"a1", b"a2"]), (5, vec![b"e1"])] {
+            let proof = proof_for_namespace(&mtree, namespace).unwrap();
+            assert!(proof.verify(root, namespace));
+            assert!(!proof.verify(root, namespace + 1));
+            let proven : Vec<&[u8]> = proof.leaves().iter().map(NamespacedLeaf::data).collect();
+            assert_eq!(proven, data);
+        }
+
+        // Namespaces without leaves, before, between and after the others
+        for namespace in [0, 2, 4, 6] {
+            let proof = proof_for_namespace(&mtree, namespace).unwrap();
+            assert!(proof.leaves().is_empty());
+            assert!(proof.verify(root, namespace));
+        }
+        // A proof of an empty namespace can't hide the leaves of another one
+        assert!(!proof_for_namespace(&mtree, 2).unwrap().verify(root, 3));
+
+        // Leaving out a leaf is noticed, even when the tree is built without it
+        let mut incomplete = leaves.clone();
+        incomplete.remove(3);
+        let other = namespaced_tree(&incomplete).unwrap();
+        assert!(!proof_for_namespace(&other, 3).unwrap().verify(root, 3));
+
+        // Duplicates of leaves are proven as well
+        let mut duplicated = leaves.clone();
+        duplicated.push(NamespacedLeaf::new(1, b"a2"));
+        let mtree = namespaced_tree(&duplicated).unwrap();
+        for namespace in 0..7 {
+            assert!(proof_for_namespace(&mtree, namespace).unwrap().verify(mtree.get_root_hash(), namespace));
+        }
+
+        // A single namespace
+        let mtree = namespaced_tree(&[NamespacedLeaf::new(7, b"only")]).unwrap();
+        assert!(proof_for_namespace(&mtree, 7).unwrap().verify(mtree.get_root_hash(), 7));
+        assert!(proof_for_namespace(&mtree, 8).unwrap().verify(mtree.get_root_hash(), 8));
+        assert!(namespaced_tree(&[]).is_err());
+        assert_eq!(NamespacedLeaf::from_bytes(vec![0; 7]), None);
+    }
+
+    #[test]
+    fn test_leaf_codec() {
+        use std::convert::TryInto;
+        // Only the signature is hashed, but the whole transfer has to be exported
+        #[derive(Clone, Debug, PartialEq)]
+        struct Transfer {
+            amount : u64,
+            signature : [u8; 32]
+        }
+
+        impl AsRef<[u8]> for Transfer {
+            fn as_ref(&self) -> &[u8] {
+                &self.signature
+            }
+        }
+
+        impl LeafCodec for Transfer {
+            fn encode(&self) -> Vec<u8> {
+                let mut bytes = self.amount.to_be_bytes().to_vec();
+                bytes.extend_from_slice(&self.signature);
+                bytes
+            }
+
+            fn decode(bytes: &[u8]) -> Option<Self> {
+                if bytes.len() != 40 {
+                    return None;
+                }
+                Some(Transfer {
+                    amount: u64::from_be_bytes(bytes[..8].try_into().unwrap()),
+                    signature: bytes[8..].try_into().unwrap()
+                })
+            }
+        }
+
+        let transfers : Vec<Transfer> = (0..3u8).map(|i| Transfer { amount: 100 * i as u64, signature: [i; 32] }).collect();
+        let mtree = MerkleTree::new(&transfers);
+        let from_xml : MerkleTree<Transfer> = MerkleTree::import_xml(&mtree.export_xml()).unwrap();
+        assert!(from_xml.verify_against(&mtree.get_root_hash()));
+        assert_eq!(from_xml.get_currently_stored_data(), transfers);
+        let from_bytes : MerkleTree<Transfer> = MerkleTree::from_bytes(&mtree.to_bytes().unwrap()).unwrap();
+        assert_eq!(from_bytes.get_currently_stored_data(), transfers);
+        assert!(MerkleTree::<Transfer>::import_xml(&MerkleTree::new(&["text".to_string()]).export_xml()).is_err());
+        assert_eq!(LeafCodec::decode_string(&"text".to_string().encode_string()), Some("text".to_string()));
+    }
+
+    #[test]
+    fn test_lazy_merkle_tree() {
+        use sha2::Sha256;
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        // SHA-256, counting how many hashes are calculated
+        static HASHES : AtomicUsize = AtomicUsize::new(0);
+        struct Counting;
+        impl TreeHasher for Counting {
+            fn hash_leaf(data: &[u8]) -> ShaHash {
+                HASHES.fetch_add(1, Ordering::SeqCst);
+                <Sha256 as TreeHasher>::hash_leaf(data)
+            }
+
+            fn hash_node(left: ShaHash, right: ShaHash) -> ShaHash {
+                HASHES.fetch_add(1, Ordering::SeqCst);
+                <Sha256 as TreeHasher>::hash_node(left, right)
+            }
+        }
+
+        let all = transactions(14);
+        let mut transactions = all[..8].to_vec();
+        let mut lazy = LazyMerkleTree::new_with::<Counting>(transactions.clone());
+        assert_eq!(HASHES.load(Ordering::SeqCst), 0);
+        assert_eq!(lazy.get_root_hash(), Some(MerkleTree::new(&transactions).get_root_hash()));
+        assert_eq!(HASHES.load(Ordering::SeqCst), 15);
+        // The hashes are cached
+        let mtree = lazy.to_merkle_tree().unwrap();
+        assert!(mtree.verify());
+        assert_eq!(mtree.get_currently_stored_data(), transactions);
+        assert_eq!(HASHES.load(Ordering::SeqCst), 15);
+
+        // Changing an element only invalidates the hashes above it, and only once they are needed
+        for replacement in &all[11..] {
+            transactions[5] = *replacement;
+            assert!(lazy.update(5, *replacement).is_some());
+        }
+        assert_eq!(HASHES.load(Ordering::SeqCst), 15);
+        assert_eq!(lazy.get_root_hash(), Some(MerkleTree::new(&transactions).get_root_hash()));
+        assert_eq!(HASHES.load(Ordering::SeqCst), 19);
+        assert_eq!(lazy.update(8, TRANSACTION_1), None);
+
+        // Pushing changes the shape of the tree
+        for transaction in &all[8..11] {
+            transactions.push(*transaction);
+            lazy.push(*transaction);
+            assert_eq!(lazy.get_root_hash(), Some(MerkleTree::new(&transactions).get_root_hash()));
+        }
+        assert_eq!(lazy.to_merkle_tree().unwrap().get_root_hash(), MerkleTree::new(&transactions).get_root_hash());
+        assert_eq!(lazy.len(), 11);
+
+        let empty : LazyMerkleTree<Transaction> = LazyMerkleTree::new(Vec::new());
+        assert!(empty.is_empty());
+        assert_eq!(empty.get_root_hash(), None);
+        assert_eq!(empty.to_merkle_tree().unwrap_err(), MerkleTreeError::Empty);
+    }
+
+    #[test]
+    fn test_sha_hash() {
+        use std::convert::TryFrom;
+        use std::str::FromStr;
+        let mut bytes = [0u8; 32];
+        bytes[0] = 0xab;
+        bytes[31] = 0x01;
+        let hash = ShaHash::new(bytes);
+        let text = format!("ab{}01", "00".repeat(30));
+        assert_eq!(hash.to_string(), text);
+        assert_eq!(format!("{:x}", hash), text);
+        assert_eq!(format!("{:?}", hash), format!("ShaHash({})", text));
+        assert_eq!(ShaHash::from_str(&text), Ok(hash));
+        assert!(ShaHash::from_str("abcd").is_err());
+        assert!(ShaHash::from_str(&"zz".repeat(32)).is_err());
+
+        assert_eq!(<[u8; 32]>::from(hash), bytes);
+        assert_eq!(ShaHash::try_from(&bytes[..]).unwrap(), hash);
+        assert!(ShaHash::try_from(&bytes[1..]).is_err());
+        assert_ne!(hash, ShaHash::ZERO);
+        assert!(ShaHash::ZERO < hash && hash < ShaHash::new([0xff; 32]));
+        assert_eq!(ShaHash::default(), ShaHash::ZERO);
+
+        // Hashes are written as hex in the exports
+        let mtree = MerkleTree::new(&["Alice".to_string()]);
+        assert!(mtree.export_xml().contains(&mtree.get_root_hash().to_string()));
+    }
+
+    #[test]
+    fn test_empty_tree() {
+        let empty: MerkleTree<Vec<u8>> = MerkleTree::new(&[]);
+        assert!(empty.is_empty());
+        assert_eq!(empty.get_root_hash(), EMPTY_TREE_ROOT);
+        assert!(empty.verify() && empty.verify_against(&EMPTY_TREE_ROOT));
+        assert!(MerkleTree::<Vec<u8>>::new_with::<DomainSeparated<Sha256d>>(&[]).is_empty());
+        assert!(MerkleTree::<Vec<u8>>::try_new(&[]).is_err());
+        assert!(MerkleTree::<Vec<u8>>::new_bitcoin(&[]).is_empty());
+        assert!(MerkleTree::<Vec<u8>>::new_bitcoin_from_leaf_hashes(&[]).is_empty());
+        assert!(empty.get_currently_stored_data().is_empty());
+        assert!(empty.get_leaf_hashes().is_empty());
+        assert_eq!((empty.stored_leaf_count(), empty.forgotten_leaf_count()), (0, 0));
+        assert!(!MerkleTree::new(&[vec![0u8]]).is_empty());
+        // There is no leaf to update
+        assert_eq!(empty.clone().update_leaf(0, vec![1]), None);
+    }
+
+    #[test]
+    fn test_forget_below_depth() {
+        let transactions = transactions(8);
+        let original = MerkleTree::new(&transactions);
+        assert_eq!(original.depth(), 3);
+
+        let mut mtree = original.clone();
+        mtree.forget_below_depth(1);
+        assert_eq!((mtree.depth(), mtree.node_count(), mtree.stored_leaf_count()), (1, 3, 0));
+        assert_eq!(mtree.get_root_hash(), original.get_root_hash());
+        assert!(mtree.verify());
+        // The forgotten subtrees can be restored
+        assert!(mtree.restore_subtree(MerkleTree::new(&transactions[..4])));
+        assert_eq!(mtree.get_currently_stored_data(), transactions[..4].to_vec());
+
+        let mut minimum = original.clone();
+        minimum.forget_below_depth(0);
+        assert_eq!(minimum.node_count(), 1);
+        let mut all_leaves = original.clone();
+        all_leaves.forget_below_depth(10);
+        assert_eq!((all_leaves.node_count(), all_leaves.stored_leaf_count()), (original.node_count(), 0));
+    }
+
+    /// The tests of the Blocks and Blockchains and everything built on top of them.
+    #[cfg(feature = "blockchain")]
+    mod blockchain {
+        use super::*;
+        use rust_blockchain::block::*;
+        use rust_blockchain::blockchain::*;
+        use rust_blockchain::bridge::*;
+        use rust_blockchain::audit_log::*;
+        use rust_blockchain::header_batch::*;
+        use rust_blockchain::pow::*;
+        use rust_blockchain::chain_params::*;
+        use rust_blockchain::overlay::*;
+        use rust_blockchain::watchtower::*;
+        use rust_blockchain::observer::*;
+        use rust_blockchain::governance::*;
+        use rust_blockchain::bulk_import::*;
+        use rust_blockchain::replica::*;
+        use rust_blockchain::kv_state::*;
+        use rust_blockchain::beacon::*;
+
+        #[test]
+        fn test_block() {
+            let previous_hash : ShaHash = ShaHash::new([11u8; 32]);
+            let data : MerkleTree<Transaction> = MerkleTree::new(&[TRANSACTION_1]);
+            let mut test_block : Block<Transaction> = Block::new(previous_hash, data);
+            assert!(!test_block.verify());
+            test_block.calculate_nonce();
+            assert!(test_block.verify());
+        }
+
+        #[test]
+        fn test_header_relay() {
+            // Mine a few Blocks on a foreign Blockchain
+            let mut foreign_chain : Blockchain<Transaction> = Blockchain::new();
+            let foreign_blocks : Vec<Block<Transaction>> = transactions(3).into_iter()
+                .map(|transaction| foreign_chain.append_data(MerkleTree::new(&[transaction])))
+                .collect();
+
+            // Relay their headers on this Blockchain
+            let relayed = relay_headers(&foreign_blocks);
+            assert!(verify_relayed_headers(INITIAL_HASH, &relayed));
+            let mut chain : Blockchain<BlockHeader> = Blockchain::new();
+            chain.append_data(relayed);
+            assert!(chain.verify());
+
+            // A gap in the foreign Blockchain is detected
+            let headers : Vec<BlockHeader> = foreign_blocks.iter().map(|block| block.header()).collect();
+            assert!(verify_header_chain(INITIAL_HASH, &headers));
+            assert!(verify_header_chain(headers[0].calculate_hash(), &headers[1..]));
+            assert!(!verify_header_chain(INITIAL_HASH, &[headers[0].clone(), headers[2].clone()]));
+            assert_eq!(headers[2].calculate_hash(), foreign_chain.hash_of_last_block());
+        }
+
+        #[test]
+        fn test_audit_log() {
+            let entries = transactions(5);
+            let mut log : AuditLog<Transaction> = AuditLog::new();
+            let first_receipt = log.append(entries[0]);
+            let old_head = log.head(log.size()).unwrap();
+            let receipts = log.append_all(&entries[1..]);
+
+            // Receipts prove their entries, but no other entries
+            assert!(log.verify_receipt(&first_receipt, &entries[0]));
+            assert_eq!((receipts[2].height, receipts[2].leaf_index), (1, 2));
+            assert!(log.verify_receipt(&receipts[2], &entries[3]));
+            assert!(!log.verify_receipt(&receipts[2], &entries[4]));
+
+            // The log was only appended to
+            let new_head = log.head(log.size()).unwrap();
+            let proof = log.consistency_proof(1, 2).unwrap();
+            assert!(verify_consistency(old_head, new_head, &proof));
+            assert!(!verify_consistency(INITIAL_HASH, new_head, &proof));
+            assert!(log.consistency_proof(2, 1).is_none());
+
+            // Equal entries get receipts for their own positions
+            let duplicates = log.append_all(&[entries[0], entries[1], entries[0]]);
+            assert_ne!(duplicates[0].proof, duplicates[2].proof);
+            assert!(log.verify_receipt(&duplicates[0], &entries[0]));
+            assert!(log.verify_receipt(&duplicates[2], &entries[0]));
+            let wrong_index = Receipt { leaf_index: 0, ..duplicates[2].clone() };
+            assert!(!log.verify_receipt(&wrong_index, &entries[0]));
+        }
+
+        #[test]
+        fn test_consistency_proof() {
+            let mut chain : Blockchain<Transaction> = Blockchain::new();
+            for transaction in transactions(4) {
+                chain.append_data(MerkleTree::new(&[transaction]));
+            }
+            let old_tip = chain.get_block(1).unwrap().calculate_hash();
+            let new_tip = chain.hash_of_last_block();
+
+            let proof = chain.consistency_proof(old_tip, new_tip).unwrap();
+            assert_eq!(proof.len(), 2);
+            assert!(verify_consistency(old_tip, new_tip, &proof));
+            assert!(verify_consistency(INITIAL_HASH, new_tip, &chain.consistency_proof(INITIAL_HASH, new_tip).unwrap()));
+            assert!(chain.consistency_proof(new_tip, old_tip).is_none());
+
+            // A rewritten history is detected
+            let mut rewritten : Blockchain<Transaction> = Blockchain::new();
+            for transaction in &transactions(8)[4..] {
+                rewritten.append_data(MerkleTree::new(&[*transaction]));
+            }
+            let forged_proof = rewritten.consistency_proof(INITIAL_HASH, rewritten.hash_of_last_block()).unwrap();
+            assert!(!verify_consistency(old_tip, rewritten.hash_of_last_block(), &forged_proof[2..]));
+        }
+
+        #[test]
+        fn test_prune_report() {
+            let transactions = transactions(6);
+            let mut chain : Blockchain<Transaction> = Blockchain::new();
+            for range in [0..1, 1..3, 3..6] {
+                chain.append_data(MerkleTree::new(&transactions[range]));
+            }
+
+            let report = chain.prune(0..2, |mtree| mtree.forget_all_leaves());
+            assert_eq!(report.per_block, vec![
+                (0, Reclaimed { leaves: 1, bytes: 32 }),
+                (1, Reclaimed { leaves: 2, bytes: 64 })
+            ]);
+            assert_eq!(report.total, Reclaimed { leaves: 3, bytes: 96 });
+
+            // Already forgotten data is not reclaimed twice, heights beyond the end are ignored
+            let report = chain.prune(1..10, |mtree| mtree.shrink_to_minimum());
+            assert_eq!(report.total, Reclaimed { leaves: 3, bytes: 96 });
+            assert_eq!(chain.stats().reclaimed, Reclaimed { leaves: 6, bytes: 192 });
+            assert!(chain.verify());
+        }
+
+        #[test]
+        fn test_deterministic_mining() {
+            let mine = |seed : Option<u64>| {
+                let mut chain : Blockchain<Transaction> = Blockchain::new();
+                for transaction in transactions(3) {
+                    let data = MerkleTree::new(&[transaction]);
+                    match seed {
+                        Some(seed) => chain.append_data_seeded(data, seed),
+                        None => chain.append_data(data)
+                    };
+                }
+                assert!(chain.verify());
+                chain.hash_of_last_block()
+            };
+            assert_eq!(mine(None), mine(None));
+            assert_eq!(mine(Some(7)), mine(Some(7)));
+            assert_ne!(mine(Some(7)), mine(Some(8)));
+        }
+
+        #[test]
+        fn test_header_batch() {
+            let mut chain : Blockchain<Transaction> = Blockchain::new();
+            for transaction in transactions(10) {
+                chain.append_data(MerkleTree::new(&[transaction]));
+            }
+            let headers : Vec<BlockHeader> = (0..chain.length())
+                .map(|height| chain.get_block(height).unwrap().header())
+                .collect();
+
+            let compressed = compress_headers(&headers);
+            assert!(compressed.len() < headers.len() * HEADER_SIZE * 2 / 3);
+            assert_eq!(decompress_headers(&compressed), Some(headers));
+            assert_eq!(decompress_headers(&compressed[..compressed.len() - 1]), None);
+            assert_eq!(decompress_headers(&[]), Some(vec![]));
+
+            // Every nonce has exactly one encoding
+            let batch = |nonce : &[u8]| [&[0u8; 32][..], nonce, &[1u8; 32][..]].concat();
+            let max = BlockHeader::new(ShaHash::ZERO, u64::MAX, ShaHash::new([1; 32]));
+            let mut max_nonce = vec![0xff; 9];
+            max_nonce.push(0x01);
+            assert_eq!(compress_headers(std::slice::from_ref(&max)), batch(&max_nonce));
+            assert_eq!(decompress_headers(&batch(&max_nonce)), Some(vec![max]));
+            *max_nonce.last_mut().unwrap() = 0x03; // bits beyond the 64th
+            assert_eq!(decompress_headers(&batch(&max_nonce)), None);
+            let five = BlockHeader::new(ShaHash::ZERO, 5, ShaHash::new([1; 32]));
+            assert_eq!(decompress_headers(&batch(&[0x05])), Some(vec![five]));
+            assert_eq!(decompress_headers(&batch(&[0x85, 0x00])), None); // overlong
+            assert_eq!(decompress_headers(&batch(&[0x85, 0x80, 0x00])), None);
+            assert_eq!(decompress_headers(&batch(&[0x00])).unwrap()[0].nonce(), 0);
+        }
+
+        #[test]
+        fn test_pow_functions() {
+            assert_eq!(hex::encode(Sha256Pow.hash(b"abc")),
+                       "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad");
+            assert_eq!(hex::encode(Sha256dPow.hash(b"abc")),
+                       "4f8b42c22dd3729b519ba6f68d2da7cc5b2d606d05daed5ad5128cc03e6c6358");
+            #[cfg(feature = "blake3")]
+            assert_eq!(hex::encode(Blake3Pow.hash(b"")),
+                       "af1349b9f5f9a1a6a0404dea36dcc9499bcb25c9adc112b7cc9a93cae41f3262");
+
+            // Mining with a different proof of work function
+            let params = ChainParams { pow: &Sha256dPow, ..ChainParams::default() };
+            let mut chain : Blockchain<Transaction> = Blockchain::with_params(params);
+            for transaction in transactions(3) {
+                let block = chain.append_data(MerkleTree::new(&[transaction]));
+                assert!(block.verify_with(&params));
+            }
+            assert!(chain.verify());
+            let proof = chain.consistency_proof(INITIAL_HASH, chain.hash_of_last_block()).unwrap();
+            assert!(verify_consistency_with(&params, INITIAL_HASH, chain.hash_of_last_block(), &proof));
+            assert!(!verify_consistency(INITIAL_HASH, chain.hash_of_last_block(), &proof));
+        }
+
+        #[test]
+        fn test_initial_hash() {
+            let params_a = ChainParams::for_genesis_document(b"Network A, started on 2021-03-01");
+            let params_b = ChainParams::for_genesis_document(b"Network B, started on 2021-03-01");
+            let mut chain_a : Blockchain<Transaction> = Blockchain::with_params(params_a);
+            let mut chain_b : Blockchain<Transaction> = Blockchain::with_params(params_b);
+            assert_eq!(chain_a.hash_of_last_block(), params_a.initial_hash);
+            assert_ne!(params_a.initial_hash, INITIAL_HASH);
+
+            // The first Block of one network is not accepted by the other one
+            let block = chain_a.append_data(MerkleTree::new(&[TRANSACTION_1]));
+            assert!(chain_a.verify());
+            assert!(!chain_b.append_block(block.clone()));
+            assert!(!Blockchain::<Transaction>::new().append_block(block));
+            assert_eq!(chain_b.length(), 0);
+            chain_b.append_data(MerkleTree::new(&[TRANSACTION_1]));
+            assert!(chain_b.verify());
+        }
+
+        #[test]
+        fn test_overlay() {
+            let transactions = transactions(3);
+            let mut chain : Blockchain<Transaction> = Blockchain::new();
+            chain.append_data(MerkleTree::new(&[TRANSACTION_1]));
+
+            let mut overlay : Overlay<Transaction> = chain.overlay();
+            let candidate = overlay.append_data(MerkleTree::new(&[transactions[1]]));
+            assert!(!overlay.append_block(candidate.clone())); // does not link to the new last Block
+            overlay.append_data(MerkleTree::new(&[transactions[2]]));
+            assert_eq!(overlay.length(), 3);
+            assert_eq!(overlay.get_block(1).unwrap().calculate_hash(), candidate.calculate_hash());
+            assert_eq!(chain.length(), 1);
+
+            // Apply the overlay to the Blockchain
+            let blocks = overlay.into_blocks();
+            for block in blocks {
+                assert!(chain.append_block(block));
+            }
+            assert_eq!(chain.length(), 3);
+            assert!(chain.verify());
+        }
+
+        #[test]
+        fn test_sequence_numbers() {
+            let transactions = transactions(5);
+            let hash_of = |transaction : &Transaction| -> ShaHash {
+                MerkleTree::new(&[*transaction]).get_root_hash()
+            };
+            let mut chain : Blockchain<Transaction> = Blockchain::new();
+            chain.append_data(MerkleTree::new(&transactions[..2]));
+            assert_eq!(chain.sequence_of(&hash_of(&transactions[0])), None);
+
+            // Enabling numbers the existing leaves, later ones are numbered on confirmation
+            chain.enable_sequence_numbers();
+            chain.append_data(MerkleTree::new(&transactions[2..]));
+            let sequence : Vec<Option<u64>> = transactions.iter()
+                .map(|transaction| chain.sequence_of(&hash_of(transaction)))
+                .collect();
+            assert_eq!(sequence, vec![Some(0), Some(1), Some(2), Some(3), Some(4)]);
+
+            // Forgetting data does not change the order
+            chain.prune(0..2, |mtree| mtree.shrink_to_minimum());
+            assert_eq!(chain.sequence_of(&hash_of(&transactions[3])), Some(3));
+        }
+
+        #[test]
+        fn test_first_invalid() {
+            let mut chain : Blockchain<Transaction> = Blockchain::new();
+            for transaction in transactions(4) {
+                chain.append_data(MerkleTree::new(&[transaction]));
+            }
+            assert_eq!(chain.first_invalid(), None);
+
+            // Tamper with the data of Block 2 without changing its root hash
+            let forged = Transaction { amount: 1_000_000.0, signature: [99; 32], ..TRANSACTION_1 };
+            chain.prune(2..3, |mtree| *mtree = MerkleTree::Leaf { hash: mtree.get_root_hash(), data: Some(forged) });
+            assert_eq!(chain.first_invalid(), Some((2, BlockchainError::InvalidMerkleTree)));
+
+            // Replace the data of Block 1, changing its hash
+            chain.prune(1..2, |mtree| *mtree = MerkleTree::new(&[forged]));
+            assert_eq!(chain.first_invalid(), Some((1, BlockchainError::InvalidNonce)));
+            assert!(!chain.verify());
+        }
+
+        #[test]
+        fn test_get_block_data() {
+            let transactions = transactions(5);
+            let mut chain : Blockchain<Transaction> = Blockchain::new();
+            let block = chain.append_data(MerkleTree::new(&transactions));
+            let root_hash = block.get_merkle_tree().get_root_hash();
+
+            // Page through the data two elements at a time
+            let mut fetched = Vec::new();
+            for offset in (0..6).step_by(2) {
+                let page = chain.get_block_data(block.calculate_hash(), offset, 2).unwrap();
+                for (transaction, proof) in page {
+                    assert!(proof.verify(root_hash, &transaction));
+                    fetched.push(transaction.signature[0]);
+                }
+            }
+            assert_eq!(fetched, vec![0, 1, 2, 3, 4]);
+            assert!(chain.get_block_data(block.calculate_hash(), 5, 2).unwrap().is_empty());
+            assert!(chain.get_block_data(ShaHash::new([1; 32]), 0, 2).is_none());
+
+            // Equal elements are told apart by their position
+            let duplicates = vec![TRANSACTION_1; 3];
+            let block = chain.append_data(MerkleTree::new(&duplicates));
+            let mtree = block.get_merkle_tree();
+            let page = chain.get_block_data(block.calculate_hash(), 1, 2).unwrap();
+            assert_eq!(page.len(), 2);
+            for (index, (transaction, proof)) in page.iter().enumerate() {
+                assert!(proof.verify(mtree.get_root_hash(), transaction));
+                assert_eq!(Some(proof), mtree.generate_proof_for_index(index + 1).as_ref());
+            }
+            assert_ne!(page[0].1, page[1].1);
+        }
+
+        #[test]
+        fn test_watchtower() {
+            let transactions = transactions(13);
+            let mine = |prev_hash : ShaHash, i : usize| -> Block<Transaction> {
+                let mut block = Block::new(prev_hash, MerkleTree::new(&[transactions[i]]));
+                block.calculate_nonce();
+                block
+            };
+            let mut watchtower = Watchtower::new(ChainParams::default(), 2);
+            let mut main = vec![mine(INITIAL_HASH, 0)];
+            for i in 1..4 {
+                main.push(mine(main.last().unwrap().calculate_hash(), i));
+            }
+            for block in &main {
+                assert_eq!(watchtower.observe_block(block), vec![]);
+            }
+            assert_eq!(watchtower.best_tip(), main[3].calculate_hash());
+
+            // A shallow fork only conflicts with the Block at the same height
+            let shallow = mine(main[2].calculate_hash(), 10);
+            assert_eq!(watchtower.observe_block(&shallow), vec![Alert::ConflictingBlocks {
+                height: 3, existing: main[3].calculate_hash(), new: shallow.calculate_hash()
+            }]);
+
+            // A fork replacing 2 Blocks is deep
+            let deep = mine(main[1].calculate_hash(), 11);
+            assert_eq!(watchtower.observe_block(&deep), vec![
+                Alert::ConflictingBlocks { height: 2, existing: main[2].calculate_hash(), new: deep.calculate_hash() },
+                Alert::DeepFork { hash: deep.calculate_hash(), depth: 2 }
+            ]);
+
+            // Invalid and orphaned Blocks
+            let unmined = Block::new(main[3].calculate_hash(), MerkleTree::new(&[TRANSACTION_1]));
+            assert!(!unmined.verify_nonce());
+            assert_eq!(watchtower.observe_header(&unmined.header()),
+                vec![Alert::InvalidNonce { hash: unmined.calculate_hash() }]);
+            let orphan = mine(ShaHash::new([7; 32]), 12);
+            assert_eq!(watchtower.observe_block(&orphan), vec![Alert::UnknownParent { hash: orphan.calculate_hash() }]);
+            assert_eq!(watchtower.best_tip(), main[3].calculate_hash());
+
+            // Blocks are checked against the BlockLimits of their height, just like by a Blockchain
+            let limits = BlockLimits { max_leaves: 1, max_bytes: usize::MAX };
+            let mut strict = Watchtower::new(ChainParams { limits, ..ChainParams::default() }, 2);
+            assert_eq!(strict.observe_block(&main[0]), vec![]);
+            let mut big = Block::new(main[0].calculate_hash(), MerkleTree::new(&transactions[4..6]));
+            big.calculate_nonce();
+            assert_eq!(strict.observe_block(&big), vec![Alert::LimitExceeded { hash: big.calculate_hash() }]);
+            strict.schedule_update(1, ParamUpdate::Limits(BlockLimits::UNLIMITED));
+            assert_eq!(strict.params_at(0).limits, limits);
+            assert_eq!(strict.observe_block(&big), vec![]);
+            assert_eq!(strict.best_tip(), big.calculate_hash());
+        }
+
+        #[test]
+        #[cfg(feature = "stress")]
+        fn test_stress() {
+            let config = rust_blockchain::stress::StressConfig::default();
+            let report = rust_blockchain::stress::run(config);
+            assert!(report.blocks >= config.blocks);
+        }
+
+        #[test]
+        fn test_export_csv() {
+            let transactions = transactions(3);
+            let mut chain : Blockchain<Transaction> = Blockchain::new();
+            chain.append_data(MerkleTree::new(&transactions[..1]));
+            chain.append_data(MerkleTree::new(&transactions[1..]));
+            chain.prune(1..2, |mtree| { mtree.forget_leaf(&transactions[2]); });
+
+            let leaf_hash = |transaction : &Transaction| hex::encode(MerkleTree::new(&[*transaction]).get_root_hash());
+            let block_hash = |height : usize| hex::encode(chain.get_block(height).unwrap().calculate_hash());
+            assert_eq!(chain.export_csv(), format!(
+                "height,block_hash,leaf_index,leaf_hash,size\n0,{},0,{},32\n1,{},0,{},32\n1,{},1,{},\n",
+                block_hash(0), leaf_hash(&transactions[0]),
+                block_hash(1), leaf_hash(&transactions[1]),
+                block_hash(1), leaf_hash(&transactions[2])
+            ));
+        }
+
+        #[test]
+        fn test_spv_proof() {
+            let unknown = transactions(10)[9];
+            let transactions = transactions(5);
+            let mut chain : Blockchain<Transaction> = Blockchain::new();
+            let mut block = chain.append_data(MerkleTree::new(&transactions));
+
+            // A light client only knows the header of the Block
+            let header = chain.get_block(0).unwrap().header();
+            for transaction in &transactions {
+                let proof = block.generate_proof(transaction).unwrap();
+                assert!(proof.verify(header.merkle_root(), transaction));
+                assert!(!proof.verify(header.merkle_root(), &Transaction { signature: [9; 32], ..*transaction }));
+            }
+
+            // Proofs need the hashes on the path to the leaf, but not the data of the leaves
+            block.clear_merkle_tree();
+            assert!(block.generate_proof(&transactions[0]).is_none());
+            assert!(block.restore_merkle_tree(MerkleTree::new(&transactions)));
+            let mut mtree = block.get_merkle_tree().clone();
+            mtree.forget_all_leaves();
+            assert!(mtree.generate_proof(&transactions[4]).unwrap().verify(header.merkle_root(), &transactions[4]));
+            assert!(block.generate_proof(&unknown).is_none());
+        }
+
+        #[test]
+        fn test_validity_cache() {
+            use std::sync::atomic::{AtomicUsize, Ordering};
+
+            // A proof of work function counting how often it is used
+            #[derive(Debug)]
+            struct CountingPow(AtomicUsize);
+            impl PowFunction for CountingPow {
+                fn hash(&self, header: &[u8]) -> ShaHash {
+                    self.0.fetch_add(1, Ordering::Relaxed);
+                    Sha256Pow.hash(header)
+                }
+            }
+            static COUNTING_POW : CountingPow = CountingPow(AtomicUsize::new(0));
+            let calls = || COUNTING_POW.0.load(Ordering::Relaxed);
+
+            let mut chain : Blockchain<Transaction> = Blockchain::with_params(ChainParams { pow: &COUNTING_POW, ..ChainParams::default() });
+            for transaction in transactions(3) {
+                chain.append_data(MerkleTree::new(&[transaction]));
+            }
+
+            // Blocks validated when appending them are not validated again
+            let before = calls();
+            assert!(chain.verify());
+            assert_eq!(calls(), before);
+
+            // Pruned Blocks are validated again, but only once
+            chain.prune(1..2, |mtree| mtree.forget_all_leaves());
+            assert!(chain.verify());
+            assert_eq!(calls(), before + 1);
+            assert!(chain.verify());
+            assert_eq!(calls(), before + 1);
+        }
+
+        #[test]
+        fn test_digest_pow() {
+            use sha2::Sha512Trunc256;
+
+            // Mining with another hash function than SHA-256
+            let transactions = transactions(5);
+            static POW : DigestPow<Sha512Trunc256> = DigestPow::new();
+            let params = ChainParams { pow: &POW, ..ChainParams::default() };
+            let mut chain : Blockchain<Transaction> = Blockchain::with_params(params);
+            let block = chain.append_data(MerkleTree::new(&transactions));
+            assert!(block.verify_with(&params));
+            assert!(chain.verify());
+        }
+
+        #[test]
+        fn test_observer() {
+            use std::sync::{Arc, Mutex};
+
+            #[derive(Debug, Default)]
+            struct RecordingObserver {
+                events : Arc<Mutex<Vec<String>>>
+            }
+            impl Observer for RecordingObserver {
+                fn on_block_validated(&self, height : usize, _block_hash : ShaHash) {
+                    self.events.lock().unwrap().push(format!("validated {}", height));
+                }
+                fn on_mining_started(&self, _prev_hash : ShaHash) {
+                    self.events.lock().unwrap().push("mining".to_string());
+                }
+                fn on_prune(&self, report : &PruneReport) {
+                    self.events.lock().unwrap().push(format!("pruned {} leaves", report.total.leaves));
+                }
+            }
+
+            let observer = RecordingObserver::default();
+            let events = observer.events.clone();
+            let mut chain : Blockchain<Transaction> = Blockchain::new();
+            chain.set_observer(Box::new(observer));
+            for transaction in transactions(2) {
+                chain.append_data(MerkleTree::new(&[transaction]));
+            }
+            let stale = Block::new(INITIAL_HASH, MerkleTree::new(&[TRANSACTION_1]));
+            assert!(!chain.append_block(stale));
+            chain.prune(0..2, |mtree| mtree.forget_all_leaves());
+            assert_eq!(*events.lock().unwrap(), vec!["mining", "validated 0", "mining", "validated 1", "pruned 2 leaves"]);
+        }
+
+        #[test]
+        fn test_reindex() {
+            use std::sync::atomic::{AtomicUsize, Ordering};
+
+            // A proof of work function counting how often it is used
+            #[derive(Debug)]
+            struct CountingPow(AtomicUsize);
+            impl PowFunction for CountingPow {
+                fn hash(&self, header: &[u8]) -> ShaHash {
+                    self.0.fetch_add(1, Ordering::Relaxed);
+                    Sha256Pow.hash(header)
+                }
+            }
+            static COUNTING_POW : CountingPow = CountingPow(AtomicUsize::new(0));
+            let calls = || COUNTING_POW.0.load(Ordering::Relaxed);
+
+            let transactions = transactions(3);
+            let hash_of = |transaction : &Transaction| -> ShaHash {
+                MerkleTree::new(&[*transaction]).get_root_hash()
+            };
+            let mut chain : Blockchain<Transaction> = Blockchain::with_params(ChainParams { pow: &COUNTING_POW, ..ChainParams::default() });
+            chain.enable_sequence_numbers();
+            chain.append_data(MerkleTree::new(&transactions[..2]));
+            chain.append_data(MerkleTree::new(&transactions[2..]));
+            chain.prune(0..1, |mtree| mtree.forget_all_leaves());
+
+            // Every Block is validated again, and the sequence numbers stay the same
+            let before = calls();
+            assert_eq!(chain.reindex(), None);
+            assert_eq!(calls() - before, 2);
+            assert_eq!(chain.sequence_of(&hash_of(&transactions[2])), Some(2));
+
+            // Afterwards, the Blocks are known to be valid again
+            let before = calls();
+            assert!(chain.verify());
+            assert_eq!(calls(), before);
+        }
+
+        #[test]
+        fn test_pin() {
+            let transactions = transactions(4);
+            let mut chain : Blockchain<Transaction> = Blockchain::new();
+            chain.append_data(MerkleTree::new(&transactions));
+            let leaf_hash = MerkleTree::new(&transactions[..1]).get_root_hash();
+            let subtree_hash = MerkleTree::new(&transactions[2..]).get_root_hash();
+            assert!(chain.pin(leaf_hash));
+            assert!(!chain.pin(leaf_hash));
+            assert!(chain.pin(subtree_hash));
+            assert!(chain.is_pinned(&subtree_hash));
+
+            // Only the unpinned leaf is reclaimed, no matter how the data is forgotten
+            let report = chain.prune(0..1, |mtree| mtree.shrink_to_minimum());
+            assert_eq!(report.total.leaves, 1);
+            let stored = chain.get_block(0).unwrap().get_merkle_tree().get_currently_stored_data();
+            assert_eq!(stored, vec![transactions[0], transactions[2], transactions[3]]);
+            assert!(chain.verify());
+
+            // Unpinned data can be forgotten again
+            assert!(chain.unpin(subtree_hash));
+            assert!(!chain.unpin(subtree_hash));
+            let report = chain.prune(0..1, |mtree| mtree.forget_all_leaves());
+            assert_eq!(report.total.leaves, 2);
+        }
+
+        #[test]
+        fn test_difficulty_at() {
+            let mut chain : Blockchain<Transaction> = Blockchain::new();
+            assert_eq!(chain.difficulty_at(0), None);
+            let block = chain.append_data(MerkleTree::new(&[TRANSACTION_1]));
+            let difficulty = chain.difficulty_at(0).unwrap();
+            assert!(block.calculate_hash().as_bytes().iter().take(usize::from(difficulty / 8)).all(|&byte| byte == 0));
+            assert!(block.calculate_hash().as_bytes()[usize::from(difficulty / 8)].leading_zeros() >= u32::from(difficulty % 8));
+            assert_eq!(chain.difficulty_at(1), None);
+        }
+
+        #[test]
+        fn test_plan_restore() {
+            use sha2::Sha256;
+            let transactions = transactions(6);
+            let hash_of = |transactions : &[Transaction]| MerkleTree::new(transactions).get_root_hash();
+            let mut chain : Blockchain<Transaction> = Blockchain::new();
+            chain.append_data(MerkleTree::new(&transactions[..4]));
+            chain.append_data(MerkleTree::new(&transactions[4..]));
+            chain.prune(0..1, |mtree| { mtree.forget_subtree(hash_of(&transactions[2..4])); });
+            chain.prune(1..2, |mtree| mtree.forget_all_leaves());
+
+            // (0 1) (2 3) with (2 3) forgotten entirely, and (4 5) with both leaves forgotten
+            let plan = chain.plan_restore(&[
+                hash_of(&transactions[5..]),
+                hash_of(&transactions[..1]),
+                hash_of(&transactions[..4]),
+                hash_of(&transactions[2..3]),
+                hash_of(&transactions[4..])
+            ]);
+            assert_eq!(plan, RestorePlan {
+                fetch: vec![
+                    (0, hash_of(&transactions[2..4])),
+                    (1, hash_of(&transactions[5..])),
+                    (1, hash_of(&transactions[4..5]))
+                ],
+                available: vec![hash_of(&transactions[..1])],
+                unknown: vec![hash_of(&transactions[2..3])]
+            });
+
+            // Putting the fetched data back
+            assert!(!chain.restore_subtree(0, MerkleTree::new_with::<DomainSeparated<Sha256>>(&transactions[2..4])));
+            assert!(chain.restore_subtree(0, MerkleTree::new(&transactions[2..4])));
+            assert!(chain.restore(1, transactions[5]));
+            assert!(!chain.restore(1, transactions[0]));
+            assert!(!chain.restore(2, transactions[4]));
+            assert!(chain.restore(1, transactions[4]));
+            assert!(chain.plan_restore(&[hash_of(&transactions[..4]), hash_of(&transactions[4..])]).fetch.is_empty());
+            assert!(chain.verify());
+
+            // V2 Blockchains hash the restored data with domain separation
+            let mut chain : Blockchain<Transaction> = Blockchain::with_params(ChainParams::regtest());
+            chain.append_data(TreeVersion::V2.new_tree(&transactions));
+            chain.prune(0..1, |mtree| mtree.forget_all_leaves());
+            assert!(chain.restore(0, transactions[3]));
+            assert_eq!(chain.get_block(0).unwrap().get_merkle_tree().get_currently_stored_data(), vec![transactions[3]]);
+            assert!(chain.verify());
+        }
+
+        #[test]
+        fn test_kv_state() {
+            let put = KvOp::put(b"names", b"alice", b"Alice");
+            assert_eq!((put.namespace(), put.key(), put.value()), (&b"names"[..], &b"alice"[..], Some(&b"Alice"[..])));
+            assert_eq!(KvOp::from_bytes(put.as_ref().to_vec()), Some(put.clone()));
+            assert_eq!(KvOp::from_bytes(vec![2, 0, 0, 0, 0]), None);
+            assert_eq!(KvOp::from_bytes(vec![0, 0, 0, 0, 9]), None);
+
+            let mut chain : Blockchain<KvOp> = Blockchain::new();
+            chain.append_data(MerkleTree::new(&[put.clone(), KvOp::put(b"names", b"bob", b"Bob")]));
+            chain.append_data(MerkleTree::new(&[KvOp::delete(b"names", b"bob"), KvOp::put(b"ages", b"alice", b"42")]));
+            let roots = state_roots(&chain).unwrap();
+
+            // The same entries always have the same root, no matter how they came about
+            let mut state = KvState::new();
+            assert_eq!(state.root(), EMPTY_STATE_ROOT);
+            state.apply(&KvOp::put(b"ages", b"alice", b"42"));
+            state.apply(&put);
+            assert_eq!(state.root(), roots[1]);
+            assert_ne!(roots[0], roots[1]);
+            assert_eq!(state.get(b"names", b"alice"), Some(&b"Alice"[..]));
+            assert_eq!(state.get(b"ages", b"bob"), None);
+            state.apply(&KvOp::delete(b"ages", b"alice"));
+            state.apply(&KvOp::delete(b"names", b"alice"));
+            assert!(state.is_empty());
+            assert_eq!(state.root(), EMPTY_STATE_ROOT);
+
+            // The state can't be replayed once operations are forgotten
+            chain.prune(0..1, |mtree| mtree.forget_all_leaves());
+            assert_eq!(state_roots(&chain), None);
+
+            // KvOps round-trip like any other LeafCodec
+            let ops = vec![KvOp::put(b"app", b"key", b"value"), KvOp::delete(b"app", b"key")];
+            let mtree = MerkleTree::new(&ops);
+            let imported : MerkleTree<KvOp> = MerkleTree::from_bytes(&mtree.to_bytes().unwrap()).unwrap();
+            assert_eq!(imported.get_currently_stored_data(), ops);
+        }
+
+        #[test]
+        fn test_block_limits() {
+            let transactions = transactions(4);
+            let limits = BlockLimits { max_leaves: 3, max_bytes: 2 * 32 };
+            let mut chain : Blockchain<Transaction> = Blockchain::with_params(ChainParams { limits, ..ChainParams::default() });
+            chain.append_data(MerkleTree::new(&transactions[..2]));
+            assert_eq!(chain.length(), 1);
+
+            // Too many bytes, then too many leaves
+            let mut block = Block::new(chain.hash_of_last_block(), MerkleTree::new(&transactions[..3]));
+            block.calculate_nonce();
+            assert!(!chain.overlay().append_block(block.clone()));
+            assert!(!chain.append_block(block));
+            let mut mtree = MerkleTree::new(&transactions);
+            mtree.forget_all_leaves();
+            let mut block = Block::new(chain.hash_of_last_block(), mtree);
+            block.calculate_nonce();
+            assert!(!chain.append_block(block));
+            assert_eq!(chain.length(), 1);
+            assert!(chain.verify());
+            assert_eq!(BlockLimits::default(), BlockLimits::UNLIMITED);
+        }
+
+        #[test]
+        fn test_approximate_size_bytes() {
+            let documents : Vec<Vec<u8>> = (0..4).map(|i| vec![i; 1000]).collect();
+            let mut mtree = MerkleTree::new(&documents);
+            let node_size = std::mem::size_of::<MerkleTree<Vec<u8>>>();
+            assert_eq!(mtree.approximate_size_bytes(), 7 * node_size + 4000);
+            assert_eq!(mtree.approximate_size_bytes_with(|_| 1), 7 * node_size + 4);
+
+            // Pruning a Blockchain decreases its size
+            let mut chain : Blockchain<Vec<u8>> = Blockchain::new();
+            chain.append_data(mtree.clone());
+            let before = chain.approximate_size_bytes();
+            assert!(before > mtree.approximate_size_bytes());
+            chain.prune(0..1, |mtree| mtree.forget_all_leaves());
+            assert!(chain.verify()); // validated again, so the validity cache is as big as before
+            assert_eq!(before - chain.approximate_size_bytes(), 4000);
+
+            // The indexes count as well
+            let before = chain.approximate_size_bytes();
+            chain.pin(ShaHash::ZERO);
+            assert_eq!(chain.approximate_size_bytes() - before, std::mem::size_of::<ShaHash>());
+            let before = chain.approximate_size_bytes();
+            chain.append_data(MerkleTree::new(&documents[..1]));
+            assert!(chain.approximate_size_bytes() - before > 1000 + std::mem::size_of::<(ShaHash, usize)>());
+            mtree.shrink_to_minimum();
+            assert_eq!(mtree.approximate_size_bytes(), node_size);
+        }
+
+        #[test]
+        fn test_randomness_beacon() {
+            let mut chain : Blockchain<Transaction> = Blockchain::new();
+            assert_eq!(chain.randomness_at(0, b"lottery"), None);
+            for transaction in transactions(RANDOMNESS_WINDOW as u8 + 2) {
+                chain.append_data(MerkleTree::new(&[transaction]));
+            }
+            let randomness = chain.randomness_at(3, b"lottery").unwrap();
+            assert_ne!(randomness, chain.randomness_at(3, b"raffle").unwrap());
+            assert_ne!(randomness, chain.randomness_at(4, b"lottery").unwrap());
+
+            // Later Blocks don't change the randomness at a height
+            chain.append_data(MerkleTree::new(&[TRANSACTION_1]));
+            assert_eq!(chain.randomness_at(3, b"lottery"), Some(randomness));
+
+            // Commit-reveal
+            let (alice, bob) = (&[1u8; 32][..], &[2u8; 32][..]);
+            let commitments = [commit(alice), commit(bob)];
+            assert!(verify_reveal(&commitments[0], alice));
+            assert!(!verify_reveal(&commitments[0], bob));
+            let combined = combine(&randomness, &[alice, bob]);
+            assert_ne!(combined, combine(&randomness, &[bob, alice]));
+            assert_ne!(combined, randomness);
+        }
+
+        #[test]
+        fn test_domain_separation() {
+            use sha2::Sha256;
+
+            let transactions = transactions(4);
+
+            // Without domain separation, a leaf storing the hashes of two children has the hash of
+            // the node above them
+            let v1 = MerkleTree::new(&transactions[..2]);
+            let (left, right) = match &v1 {
+                MerkleTree::Node { left, right, .. } => (left.get_root_hash(), right.get_root_hash()),
+                MerkleTree::Leaf { .. } => unreachable!()
+            };
+            let fake_leaf = [left.as_ref(), right.as_ref()].concat();
+            assert_eq!(MerkleTree::new(std::slice::from_ref(&fake_leaf)).get_root_hash(), v1.get_root_hash());
+            let v2 = MerkleTree::new_with::<DomainSeparated<Sha256>>(&transactions[..2]);
+            assert!(v2.verify_with::<DomainSeparated<Sha256>>());
+            assert!(!v2.verify());
+            assert_ne!(MerkleTree::new_with::<DomainSeparated<Sha256>>(&[fake_leaf]).get_root_hash(), v2.get_root_hash());
+
+            // A Blockchain only accepts Merkle Trees of its TreeVersion
+            let params = ChainParams { tree_version: TreeVersion::V2, ..ChainParams::default() };
+            let mut chain : Blockchain<Transaction> = Blockchain::with_params(params);
+            let block = chain.append_data(TreeVersion::V2.new_tree(&transactions));
+            assert_eq!(chain.length(), 1);
+            let (element, proof) = chain.get_block_data(block.calculate_hash(), 1, 1).unwrap().remove(0);
+            assert!(TreeVersion::V2.verify_proof(&proof, block.header().merkle_root(), &element));
+            assert!(!TreeVersion::V1.verify_proof(&proof, block.header().merkle_root(), &element));
+            // The Blocks of such a Blockchain have to be checked with its TreeVersion as well
+            assert!(block.verify_with(&params) && block.verify_merkle_tree_with(TreeVersion::V2));
+            assert!(!block.verify() && !block.verify_merkle_tree());
+            let proof = block.generate_proof_with(&transactions[2], TreeVersion::V2).unwrap();
+            assert!(TreeVersion::V2.verify_proof(&proof, block.header().merkle_root(), &transactions[2]));
+            assert!(block.generate_proof(&transactions[2]).is_none());
+            let mut pruned = block.clone();
+            pruned.clear_merkle_tree();
+            assert!(!pruned.restore_merkle_tree(TreeVersion::V2.new_tree(&transactions)));
+            assert!(pruned.restore_merkle_tree_with(TreeVersion::V2.new_tree(&transactions), TreeVersion::V2));
+            assert!(!pruned.clone().restore_merkle_tree_with(TreeVersion::V1.new_tree(&transactions), TreeVersion::V2));
+            assert_eq!(chain.try_append_data(TreeVersion::V1.new_tree(&transactions)).unwrap_err(), BlockchainError::InvalidMerkleTree);
+            assert_eq!(chain.length(), 1);
+            assert!(chain.verify());
+            assert_eq!(TreeVersion::default(), TreeVersion::V1);
+        }
+
+        #[test]
+        fn test_chain_presets() {
+            let regtest = ChainParams::regtest();
+            let testnet = ChainParams::testnet();
+            let production = ChainParams::production(b"My network, started on 2021-03-01");
+            let initial_hashes = [regtest.initial_hash, testnet.initial_hash, production.initial_hash, INITIAL_HASH];
+            for (i, hash) in initial_hashes.iter().enumerate() {
+                assert!(initial_hashes[i + 1..].iter().all(|other| other != hash));
+            }
+            assert_eq!(testnet.limits, BlockLimits::RECOMMENDED);
+            assert_eq!(production.tree_version, TreeVersion::V2);
+            assert_eq!(regtest.limits, BlockLimits::UNLIMITED);
+
+            // Blocks of one preset are not accepted by the others
+            let mut chain : Blockchain<Transaction> = Blockchain::with_params(regtest);
+            let block = chain.append_data(regtest.tree_version.new_tree(&[TRANSACTION_1]));
+            assert!(chain.verify());
+            assert!(!Blockchain::<Transaction>::with_params(testnet).append_block(block));
+        }
+
+        #[test]
+        fn test_sorted_block() {
+            // A SortedMerkleTree can be appended to a Blockchain using TreeVersion::V2
+            let sorted = SortedMerkleTree::new(&transactions(10)).unwrap();
+            let root = sorted.get_root_hash();
+            let mut chain : Blockchain<Transaction> = Blockchain::with_params(ChainParams::regtest());
+            let block = chain.append_data(sorted.into_merkle_tree());
+            assert_eq!(block.header().merkle_root(), root);
+            assert!(chain.verify());
+        }
+
+        #[test]
+        fn test_scrub() {
+            use std::collections::HashMap;
+            use std::sync::{Arc, Mutex};
+
+            #[derive(Debug, Default)]
+            struct ScrubObserver {
+                reports : Arc<Mutex<Vec<ScrubReport>>>
+            }
+            impl Observer for ScrubObserver {
+                fn on_scrub(&self, report : &ScrubReport) {
+                    self.reports.lock().unwrap().push(report.clone());
+                }
+            }
+
+            let transactions = transactions(4);
+            let observer = ScrubObserver::default();
+            let reports = observer.reports.clone();
+            let mut chain : Blockchain<Transaction> = Blockchain::new();
+            chain.set_observer(Box::new(observer));
+            chain.append_data(MerkleTree::new(&transactions[..2]));
+            chain.append_data(MerkleTree::new(&transactions[2..]));
+            let archive : HashMap<ShaHash, Transaction> = transactions.iter()
+                .map(|transaction| (MerkleTree::new(std::slice::from_ref(transaction)).get_root_hash(), *transaction))
+                .collect();
+            assert_eq!(chain.scrub(0..10, &archive), ScrubReport { checked: 2, ..ScrubReport::default() });
+
+            // Corrupt the data of a leaf of each Block (keeping their hashes)
+            let corrupt = |mtree : &mut MerkleTree<Transaction>| {
+                if let MerkleTree::Node{left, ..} = mtree {
+                    if let MerkleTree::Leaf{data: Some(data), ..} = left.as_mut() {
+                        data.signature = [0xff; 32];
+                    }
+                }
+            };
+            chain.prune(0..2, corrupt);
+            assert!(!chain.verify());
+            let leaf_hashes : Vec<ShaHash> = (0..2).map(|height| chain.get_block(height).unwrap().get_merkle_tree().get_leaf_hashes()[0]).collect();
+
+            // Only the first leaf is in the archive
+            let archive : HashMap<ShaHash, Transaction> = vec![(leaf_hashes[0], transactions[0])].into_iter().collect();
+            let report = chain.scrub(0..2, &archive);
+            assert_eq!(report, ScrubReport {
+                checked: 2,
+                repaired: vec![(0, leaf_hashes[0])],
+                forgotten: vec![(1, leaf_hashes[1])],
+                invalid: Vec::new()
+            });
+            assert!(chain.verify());
+            assert_eq!(chain.get_block(0).unwrap().get_merkle_tree().get_currently_stored_data(), transactions[..2].to_vec());
+            assert_eq!(chain.get_block(1).unwrap().get_merkle_tree().get_currently_stored_data(), vec![transactions[3]]);
+            assert_eq!(reports.lock().unwrap().last(), Some(&report));
+
+            let mut mtree = MerkleTree::new(&transactions);
+            assert!(mtree.forget_corrupted().is_empty());
+            assert_eq!(mtree.forget_corrupted_with::<Sha256d>().len(), 4);
+        }
+
+        #[test]
+        fn test_tip() {
+            let mut chain : Blockchain<Transaction> = Blockchain::new();
+            assert_eq!(chain.tip(), TipInfo { length: 0, hash: INITIAL_HASH });
+            let block = chain.append_data(MerkleTree::new(&[TRANSACTION_1]));
+            assert_eq!(chain.tip(), TipInfo { length: 1, hash: block.calculate_hash() });
+        }
+
+        #[cfg(feature = "watch")]
+        #[test]
+        fn test_watch_tip() {
+            let mut chain : Blockchain<Transaction> = Blockchain::new();
+            let mut receiver = chain.watch_tip();
+            assert!(!receiver.has_changed().unwrap());
+            assert_eq!(*receiver.borrow(), chain.tip());
+
+            let block = chain.append_data(MerkleTree::new(&[TRANSACTION_1]));
+            assert!(receiver.has_changed().unwrap());
+            assert_eq!(*receiver.borrow_and_update(), TipInfo { length: 1, hash: block.calculate_hash() });
+            assert!(!receiver.has_changed().unwrap());
+
+            // Rejected Blocks don't change the tip
+            assert!(!chain.append_block(block));
+            assert!(!receiver.has_changed().unwrap());
+        }
+
+        #[test]
+        fn test_governance() {
+            // Updates are authorized by a password, standing in for a signature of the authority
+            #[derive(Debug)]
+            struct Authority;
+            impl Governance<Vec<u8>> for Authority {
+                fn is_authorized(&self, _sequence: u64, _update: &ParamUpdate, authorization: &[u8]) -> bool {
+                    authorization == b"password"
+                }
+
+                fn activation_delay(&self) -> usize {
+                    1
+                }
+            }
+
+            let small = BlockLimits { max_leaves: 2, max_bytes: 1 << 10 };
+            let big = BlockLimits { max_leaves: 4, ..small };
+            let mut chain : Blockchain<Vec<u8>> = Blockchain::with_params(ChainParams { limits: small, ..ChainParams::regtest() });
+            chain.set_governance(Box::new(Authority));
+            let tree = |leaves : Vec<Vec<u8>>| TreeVersion::V2.new_tree(&leaves);
+
+            let update = ParamUpdate::Limits(big);
+            assert_eq!(ParamUpdate::from_leaf(&update.to_leaf(1, b"password")), Some((1, update, &b"password"[..])));
+            assert_eq!(ParamUpdate::from_leaf(b"not governance"), None);
+            let unauthorized = tree(vec![ParamUpdate::Limits(BlockLimits::UNLIMITED).to_leaf(1, b"guess"), vec![1]]);
+            let authorized = tree(vec![update.to_leaf(1, b"password"), vec![2]]);
+            let three_leaves = tree(vec![vec![3], vec![4], vec![5]]);
+            chain.append_data(unauthorized);
+            chain.append_data(authorized);
+            // The update takes effect one Block after the next one
+            assert_eq!(chain.try_append_data(three_leaves.clone()).unwrap_err(), BlockchainError::LimitExceeded);
+            assert_eq!(chain.length(), 2);
+            chain.append_data(tree(vec![vec![6]]));
+            chain.append_data(three_leaves.clone());
+            assert_eq!(chain.length(), 4);
+            assert_eq!(chain.params_at(2).limits, small);
+            assert_eq!(chain.params_at(3).limits, big);
+            assert_eq!(chain.get_params().limits, small);
+
+            // Blocks can't hide a governance leaf by forgetting its data
+            let mut hidden = tree(vec![ParamUpdate::Limits(small).to_leaf(2, b"password"), vec![8]]);
+            assert_eq!(hidden.forget_where(|leaf| ParamUpdate::from_leaf(leaf).is_some()), 1);
+            let mut block = Block::new(chain.hash_of_last_block(), hidden.clone());
+            block.calculate_nonce_with(chain.get_params().pow);
+            assert!(!chain.append_block(block.clone()));
+            assert!(!chain.overlay().append_block(block));
+            assert_eq!(chain.try_append_data(hidden).unwrap_err(), BlockchainError::MissingData);
+            assert_eq!(chain.length(), 4);
+
+            // Overlays validate their Blocks with the same limits as the Blockchain
+            let base : Blockchain<Vec<u8>> = {
+                let mut base = Blockchain::with_params(*chain.get_params());
+                base.set_governance(Box::new(Authority));
+                base.append_block(chain.get_block(0).unwrap().clone());
+                base.append_block(chain.get_block(1).unwrap().clone());
+                base
+            };
+            let mut overlay = base.overlay();
+            assert_eq!(overlay.try_append_data(three_leaves.clone()).unwrap_err(), BlockchainError::LimitExceeded);
+            assert!(overlay.append_block(chain.get_block(2).unwrap().clone()));
+            assert!(overlay.append_block(chain.get_block(3).unwrap().clone()));
+            // ... including the updates confirmed by the Blocks of the Overlay itself
+            let mut overlay = chain.overlay();
+            let bigger = BlockLimits { max_leaves: 5, ..small };
+            overlay.append_data(tree(vec![ParamUpdate::Limits(bigger).to_leaf(2, b"password")]));
+            let five_leaves = tree((10..15).map(|i| vec![i]).collect());
+            assert_eq!(overlay.try_append_data(five_leaves.clone()).unwrap_err(), BlockchainError::LimitExceeded);
+            overlay.append_data(tree(vec![vec![7]]));
+            assert_eq!(overlay.params_at(6).limits, bigger);
+            overlay.append_data(five_leaves);
+
+            // Replacing the Governance validates all Blocks again
+            #[derive(Debug)]
+            struct Nobody;
+            impl Governance<Vec<u8>> for Nobody {
+                fn is_authorized(&self, _sequence: u64, _update: &ParamUpdate, _authorization: &[u8]) -> bool {
+                    false
+                }
+            }
+            let mut replaced = Blockchain::with_params(*chain.get_params());
+            replaced.set_governance(Box::new(Authority));
+            for height in 0..4 {
+                assert!(replaced.append_block(chain.get_block(height).unwrap().clone()));
+            }
+            assert!(replaced.verify());
+            replaced.set_governance(Box::new(Nobody));
+            assert_eq!(replaced.first_invalid(), Some((3, BlockchainError::LimitExceeded)));
+
+            // The updates survive pruning the governance leaves
+            chain.prune(0..4, |mtree| mtree.shrink_to_minimum());
+            assert_eq!(chain.reindex(), None);
+            assert_eq!(chain.params_at(3).limits, big);
+
+            // Replaying an old governance leaf does not revert later updates
+            let smaller = BlockLimits { max_leaves: 3, ..small };
+            chain.append_data(tree(vec![ParamUpdate::Limits(smaller).to_leaf(2, b"password")]));
+            chain.append_data(tree(vec![update.to_leaf(1, b"password")]));
+            chain.append_data(tree(vec![ParamUpdate::Limits(big).to_leaf(2, b"password")]));
+            for _ in 0..2 {
+                chain.append_data(tree(vec![vec![9]]));
+            }
+            assert_eq!(chain.params_at(6).limits, smaller);
+            assert_eq!(chain.params_at(8).limits, smaller);
+        }
+
+        #[test]
+        fn test_bulk_import() {
+            let mut source : Blockchain<Vec<u8>> = Blockchain::new();
+            for i in 0..30u8 {
+                source.append_data(MerkleTree::new(&[vec![i], vec![i, i]]));
+            }
+            let blocks : Vec<Block<Vec<u8>>> = (0..30).map(|height| source.get_block(height).unwrap().clone()).collect();
+            let options = ImportOptions { workers: 4, queue_size: 2 };
+
+            let mut chain : Blockchain<Vec<u8>> = Blockchain::new();
+            let report = chain.bulk_import(blocks.clone(), Some, &options);
+            assert_eq!(report, ImportReport { appended: 30, failed: None });
+            assert_eq!(chain.hash_of_last_block(), source.hash_of_last_block());
+            assert!(chain.verify());
+
+            // Stops at the first item that can't be imported, keeping everything before it
+            let mut items : Vec<Option<Block<Vec<u8>>>> = blocks.iter().cloned().map(Some).collect();
+            items[12] = None;
+            let mut chain : Blockchain<Vec<u8>> = Blockchain::new();
+            assert_eq!(chain.bulk_import(items, |item| item, &options),
+                       ImportReport { appended: 12, failed: Some((12, ImportError::Undecodable)) });
+            assert_eq!(chain.length(), 12);
+            // Continuing with the Block after the missing one
+            assert_eq!(chain.bulk_import(blocks[13..].to_vec(), Some, &options).failed,
+                       Some((0, ImportError::Invalid(BlockchainError::InvalidPrevHash))));
+            let mut unmined = blocks.clone();
+            unmined[20] = Block::new(blocks[19].calculate_hash(), MerkleTree::new(&[vec![0]]));
+            assert!(!unmined[20].verify_nonce());
+            let report = chain.bulk_import(unmined[12..].to_vec(), Some, &ImportOptions::default());
+            assert_eq!(report.failed, Some((8, ImportError::Invalid(BlockchainError::InvalidNonce))));
+            assert_eq!(chain.length(), 20);
+            assert!(chain.verify());
+
+            // A slow item holds back reading the items after it
+            use std::sync::atomic::{AtomicUsize, Ordering};
+            let read = AtomicUsize::new(0);
+            let items = blocks.iter().cloned().enumerate().inspect(|(index, _)| { read.fetch_max(*index, Ordering::SeqCst); });
+            let mut chain : Blockchain<Vec<u8>> = Blockchain::new();
+            let report = chain.bulk_import(items, |(index, block)| {
+                if index == 0 {
+                    std::thread::sleep(std::time::Duration::from_millis(100));
+                    assert!(read.load(Ordering::SeqCst) <= options.queue_size);
+                }
+                Some(block)
+            }, &options);
+            assert_eq!(report, ImportReport { appended: 30, failed: None });
+        }
+
+        #[test]
+        fn test_empty_block() {
+            // Blocks without any data can be appended and verified like any other
+            let mut chain = Blockchain::new();
+            chain.append_data(MerkleTree::new(&[vec![1u8]]));
+            let block = chain.append_data(MerkleTree::empty());
+            assert!(block.get_merkle_tree().is_empty());
+            chain.append_data(MerkleTree::new(&[vec![2u8]]));
+            assert!(chain.verify());
+
+            let mut state = KvState::new();
+            assert!(state.apply_block(&Block::new(ShaHash::ZERO, MerkleTree::new(&[]))));
+            assert_eq!(state.root(), EMPTY_STATE_ROOT);
+        }
+
+        #[test]
+        fn test_replica() {
+            use std::fs::File;
+            let path = std::env::temp_dir().join(format!("rust-blockchain-replica-{}.log", std::process::id()));
+            let mut primary = Blockchain::new();
+            primary.append_data(MerkleTree::new(&[vec![1u8], vec![2]]));
+            primary.append_data(MerkleTree::new(&[vec![3u8]]));
+            let mut shipper = LogShipper::new(File::create(&path).unwrap());
+            assert_eq!(shipper.ship(&primary).unwrap(), 2);
+
+            // The replica tails the log file while the primary keeps appending to it
+            let mut chain: Blockchain<Vec<u8>> = Blockchain::new();
+            chain.enable_sequence_numbers();
+            let mut replica = Replica::with_chain(chain, File::open(&path).unwrap());
+            assert_eq!(replica.poll(), Ok(0..2));
+            assert_eq!(replica.poll(), Ok(2..2));
+            primary.append_data(MerkleTree::new(&[vec![4u8]]));
+            primary.append_data(MerkleTree::new(&[vec![5u8]]));
+            assert_eq!(shipper.ship(&primary).unwrap(), 2);
+            assert_eq!(shipper.ship(&primary).unwrap(), 0);
+            assert_eq!(replica.poll(), Ok(2..4));
+            assert_eq!(replica.chain().hash_of_last_block(), primary.hash_of_last_block());
+            assert!(replica.chain().verify());
+            let leaf_hash = primary.get_block(3).unwrap().get_merkle_tree().get_root_hash();
+            assert_eq!(replica.chain().sequence_of(&leaf_hash), Some(4));
+
+            // A Block that doesn't continue the Blockchain stops the replica
+            let mut wrong = Blockchain::new();
+            wrong.append_data(MerkleTree::new(&[vec![6u8]]));
+            LogShipper::new(File::options().append(true).open(&path).unwrap()).ship(&wrong).unwrap();
+            assert_eq!(replica.poll(), Err(ReplicaError::Invalid(BlockchainError::InvalidPrevHash)));
+            assert_eq!(replica.poll(), Err(ReplicaError::Invalid(BlockchainError::InvalidPrevHash)));
+            assert_eq!(replica.chain().length(), 4);
+
+            let mut garbage = Replica::<Vec<u8>, _>::new(ChainParams::default(), &[0u8, 0, 0, 2, 1, 2][..]);
+            assert_eq!(garbage.poll(), Err(ReplicaError::Undecodable));
+
+            // A length beyond the BlockLimits is rejected without waiting for the record
+            let limits = BlockLimits { max_leaves: 2, max_bytes: 16 };
+            let params = ChainParams { limits, ..ChainParams::default() };
+            let mut small = Blockchain::with_params(params);
+            small.append_data(MerkleTree::new(&[vec![1u8; 8], vec![2u8; 8]]));
+            let mut log = LogShipper::new(Vec::new());
+            log.ship(&small).unwrap();
+            let mut log = log.into_inner();
+            let mut following = Replica::<Vec<u8>, _>::new(params, &log[..]);
+            assert_eq!(following.poll(), Ok(0..1));
+            log.extend_from_slice(&[0xff, 0xff, 0xff, 0xff]);
+            let mut oversized = Replica::<Vec<u8>, _>::new(params, &log[..]);
+            assert_eq!(oversized.poll(), Err(ReplicaError::Invalid(BlockchainError::LimitExceeded)));
+            assert_eq!(oversized.chain().length(), 1);
+            std::fs::remove_file(&path).unwrap();
+        }
+    }
 }